@@ -1,10 +1,11 @@
 #![feature(test)]
+#![allow(clippy::let_and_return)]
 
 extern crate flat_map;
 extern crate rand;
 extern crate test;
 
-use flat_map::FlatMap;
+use flat_map::{BufferedFlatMap, FlatMap};
 use rand::distributions::{IndependentSample, Range};
 use rand::Rng;
 use std::collections::BTreeMap;
@@ -124,3 +125,32 @@ fn bench_btree_map_get(b: &mut Bencher) {
         map.get(&keys[i])
     })
 }
+
+fn random_keys(n: usize) -> Vec<Key> {
+    let mut rng = rand::IsaacRng::new_unseeded();
+    (0..n).map(|_| rng.gen::<Key>()).collect()
+}
+
+#[bench]
+fn bench_flat_map_insert_burst(b: &mut Bencher) {
+    let keys = random_keys(20_000);
+    b.iter(|| {
+        let mut map = FlatMap::new();
+        for &k in &keys {
+            map.insert(k, k);
+        }
+        map
+    })
+}
+
+#[bench]
+fn bench_buffered_flat_map_insert_burst(b: &mut Bencher) {
+    let keys = random_keys(20_000);
+    b.iter(|| {
+        let mut map = BufferedFlatMap::new();
+        for &k in &keys {
+            map.insert(k, k);
+        }
+        map.into_map()
+    })
+}
@@ -0,0 +1,60 @@
+#![feature(test)]
+
+extern crate flat_map;
+extern crate rand;
+extern crate test;
+
+use flat_map::{FlatMap, FrozenFlatMap};
+use rand::distributions::{IndependentSample, Range};
+use rand::Rng;
+use std::collections::BTreeMap;
+use test::Bencher;
+
+type Key = u64;
+type Value = u64;
+
+// Large enough that the entries spill well out of L2.
+const NUM_ITEMS: usize = 1 << 22;
+
+fn key_vals() -> Vec<(Key, Value)> {
+    let mut rng = rand::IsaacRng::new_unseeded();
+    (0..NUM_ITEMS)
+        .map(|i| (i as Key * 2, rng.gen::<Value>()))
+        .collect()
+}
+
+fn bench_get<F: FnMut(&Key) -> Option<Value>>(b: &mut Bencher, mut get: F) {
+    let mut rng = rand::IsaacRng::new_unseeded();
+    let between = Range::new(0, NUM_ITEMS as Key * 2);
+    b.iter(|| get(&between.ind_sample(&mut rng)))
+}
+
+#[bench]
+fn bench_frozen_flat_map_get(b: &mut Bencher) {
+    let map: FrozenFlatMap<_, _> = key_vals().into_iter().collect::<FlatMap<_, _>>().freeze();
+    bench_get(b, |k| map.get(k).cloned())
+}
+
+#[bench]
+fn bench_flat_map_get(b: &mut Bencher) {
+    let map: FlatMap<_, _> = key_vals().into_iter().collect();
+    bench_get(b, |k| map.get(k).cloned())
+}
+
+#[bench]
+fn bench_btree_map_get(b: &mut Bencher) {
+    let map: BTreeMap<_, _> = key_vals().into_iter().collect();
+    bench_get(b, |k| map.get(k).cloned())
+}
+
+#[bench]
+fn bench_frozen_flat_map_iter(b: &mut Bencher) {
+    let map = key_vals().into_iter().collect::<FlatMap<_, _>>().freeze();
+    b.iter(|| map.iter().map(|(_, v)| *v).fold(0, Value::wrapping_add))
+}
+
+#[bench]
+fn bench_flat_map_iter(b: &mut Bencher) {
+    let map: FlatMap<_, _> = key_vals().into_iter().collect();
+    b.iter(|| map.iter().map(|(_, v)| *v).fold(0, Value::wrapping_add))
+}
@@ -0,0 +1,526 @@
+use self::Entry::*;
+use crate::compare::Natural;
+use crate::sorted::{range_indices, search};
+pub use crate::sorted::{Iter, IterMut, Keys, Range, RangeMut, Values, ValuesMut};
+use core::borrow::Borrow;
+use core::fmt;
+use core::fmt::Debug;
+use core::hash::{Hash, Hasher};
+use core::mem::{swap, MaybeUninit};
+use core::ops::{Index, IndexMut, RangeBounds};
+use core::ptr;
+use core::slice;
+
+#[cfg(feature = "alloc")]
+use crate::flat_map::FlatMap;
+#[cfg(feature = "alloc")]
+use core::convert::TryFrom;
+
+/// A map of at most `N` entries stored inline in a sorted array.
+///
+/// It never allocates, so it is available without `alloc`. Once the map is
+/// full, inserting a new key hands the entry back instead of growing.
+///
+/// # Examples
+///
+/// ```
+/// use flat_map::ArrayFlatMap;
+///
+/// let mut map: ArrayFlatMap<u8, &str, 2> = ArrayFlatMap::new();
+/// assert_eq!(map.insert(2, "b"), Ok(None));
+/// assert_eq!(map.insert(1, "a"), Ok(None));
+/// assert_eq!(map.insert(3, "c"), Err((3, "c")));
+/// assert_eq!(map.insert(2, "B"), Ok(Some("b")));
+/// ```
+pub struct ArrayFlatMap<K, V, const N: usize> {
+    len: usize,
+    data: [MaybeUninit<(K, V)>; N],
+}
+
+pub enum Entry<'a, K: 'a, V: 'a, const N: usize> {
+    Vacant(VacantEntry<'a, K, V, N>),
+    Occupied(OccupiedEntry<'a, K, V, N>),
+}
+
+pub struct VacantEntry<'a, K: 'a, V: 'a, const N: usize> {
+    map: &'a mut ArrayFlatMap<K, V, N>,
+    key: K,
+    index: usize,
+}
+
+pub struct OccupiedEntry<'a, K: 'a, V: 'a, const N: usize> {
+    map: &'a mut ArrayFlatMap<K, V, N>,
+    index: usize,
+}
+
+pub struct IntoIter<K, V, const N: usize> {
+    data: [MaybeUninit<(K, V)>; N],
+    start: usize,
+    end: usize,
+}
+
+impl<K, V, const N: usize> ArrayFlatMap<K, V, N> {
+    pub const fn new() -> ArrayFlatMap<K, V, N> {
+        ArrayFlatMap {
+            len: 0,
+            data: [const { MaybeUninit::uninit() }; N],
+        }
+    }
+
+    /// Returns `N`, the most entries the map can hold.
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len == N
+    }
+
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            inner: self.as_slice().iter(),
+        }
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        IterMut {
+            inner: self.as_mut_slice().iter_mut(),
+        }
+    }
+
+    pub fn keys(&self) -> Keys<'_, K, V> {
+        self.iter().into_keys()
+    }
+
+    pub fn values(&self) -> Values<'_, K, V> {
+        self.iter().into_values()
+    }
+
+    pub fn values_mut(&mut self) -> ValuesMut<'_, K, V> {
+        ValuesMut {
+            inner: self.iter_mut(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        let len = self.len;
+        // Forget the entries before dropping them, so a panicking drop
+        // cannot lead to a double drop.
+        self.len = 0;
+        unsafe {
+            ptr::drop_in_place(ptr::slice_from_raw_parts_mut(
+                self.data.as_mut_ptr() as *mut (K, V),
+                len,
+            ));
+        }
+    }
+
+    /// Returns the entry with the smallest key, if any.
+    pub fn first_key_value(&self) -> Option<(&K, &V)> {
+        self.as_slice().first().map(|(k, v)| (k, v))
+    }
+
+    /// Returns the entry with the largest key, if any.
+    pub fn last_key_value(&self) -> Option<(&K, &V)> {
+        self.as_slice().last().map(|(k, v)| (k, v))
+    }
+
+    /// Removes and returns the entry with the largest key, if any.
+    pub fn pop_last(&mut self) -> Option<(K, V)> {
+        if self.len == 0 {
+            None
+        } else {
+            self.len -= 1;
+            Some(unsafe { self.data[self.len].assume_init_read() })
+        }
+    }
+
+    fn as_slice(&self) -> &[(K, V)] {
+        unsafe { slice::from_raw_parts(self.data.as_ptr() as *const (K, V), self.len) }
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [(K, V)] {
+        unsafe { slice::from_raw_parts_mut(self.data.as_mut_ptr() as *mut (K, V), self.len) }
+    }
+
+    // Shifts the entries from `index` up by one and writes `entry` into the
+    // gap. The caller checks that the map is not full.
+    fn insert_at(&mut self, index: usize, entry: (K, V)) {
+        debug_assert!(self.len < N && index <= self.len);
+        unsafe {
+            let p = self.data.as_mut_ptr().add(index);
+            ptr::copy(p, p.add(1), self.len - index);
+            (*p).write(entry);
+        }
+        self.len += 1;
+    }
+
+    fn remove_at(&mut self, index: usize) -> (K, V) {
+        assert!(index < self.len);
+        unsafe {
+            let p = self.data.as_mut_ptr().add(index);
+            let entry = (*p).assume_init_read();
+            ptr::copy(p.add(1), p, self.len - index - 1);
+            self.len -= 1;
+            entry
+        }
+    }
+}
+
+impl<K: Ord, V, const N: usize> ArrayFlatMap<K, V, N> {
+    /// Inserts a key-value pair, returning the previous value for the key.
+    ///
+    /// If the key is new and the map is already full, nothing changes and the
+    /// pair is returned as the error.
+    pub fn insert(&mut self, key: K, mut v: V) -> Result<Option<V>, (K, V)> {
+        match search(self.as_slice(), &Natural, &key) {
+            Ok(i) => {
+                swap(&mut self.as_mut_slice()[i].1, &mut v);
+                Ok(Some(v))
+            }
+            Err(_) if self.is_full() => Err((key, v)),
+            Err(i) => {
+                self.insert_at(i, (key, v));
+                Ok(None)
+            }
+        }
+    }
+
+    pub fn get<Q>(&self, q: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        match search(self.as_slice(), &Natural, q) {
+            Ok(i) => Some(&self.as_slice()[i].1),
+            Err(_) => None,
+        }
+    }
+
+    pub fn get_key_value<Q>(&self, q: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        match search(self.as_slice(), &Natural, q) {
+            Ok(i) => {
+                let (ref k, ref v) = self.as_slice()[i];
+                Some((k, v))
+            }
+            Err(_) => None,
+        }
+    }
+
+    pub fn contains_key<Q>(&self, q: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.get(q).is_some()
+    }
+
+    pub fn get_mut<Q>(&mut self, q: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        match search(self.as_slice(), &Natural, q) {
+            Ok(i) => Some(&mut self.as_mut_slice()[i].1),
+            Err(_) => None,
+        }
+    }
+
+    /// Returns a double-ended iterator over the entries whose keys fall in
+    /// `range`.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as `FlatMap::range`.
+    pub fn range<Q, R>(&self, range: R) -> Range<'_, K, V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        let (start, end) = range_indices(self.as_slice(), &Natural, &range);
+        Range {
+            inner: Iter {
+                inner: self.as_slice()[start..end].iter(),
+            },
+        }
+    }
+
+    pub fn range_mut<Q, R>(&mut self, range: R) -> RangeMut<'_, K, V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        let (start, end) = range_indices(self.as_slice(), &Natural, &range);
+        RangeMut {
+            inner: IterMut {
+                inner: self.as_mut_slice()[start..end].iter_mut(),
+            },
+        }
+    }
+
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, N> {
+        match search(self.as_slice(), &Natural, &key) {
+            Ok(index) => Occupied(OccupiedEntry { map: self, index }),
+            Err(index) => Vacant(VacantEntry {
+                map: self,
+                key,
+                index,
+            }),
+        }
+    }
+
+    pub fn remove<Q>(&mut self, q: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.remove_entry(q).map(|(_, v)| v)
+    }
+
+    pub fn remove_entry<Q>(&mut self, q: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        match search(self.as_slice(), &Natural, q) {
+            Ok(i) => Some(self.remove_at(i)),
+            Err(_) => None,
+        }
+    }
+}
+
+impl<'a, K, V, const N: usize> Entry<'a, K, V, N> {
+    /// Returns the value for the entry's key, inserting `default` first if
+    /// there is none. Fails with the key and `default` if the map is full.
+    pub fn or_insert(self, default: V) -> Result<&'a mut V, (K, V)> {
+        match self {
+            Occupied(entry) => Ok(entry.into_mut()),
+            Vacant(entry) => entry.insert(default),
+        }
+    }
+
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> Result<&'a mut V, (K, V)> {
+        match self {
+            Occupied(entry) => Ok(entry.into_mut()),
+            Vacant(entry) => entry.insert(default()),
+        }
+    }
+}
+
+impl<'a, K, V, const N: usize> VacantEntry<'a, K, V, N> {
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Inserts the entry, or hands it back if the map is full.
+    pub fn insert(self, value: V) -> Result<&'a mut V, (K, V)> {
+        if self.map.is_full() {
+            return Err((self.key, value));
+        }
+        self.map.insert_at(self.index, (self.key, value));
+        Ok(&mut self.map.as_mut_slice()[self.index].1)
+    }
+}
+
+impl<'a, K, V, const N: usize> OccupiedEntry<'a, K, V, N> {
+    pub fn key(&self) -> &K {
+        &self.map.as_slice()[self.index].0
+    }
+
+    pub fn get(&self) -> &V {
+        &self.map.as_slice()[self.index].1
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        &mut self.map.as_mut_slice()[self.index].1
+    }
+
+    pub fn into_mut(self) -> &'a mut V {
+        &mut self.map.as_mut_slice()[self.index].1
+    }
+
+    pub fn insert(&mut self, mut value: V) -> V {
+        swap(self.get_mut(), &mut value);
+        value
+    }
+
+    pub fn remove(self) -> V {
+        self.map.remove_at(self.index).1
+    }
+}
+
+impl<K, V, const N: usize> Drop for ArrayFlatMap<K, V, N> {
+    fn drop(&mut self) {
+        self.clear()
+    }
+}
+
+impl<K, V, const N: usize> Default for ArrayFlatMap<K, V, N> {
+    fn default() -> Self {
+        ArrayFlatMap::new()
+    }
+}
+
+impl<K: Clone, V: Clone, const N: usize> Clone for ArrayFlatMap<K, V, N> {
+    fn clone(&self) -> Self {
+        let mut map = ArrayFlatMap::new();
+        for (i, (k, v)) in self.iter().enumerate() {
+            map.insert_at(i, (k.clone(), v.clone()));
+        }
+        map
+    }
+}
+
+impl<K: Hash, V: Hash, const N: usize> Hash for ArrayFlatMap<K, V, N> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for elt in self {
+            elt.hash(state);
+        }
+    }
+}
+
+impl<K: PartialEq, V: PartialEq, const N: usize> PartialEq for ArrayFlatMap<K, V, N> {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+impl<K: Eq, V: Eq, const N: usize> Eq for ArrayFlatMap<K, V, N> {}
+
+impl<K: Debug, V: Debug, const N: usize> Debug for ArrayFlatMap<K, V, N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K, Q: ?Sized, V, const N: usize> Index<&Q> for ArrayFlatMap<K, V, N>
+where
+    K: Ord + Borrow<Q>,
+    Q: Ord,
+{
+    type Output = V;
+
+    fn index(&self, key: &Q) -> &V {
+        self.get(key).expect("no entry found for key")
+    }
+}
+
+impl<K, Q: ?Sized, V, const N: usize> IndexMut<&Q> for ArrayFlatMap<K, V, N>
+where
+    K: Ord + Borrow<Q>,
+    Q: Ord,
+{
+    fn index_mut(&mut self, key: &Q) -> &mut V {
+        self.get_mut(key).expect("no entry found for key")
+    }
+}
+
+impl<'a, K, V, const N: usize> IntoIterator for &'a ArrayFlatMap<K, V, N> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+impl<'a, K, V, const N: usize> IntoIterator for &'a mut ArrayFlatMap<K, V, N> {
+    type Item = (&'a K, &'a mut V);
+    type IntoIter = IterMut<'a, K, V>;
+
+    fn into_iter(self) -> IterMut<'a, K, V> {
+        self.iter_mut()
+    }
+}
+
+impl<K, V, const N: usize> IntoIterator for ArrayFlatMap<K, V, N> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V, N>;
+
+    fn into_iter(self) -> IntoIter<K, V, N> {
+        let end = self.len;
+        let mut map = self;
+        // The iterator takes ownership of the entries.
+        map.len = 0;
+        IntoIter {
+            data: unsafe { ptr::read(&map.data) },
+            start: 0,
+            end,
+        }
+    }
+}
+
+impl<K, V, const N: usize> Iterator for IntoIter<K, V, N> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<(K, V)> {
+        if self.start == self.end {
+            None
+        } else {
+            self.start += 1;
+            Some(unsafe { self.data[self.start - 1].assume_init_read() })
+        }
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.end - self.start;
+        (len, Some(len))
+    }
+}
+
+impl<K, V, const N: usize> DoubleEndedIterator for IntoIter<K, V, N> {
+    fn next_back(&mut self) -> Option<(K, V)> {
+        if self.start == self.end {
+            None
+        } else {
+            self.end -= 1;
+            Some(unsafe { self.data[self.end].assume_init_read() })
+        }
+    }
+}
+
+impl<K, V, const N: usize> ExactSizeIterator for IntoIter<K, V, N> {}
+
+impl<K, V, const N: usize> Drop for IntoIter<K, V, N> {
+    fn drop(&mut self) {
+        for _ in self {}
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<K, V, const N: usize> From<ArrayFlatMap<K, V, N>> for FlatMap<K, V> {
+    fn from(map: ArrayFlatMap<K, V, N>) -> FlatMap<K, V> {
+        // Safe: the array map is already sorted and free of duplicates.
+        unsafe { FlatMap::from_sorted_vec_unchecked(map.into_iter().collect()) }
+    }
+}
+
+/// Fails, returning the map unchanged, if it has more than `N` entries.
+#[cfg(feature = "alloc")]
+impl<K, V, const N: usize> TryFrom<FlatMap<K, V>> for ArrayFlatMap<K, V, N> {
+    type Error = FlatMap<K, V>;
+
+    fn try_from(map: FlatMap<K, V>) -> Result<Self, FlatMap<K, V>> {
+        if map.len() > N {
+            return Err(map);
+        }
+        let mut array = ArrayFlatMap::new();
+        for (i, entry) in map.into_iter().enumerate() {
+            array.insert_at(i, entry);
+        }
+        Ok(array)
+    }
+}
@@ -0,0 +1,408 @@
+use crate::compare::{Comparator, Natural};
+use crate::flat_map::{FlatMapBy, IntoIter, Iter, IterMut, Keys, Range, Values};
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::cmp::Ordering;
+use core::fmt;
+use core::fmt::Debug;
+use core::iter::{FromIterator, Peekable};
+use core::mem;
+use core::ops::{Index, RangeBounds};
+
+/// A map that defers inserting new keys into its sorted entries, making
+/// long runs of random inserts cheap.
+///
+/// A `FlatMap` shifts its tail on every insert of a new key, so inserting n
+/// keys in random order is O(n²). This map instead appends new keys to a
+/// small unsorted buffer, which is sorted and merged into the sorted entries
+/// in one pass once it outgrows its limit, or before anything that needs the
+/// entries in order. Lookups search the sorted entries and then scan the
+/// buffer, so they see every insert straight away.
+///
+/// By default the buffer may hold up to the square root of the map's length
+/// (and at least 64 entries), which balances the cost of a merge against the
+/// cost of scanning the buffer on lookups.
+///
+/// # Examples
+///
+/// ```
+/// use flat_map::BufferedFlatMap;
+///
+/// let mut map = BufferedFlatMap::new();
+/// for k in [5, 1, 4, 2, 3] {
+///     map.insert(k, k * 10);
+/// }
+/// assert_eq!(map.buffered(), 5);
+/// assert_eq!(map.get(&4), Some(&40));
+/// assert!(map.keys().eq(&[1, 2, 3, 4, 5]));
+/// assert_eq!(map.buffered(), 0);
+/// ```
+#[derive(Clone)]
+pub struct BufferedFlatMap<K, V, C = Natural> {
+    map: FlatMapBy<K, V, C>,
+    // Unsorted entries whose keys are distinct from each other and from
+    // those in `map`.
+    buf: Vec<(K, V)>,
+    limit: Option<usize>,
+}
+
+// The smallest limit the buffer is given by default.
+const MIN_BUFFER_LIMIT: usize = 64;
+
+// The square root of `n`, rounded down, by Newton's method.
+fn isqrt(n: usize) -> usize {
+    if n < 2 {
+        return n;
+    }
+    let mut x = n;
+    let mut y = n / 2 + n % 2;
+    while y < x {
+        x = y;
+        y = (x + n / x) / 2;
+    }
+    x
+}
+
+impl<K, V, C: Default> Default for BufferedFlatMap<K, V, C> {
+    fn default() -> Self {
+        BufferedFlatMap::from(FlatMapBy::with_comparator(C::default()))
+    }
+}
+
+impl<K, V> BufferedFlatMap<K, V> {
+    pub fn new() -> Self {
+        BufferedFlatMap::default()
+    }
+}
+
+impl<K, V, C> BufferedFlatMap<K, V, C> {
+    /// Creates an empty map ordered by `cmp`.
+    pub fn with_comparator(cmp: C) -> Self {
+        BufferedFlatMap::from(FlatMapBy::with_comparator(cmp))
+    }
+
+    /// Returns the comparator that orders the map's keys.
+    pub fn comparator(&self) -> &C {
+        self.map.comparator()
+    }
+
+    /// Returns how many entries the buffer may hold before it is merged.
+    pub fn buffer_limit(&self) -> usize {
+        self.limit
+            .unwrap_or_else(|| isqrt(self.map.len()).max(MIN_BUFFER_LIMIT))
+    }
+
+    /// Fixes how many entries the buffer may hold before it is merged, in
+    /// place of the default that grows with the map. A limit of zero merges
+    /// on every insert of a new key, like a plain `FlatMap`.
+    pub fn set_buffer_limit(&mut self, limit: usize) {
+        self.limit = Some(limit);
+    }
+
+    /// Returns the number of entries waiting in the buffer.
+    pub fn buffered(&self) -> usize {
+        self.buf.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len() + self.buf.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty() && self.buf.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.map.clear();
+        self.buf.clear();
+    }
+
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&K, &V) -> bool,
+    {
+        self.map.retain(&mut f);
+        self.buf.retain(|(k, v)| f(k, v));
+    }
+}
+
+impl<K, V, C: Comparator<K>> BufferedFlatMap<K, V, C> {
+    /// Sorts the buffer and merges it into the sorted entries.
+    pub fn flush(&mut self) {
+        if !self.buf.is_empty() {
+            let buf = mem::take(&mut self.buf);
+            self.map.extend(buf);
+        }
+    }
+
+    /// Flushes the buffer and returns the underlying map.
+    pub fn into_map(mut self) -> FlatMapBy<K, V, C> {
+        self.flush();
+        self.map
+    }
+
+    /// Flushes the buffer and borrows the underlying map, for anything
+    /// not covered here.
+    pub fn as_map(&mut self) -> &FlatMapBy<K, V, C> {
+        self.flush();
+        &self.map
+    }
+
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        if let Some(v) = self.get_mut(&key) {
+            return Some(mem::replace(v, value));
+        }
+        self.buf.push((key, value));
+        if self.buf.len() > self.buffer_limit() {
+            self.flush();
+        }
+        None
+    }
+
+    // Returns the position in the buffer of the entry for `q`.
+    fn position<Q>(&self, q: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        C: Comparator<Q>,
+        Q: ?Sized,
+    {
+        let cmp = self.map.comparator();
+        self.buf
+            .iter()
+            .position(|(k, _)| cmp.compare(k.borrow(), q) == Ordering::Equal)
+    }
+
+    pub fn get<Q>(&self, q: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        C: Comparator<Q>,
+        Q: ?Sized,
+    {
+        self.get_key_value(q).map(|(_, v)| v)
+    }
+
+    pub fn get_key_value<Q>(&self, q: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        C: Comparator<Q>,
+        Q: ?Sized,
+    {
+        self.map.get_key_value(q).or_else(|| {
+            self.position(q).map(|i| {
+                let (ref k, ref v) = self.buf[i];
+                (k, v)
+            })
+        })
+    }
+
+    pub fn contains_key<Q>(&self, q: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        C: Comparator<Q>,
+        Q: ?Sized,
+    {
+        self.get_key_value(q).is_some()
+    }
+
+    pub fn get_mut<Q>(&mut self, q: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        C: Comparator<Q>,
+        Q: ?Sized,
+    {
+        if let Some(i) = self.map.index_of(q) {
+            return self.map.get_index_mut(i).map(|(_, v)| v);
+        }
+        match self.position(q) {
+            Some(i) => Some(&mut self.buf[i].1),
+            None => None,
+        }
+    }
+
+    pub fn remove<Q>(&mut self, q: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        C: Comparator<Q>,
+        Q: ?Sized,
+    {
+        self.remove_entry(q).map(|(_, v)| v)
+    }
+
+    /// Removes `q` from the map. A buffered entry is swapped out of the
+    /// buffer without moving any sorted entries.
+    pub fn remove_entry<Q>(&mut self, q: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        C: Comparator<Q>,
+        Q: ?Sized,
+    {
+        match self.position(q) {
+            Some(i) => Some(self.buf.swap_remove(i)),
+            None => self.map.remove_entry(q),
+        }
+    }
+
+    /// Returns an iterator over the entries in order, flushing the buffer
+    /// first.
+    pub fn iter(&mut self) -> Iter<'_, K, V> {
+        self.flush();
+        self.map.iter()
+    }
+
+    /// Returns an iterator over the entries in order with mutable values,
+    /// flushing the buffer first.
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        self.flush();
+        self.map.iter_mut()
+    }
+
+    pub fn keys(&mut self) -> Keys<'_, K, V> {
+        self.flush();
+        self.map.keys()
+    }
+
+    pub fn values(&mut self) -> Values<'_, K, V> {
+        self.flush();
+        self.map.values()
+    }
+
+    /// Returns the entry with the smallest key, if any.
+    pub fn first_key_value(&mut self) -> Option<(&K, &V)> {
+        self.flush();
+        self.map.first_key_value()
+    }
+
+    /// Returns the entry with the largest key, if any.
+    pub fn last_key_value(&mut self) -> Option<(&K, &V)> {
+        self.flush();
+        self.map.last_key_value()
+    }
+
+    /// Returns a double-ended iterator over the entries whose keys fall in
+    /// `range`, flushing the buffer first.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the start of the range is greater than its end, or if both
+    /// ends are equal and excluded.
+    pub fn range<Q, R>(&mut self, range: R) -> Range<'_, K, V>
+    where
+        K: Borrow<Q>,
+        C: Comparator<Q>,
+        Q: ?Sized,
+        R: RangeBounds<Q>,
+    {
+        self.flush();
+        self.map.range(range)
+    }
+}
+
+impl<K, V, C> From<FlatMapBy<K, V, C>> for BufferedFlatMap<K, V, C> {
+    fn from(map: FlatMapBy<K, V, C>) -> BufferedFlatMap<K, V, C> {
+        BufferedFlatMap {
+            map,
+            buf: Vec::new(),
+            limit: None,
+        }
+    }
+}
+
+impl<K, V, C: Comparator<K>> From<BufferedFlatMap<K, V, C>> for FlatMapBy<K, V, C> {
+    fn from(map: BufferedFlatMap<K, V, C>) -> FlatMapBy<K, V, C> {
+        map.into_map()
+    }
+}
+
+impl<K, V, C: Comparator<K> + Default> FromIterator<(K, V)> for BufferedFlatMap<K, V, C> {
+    /// Builds a map from `iter`. If a key occurs more than once, the last
+    /// pair wins, as with `BTreeMap`.
+    fn from_iter<T: IntoIterator<Item = (K, V)>>(iter: T) -> Self {
+        BufferedFlatMap::from(iter.into_iter().collect::<FlatMapBy<K, V, C>>())
+    }
+}
+
+impl<K, V, C: Comparator<K>> Extend<(K, V)> for BufferedFlatMap<K, V, C> {
+    /// Inserts every pair from `iter`, with later pairs winning on equal
+    /// keys. The buffer is flushed first and the pairs merged in one pass.
+    fn extend<T: IntoIterator<Item = (K, V)>>(&mut self, iter: T) {
+        self.flush();
+        self.map.extend(iter);
+    }
+}
+
+impl<K, V, C: Comparator<K>> IntoIterator for BufferedFlatMap<K, V, C> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+
+    fn into_iter(self) -> IntoIter<K, V> {
+        self.into_map().into_iter()
+    }
+}
+
+impl<K, V, C: Comparator<K>> PartialEq for BufferedFlatMap<K, V, C>
+where
+    K: PartialEq,
+    V: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.sorted().eq(other.sorted())
+    }
+}
+
+impl<K: Eq, V: Eq, C: Comparator<K>> Eq for BufferedFlatMap<K, V, C> {}
+
+impl<K: Debug, V: Debug, C: Comparator<K>> Debug for BufferedFlatMap<K, V, C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.sorted()).finish()
+    }
+}
+
+impl<K, Q: ?Sized, V, C> Index<&Q> for BufferedFlatMap<K, V, C>
+where
+    K: Borrow<Q>,
+    C: Comparator<K> + Comparator<Q>,
+{
+    type Output = V;
+
+    fn index(&self, q: &Q) -> &V {
+        self.get(q).expect("no entry found for key")
+    }
+}
+
+impl<K, V, C: Comparator<K>> BufferedFlatMap<K, V, C> {
+    // Iterates over the entries in order without flushing, by sorting
+    // references to the buffered entries and merging them with the rest.
+    fn sorted(&self) -> Sorted<'_, K, V, C> {
+        let cmp = self.map.comparator();
+        let mut buf: Vec<&(K, V)> = self.buf.iter().collect();
+        buf.sort_unstable_by(|a, b| cmp.compare(&a.0, &b.0));
+        Sorted {
+            map: self.map.iter().peekable(),
+            buf: buf.into_iter().peekable(),
+            cmp,
+        }
+    }
+}
+
+struct Sorted<'a, K: 'a, V: 'a, C: 'a> {
+    map: Peekable<Iter<'a, K, V>>,
+    buf: Peekable<alloc::vec::IntoIter<&'a (K, V)>>,
+    cmp: &'a C,
+}
+
+impl<'a, K, V, C: Comparator<K>> Iterator for Sorted<'a, K, V, C> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        let from_buf = match (self.map.peek(), self.buf.peek()) {
+            (Some(&(a, _)), Some(&b)) => self.cmp.compare(&b.0, a) == Ordering::Less,
+            (None, Some(_)) => true,
+            _ => false,
+        };
+        if from_buf {
+            self.buf.next().map(|e| (&e.0, &e.1))
+        } else {
+            self.map.next()
+        }
+    }
+}
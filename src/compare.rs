@@ -0,0 +1,115 @@
+use crate::primitive::{self, PrimitiveKey};
+use core::cmp::Ordering;
+
+/// A total order on keys of type `K`, used by `FlatMapBy` in place of
+/// `K: Ord`.
+///
+/// Every binary search a map performs goes through its comparator, so the
+/// map's entries are kept sorted by `compare` rather than by `Ord`. Lookups
+/// by a borrowed form `Q` of the key need `Comparator<Q>` as well, and it
+/// must order `Q` the same way it orders `K`.
+///
+/// Closures and functions of type `Fn(&K, &K) -> Ordering` are comparators,
+/// so `f64::total_cmp` can be used directly.
+///
+/// # Examples
+///
+/// ```
+/// use flat_map::FlatMapBy;
+///
+/// let mut map = FlatMapBy::with_comparator(f64::total_cmp);
+/// map.insert(2.5, "b");
+/// map.insert(-1.0, "a");
+/// assert_eq!(map.get(&2.5), Some(&"b"));
+/// assert_eq!(map.keys().next(), Some(&-1.0));
+/// ```
+pub trait Comparator<K: ?Sized> {
+    fn compare(&self, a: &K, b: &K) -> Ordering;
+
+    // Counts the items whose key sorts before `q`, for comparators that can
+    // do so faster than a binary search. `None` means bisect instead.
+    #[doc(hidden)]
+    #[inline(always)]
+    fn count_less<T, F>(&self, _items: &[T], _key: F, _q: &K) -> Option<usize>
+    where
+        Self: Sized,
+        F: Fn(&T) -> &K,
+    {
+        None
+    }
+}
+
+/// Orders keys by their `Ord` impl. This is the comparator behind `FlatMap`.
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Natural;
+
+impl<K: Ord + ?Sized> Comparator<K> for Natural {
+    fn compare(&self, a: &K, b: &K) -> Ordering {
+        a.cmp(b)
+    }
+}
+
+/// Orders primitive integer keys by value, like `Natural`, but lets small
+/// maps be searched with SIMD comparisons.
+///
+/// With the `simd` feature, lookups in maps of up to 64 `u16`, `u32` or
+/// `u64` keys count the smaller keys a vector at a time, on x86_64 and
+/// aarch64 targets that enable SSE2 (SSE4.2 for `u64`) or NEON. Everywhere
+/// else the search is the same branchless binary search `Natural` gets.
+///
+/// # Examples
+///
+/// ```
+/// use flat_map::{FlatMapBy, Primitive};
+///
+/// let mut ports: FlatMapBy<u16, &str, Primitive> = FlatMapBy::new();
+/// ports.insert(443, "https");
+/// ports.insert(22, "ssh");
+/// assert_eq!(ports.get(&22), Some(&"ssh"));
+/// assert!(ports.keys().eq(&[22, 443]));
+/// ```
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Primitive;
+
+impl<K: PrimitiveKey> Comparator<K> for Primitive {
+    #[inline]
+    fn compare(&self, a: &K, b: &K) -> Ordering {
+        a.cmp(b)
+    }
+
+    #[inline(always)]
+    fn count_less<T, F>(&self, items: &[T], key: F, q: &K) -> Option<usize>
+    where
+        F: Fn(&T) -> &K,
+    {
+        primitive::count_less(items, key, *q)
+    }
+}
+
+/// Orders keys the opposite way to the comparator it wraps.
+///
+/// # Examples
+///
+/// ```
+/// use flat_map::{FlatMapBy, Reversed};
+///
+/// let map: FlatMapBy<_, _, Reversed> = vec![(1, 'a'), (3, 'c'), (2, 'b')].into_iter().collect();
+/// assert!(map.keys().cloned().eq(vec![3, 2, 1]));
+/// ```
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Reversed<C = Natural>(pub C);
+
+impl<K: ?Sized, C: Comparator<K>> Comparator<K> for Reversed<C> {
+    fn compare(&self, a: &K, b: &K) -> Ordering {
+        self.0.compare(b, a)
+    }
+}
+
+impl<K: ?Sized, F> Comparator<K> for F
+where
+    F: Fn(&K, &K) -> Ordering,
+{
+    fn compare(&self, a: &K, b: &K) -> Ordering {
+        self(a, b)
+    }
+}
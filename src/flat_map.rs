@@ -1,72 +1,327 @@
 use self::Entry::*;
-use std::borrow::{Borrow, BorrowMut};
-use std::cmp::Ordering;
-use std::fmt;
-use std::fmt::Debug;
-use std::hash::{Hash, Hasher};
-use std::iter::{FromIterator, Map};
-use std::mem::swap;
-use std::ops::{Index, IndexMut};
-use std::slice;
-use std::vec;
-use std::vec::Vec;
-
-#[derive(Clone, Default)]
-pub struct FlatMap<K, V> {
-    v: Vec<(K, V)>,
+use crate::compare::{Comparator, Natural};
+use crate::numeric::{interpolation_search, NumericKey};
+use crate::sorted::{range_indices, search};
+pub use crate::sorted::{Iter, IterMut, Keys, Range, RangeMut, Values, ValuesMut};
+use crate::storage::Storage;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::borrow::{Borrow, BorrowMut};
+use core::cmp::Ordering;
+use core::fmt;
+use core::fmt::Debug;
+use core::hash::{Hash, Hasher};
+use core::iter::FromIterator;
+use core::marker::PhantomData;
+use core::mem::{replace, swap};
+use core::ops::{Bound, Index, IndexMut, RangeBounds};
+use core::ptr;
+
+/// A map stored as a vector of key-value pairs, sorted by the comparator `C`.
+///
+/// Most code uses the `FlatMap` alias, which orders keys by their `Ord` impl.
+/// Any other total order can be supplied as a `Comparator`.
+///
+/// The entries live in `S`, a `Vec` by default. Any `Storage` works, such as
+/// a `Box<[(K, V)]>` for maps that are built once, or with the `smallvec`
+/// feature a `SmallVec` that keeps small maps off the heap.
+pub struct FlatMapBy<K, V, C, S = Vec<(K, V)>> {
+    v: S,
+    cmp: C,
+    marker: PhantomData<(K, V)>,
 }
 
-pub enum Entry<'a, K: 'a, V: 'a> {
-    Vacant(VacantEntry<'a, K, V>),
-    Occupied(OccupiedEntry<'a, K, V>),
+impl<K, V, C: Clone, S: Clone> Clone for FlatMapBy<K, V, C, S> {
+    fn clone(&self) -> Self {
+        FlatMapBy {
+            v: self.v.clone(),
+            cmp: self.cmp.clone(),
+            marker: PhantomData,
+        }
+    }
 }
 
-pub struct VacantEntry<'a, K: 'a, V: 'a> {
-    v: &'a mut Vec<(K, V)>,
+impl<K, V, C: Default, S: Default> Default for FlatMapBy<K, V, C, S> {
+    fn default() -> Self {
+        FlatMapBy {
+            v: S::default(),
+            cmp: C::default(),
+            marker: PhantomData,
+        }
+    }
+}
+
+/// A `FlatMapBy` ordered by the keys' `Ord` impl.
+pub type FlatMap<K, V> = FlatMapBy<K, V, Natural>;
+
+/// A `FlatMap` whose first `N` entries are stored inline.
+#[cfg(feature = "smallvec")]
+pub type SmallFlatMap<K, V, const N: usize> =
+    FlatMapBy<K, V, Natural, ::smallvec::SmallVec<[(K, V); N]>>;
+
+/// A `FlatMap` in a boxed slice with no spare capacity.
+pub type BoxedFlatMap<K, V> = FlatMapBy<K, V, Natural, Box<[(K, V)]>>;
+
+pub enum Entry<'a, K: 'a, V: 'a, S: 'a = Vec<(K, V)>> {
+    Vacant(VacantEntry<'a, K, V, S>),
+    Occupied(OccupiedEntry<'a, K, V, S>),
+}
+
+pub struct VacantEntry<'a, K: 'a, V: 'a, S: 'a = Vec<(K, V)>> {
+    v: &'a mut S,
     key: K,
     index: usize,
+    marker: PhantomData<V>,
+}
+
+pub struct OccupiedEntry<'a, K: 'a, V: 'a, S: 'a = Vec<(K, V)>> {
+    v: &'a mut S,
+    index: usize,
+    // The key passed to `entry`, if the entry came from there.
+    key: Option<K>,
+    marker: PhantomData<(K, V)>,
 }
 
-pub struct OccupiedEntry<'a, K: 'a, V: 'a> {
+pub struct IntoIter<K, V, S: IntoIterator<Item = (K, V)> = Vec<(K, V)>> {
+    inner: S::IntoIter,
+}
+
+/// A draining iterator over the entries of a `FlatMap`, created by `drain`
+/// and `drain_range`.
+pub struct Drain<'a, K: 'a, V: 'a> {
+    inner: alloc::vec::Drain<'a, (K, V)>,
+}
+
+/// An iterator that removes the entries of a `FlatMap` matching a
+/// predicate, created by `extract_if`.
+pub struct ExtractIf<'a, K: 'a, V: 'a, F> {
     v: &'a mut Vec<(K, V)>,
+    pred: F,
+    // The next entry to test.
     index: usize,
+    // Where testing stops.
+    end: usize,
+    // How many of the entries before `index` were removed. The kept ones
+    // sit compacted just before `index - removed`.
+    removed: usize,
+    old_len: usize,
+}
+
+/// The error returned by `FlatMap::try_from_iter` when its input repeats a
+/// key.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct DuplicateKeys<K> {
+    keys: Vec<K>,
+}
+
+/// The error returned by `FlatMap::try_extend` when its input repeats a key
+/// or holds one already in the map. Nothing was inserted, so it gives every
+/// pair back.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ExtendError<K, V> {
+    conflicting: Pairs<K, V>,
+    rest: Pairs<K, V>,
 }
 
-pub struct IntoIter<K, V> {
-    inner: vec::IntoIter<(K, V)>,
+type Pairs<K, V> = Vec<(K, V)>;
+
+/// The error returned by `FlatMap::try_insert` when the key is already in
+/// the map. It holds the rejected key and value, and the existing entry.
+pub struct OccupiedError<'a, K: 'a, V: 'a, S: 'a = Vec<(K, V)>> {
+    entry: OccupiedEntry<'a, K, V, S>,
+    key: K,
+    value: V,
 }
 
-pub struct IterMut<'a, K: 'a, V: 'a> {
-    inner: slice::IterMut<'a, (K, V)>,
+/// The error returned when entries handed to `FlatMap::from_sorted_vec` or
+/// `FlatMapBuilder::push` are not in strictly ascending key order.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct NotSorted {
+    pub(crate) index: usize,
 }
 
-pub struct ValuesMut<'a, K: 'a, V: 'a> {
-    inner: IterMut<'a, K, V>,
+/// Builds a `FlatMap` from entries that arrive in ascending key order,
+/// without sorting.
+///
+/// # Examples
+///
+/// ```
+/// use flat_map::FlatMapBuilder;
+///
+/// let mut builder = FlatMapBuilder::new();
+/// builder.push(1, "a").unwrap();
+/// builder.push(2, "b").unwrap();
+/// assert!(builder.push(2, "c").is_err());
+/// let map = builder.build();
+/// assert_eq!(map.len(), 2);
+/// ```
+#[derive(Clone, Default, Debug)]
+pub struct FlatMapBuilder<K, V, C = Natural> {
+    v: Vec<(K, V)>,
+    cmp: C,
 }
 
-pub struct Iter<'a, K: 'a, V: 'a> {
-    inner: slice::Iter<'a, (K, V)>,
+/// A read-only view of a sorted run of entries borrowed from a `FlatMap`.
+pub struct MapView<'a, K: 'a, V: 'a, C: 'a = Natural> {
+    v: &'a [(K, V)],
+    cmp: &'a C,
 }
 
-pub struct Keys<'a, K: 'a, V: 'a> {
-    inner: Map<Iter<'a, K, V>, fn((&'a K, &'a V)) -> &'a K>,
+/// A read-only cursor over a `FlatMap`.
+///
+/// A cursor points either at an entry or at a "ghost" position past the
+/// last entry and before the first, so moving forwards from the last entry
+/// or backwards from the first lands on the ghost, and moving on from the
+/// ghost wraps around. Cursors are created with `cursor_front`,
+/// `cursor_back`, `lower_bound` and `upper_bound`, and move in O(1).
+pub struct Cursor<'a, K: 'a, V: 'a> {
+    v: &'a [(K, V)],
+    // Equal to `v.len()` at the ghost position.
+    index: usize,
 }
 
-pub struct Values<'a, K: 'a, V: 'a> {
-    inner: Map<Iter<'a, K, V>, fn((&'a K, &'a V)) -> &'a V>,
+/// A cursor over a `FlatMap` that can also modify the map around its
+/// position without searching it again.
+///
+/// Inserting or removing shifts every later entry, so each is O(n) in the
+/// worst case like `insert` and `remove`, but O(1) near the end of the map.
+pub struct CursorMut<'a, K: 'a, V: 'a, C: 'a, S: 'a = Vec<(K, V)>> {
+    v: &'a mut S,
+    cmp: &'a C,
+    // Equal to the map's length at the ghost position.
+    index: usize,
+    marker: PhantomData<(K, V)>,
 }
 
-impl<K, V> FlatMap<K, V> {
-    pub fn new() -> FlatMap<K, V> {
-        FlatMap { v: vec![] }
+impl<K, V, C: Default, S: Storage<(K, V)>> FlatMapBy<K, V, C, S> {
+    pub fn new() -> Self {
+        FlatMapBy::from_parts(S::default(), C::default())
     }
 
-    pub fn with_capacity(capacity: usize) -> FlatMap<K, V> {
-        FlatMap {
-            v: Vec::with_capacity(capacity),
+    pub fn with_capacity(capacity: usize) -> Self {
+        FlatMapBy::from_parts(S::with_capacity(capacity), C::default())
+    }
+
+    /// Wraps a vector without checking its order. This is the inverse of
+    /// `into_inner`.
+    ///
+    /// # Safety
+    ///
+    /// The keys in `v` must be in strictly ascending order under `C`.
+    /// Lookups, inserts and every other ordered operation give unspecified
+    /// results otherwise.
+    pub unsafe fn from_sorted_vec_unchecked(v: Vec<(K, V)>) -> Self {
+        FlatMapBy::from_parts(S::from_vec(v), C::default())
+    }
+}
+
+impl<K, V, C> FlatMapBy<K, V, C> {
+    /// Creates an empty map ordered by `cmp`.
+    ///
+    /// To use other storage, convert the result with `into_storage`.
+    pub fn with_comparator(cmp: C) -> Self {
+        FlatMapBy::from_parts(Vec::new(), cmp)
+    }
+
+    pub fn with_capacity_and_comparator(capacity: usize, cmp: C) -> Self {
+        FlatMapBy::from_parts(Vec::with_capacity(capacity), cmp)
+    }
+
+    /// Removes every entry, returning them in key order as an iterator.
+    ///
+    /// Entries the iterator has not yielded when it is dropped are dropped
+    /// with it, leaving the map empty either way.
+    ///
+    /// Like `drain_range` and `extract_if`, this is only provided for maps
+    /// stored in a `Vec`, since the iterator works on the vector in place.
+    /// Other stores can use `retain_range` or `split_off`, or convert with
+    /// `into_storage` first.
+    pub fn drain(&mut self) -> Drain<'_, K, V> {
+        Drain {
+            inner: self.v.drain(..),
+        }
+    }
+
+    /// Removes the entries whose keys fall in `range`, returning them in key
+    /// order as an iterator.
+    ///
+    /// Both ends are located by binary search and the entries after the
+    /// range are shifted down once, when the iterator is dropped, so this is
+    /// O(n) however many entries are removed. Entries in the range that the
+    /// iterator has not yielded are dropped with it. Only provided for
+    /// `Vec` storage; see `drain`.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as `range`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use flat_map::FlatMap;
+    ///
+    /// let mut map: FlatMap<_, _> = (0..10).map(|i| (i, i * 10)).collect();
+    /// let expired: Vec<_> = map.drain_range(..3).collect();
+    /// assert_eq!(expired, [(0, 0), (1, 10), (2, 20)]);
+    /// assert_eq!(map.first_key_value(), Some((&3, &30)));
+    /// ```
+    pub fn drain_range<Q, R>(&mut self, range: R) -> Drain<'_, K, V>
+    where
+        K: Borrow<Q>,
+        C: Comparator<Q>,
+        Q: ?Sized,
+        R: RangeBounds<Q>,
+    {
+        let (start, end) = range_indices(&self.v, &self.cmp, &range);
+        Drain {
+            inner: self.v.drain(start..end),
+        }
+    }
+
+    /// Returns an iterator that removes and yields the entries for which
+    /// `pred` returns `true`, in key order. `pred` may modify the values of
+    /// the entries it keeps.
+    ///
+    /// Entries are tested and the kept ones moved down as the iterator
+    /// advances, so removing any number of entries takes a single pass. If
+    /// the iterator is dropped early, the untested entries are kept.
+    ///
+    /// Only provided for `Vec` storage; other stores can filter in place
+    /// with `retain_mut`, or convert with `into_storage` first.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use flat_map::FlatMap;
+    ///
+    /// let mut map: FlatMap<_, _> = (0..8).map(|i| (i, i)).collect();
+    /// let odd: FlatMap<_, _> = map.extract_if(|k, _| k % 2 == 1).collect();
+    /// assert!(map.keys().eq(&[0, 2, 4, 6]));
+    /// assert!(odd.keys().eq(&[1, 3, 5, 7]));
+    /// ```
+    pub fn extract_if<F>(&mut self, pred: F) -> ExtractIf<'_, K, V, F>
+    where
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        let len = self.v.len();
+        ExtractIf::new(&mut self.v, 0, len, pred)
+    }
+}
+
+impl<K, V, C, S: Storage<(K, V)>> FlatMapBy<K, V, C, S> {
+    fn from_parts(v: S, cmp: C) -> Self {
+        FlatMapBy {
+            v,
+            cmp,
+            marker: PhantomData,
         }
     }
 
+    /// Returns the comparator that orders the map's keys.
+    pub fn comparator(&self) -> &C {
+        &self.cmp
+    }
+
     /// Returns the number of elements the `VecMap` can hold without
     /// reallocating.
     ///
@@ -94,7 +349,7 @@ impl<K, V> FlatMap<K, V> {
     }
 
     pub fn len(&self) -> usize {
-        self.v.len()
+        self.v.as_slice().len()
     }
 
     /// Return true if the map contains no elements.
@@ -110,416 +365,2185 @@ impl<K, V> FlatMap<K, V> {
     /// assert!(!a.is_empty());
     /// ```
     pub fn is_empty(&self) -> bool {
-        self.v.is_empty()
+        self.v.as_slice().is_empty()
     }
 
-    pub fn iter<'r>(&'r self) -> Iter<'r, K, V> {
+    pub fn iter(&self) -> Iter<'_, K, V> {
         Iter {
-            inner: self.v.iter(),
+            inner: self.v.as_slice().iter(),
         }
     }
 
-    pub fn iter_mut(&mut self) -> IterMut<K, V> {
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
         IterMut {
-            inner: self.v.iter_mut(),
+            inner: self.v.as_mut_slice().iter_mut(),
         }
     }
 
-    pub fn values_mut(&mut self) -> ValuesMut<K, V> {
+    pub fn values_mut(&mut self) -> ValuesMut<'_, K, V> {
         ValuesMut {
             inner: self.iter_mut(),
         }
     }
 
-    pub fn keys<'a>(&'a self) -> Keys<'a, K, V> {
-        fn first<A, B>((a, _): (A, B)) -> A {
-            a
-        }
-        let first: fn((&'a K, &'a V)) -> &'a K = first; // coerce to fn pointer
-        Keys {
-            inner: self.iter().map(first),
-        }
+    pub fn keys(&self) -> Keys<'_, K, V> {
+        self.iter().into_keys()
     }
 
-    pub fn values<'a>(&'a self) -> Values<'a, K, V> {
-        fn second<A, B>((_, b): (A, B)) -> B {
-            b
-        }
-        let second: fn((&'a K, &'a V)) -> &'a V = second; // coerce to fn pointer
-        Values {
-            inner: self.iter().map(second),
-        }
+    pub fn values(&self) -> Values<'_, K, V> {
+        self.iter().into_values()
     }
 
     pub fn clear(&mut self) {
         self.v.clear()
     }
 
-    pub fn into_inner(self) -> Vec<(K, V)> {
+    pub fn into_inner(self) -> S {
         self.v
     }
 
+    /// Moves the entries into a different kind of storage, keeping the
+    /// comparator.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use flat_map::{BoxedFlatMap, FlatMap};
+    ///
+    /// let map: FlatMap<_, _> = vec![(1, 'a'), (2, 'b')].into_iter().collect();
+    /// let frozen: BoxedFlatMap<_, _> = map.into_storage();
+    /// assert_eq!(frozen[&2], 'b');
+    /// ```
+    pub fn into_storage<T: Storage<(K, V)>>(self) -> FlatMapBy<K, V, C, T> {
+        FlatMapBy::from_parts(T::from_vec(self.v.into_vec()), self.cmp)
+    }
+
+    /// Splits the map in two at the given position. Returns the entries from
+    /// index `at` onwards, leaving the first `at` entries in `self`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `at > len`.
+    pub fn split_off_index(&mut self, at: usize) -> Self
+    where
+        C: Clone,
+    {
+        FlatMapBy::from_parts(self.v.split_off(at), self.cmp.clone())
+    }
+
+    /// Borrows the whole map as a read-only view.
+    pub fn as_view(&self) -> MapView<'_, K, V, C> {
+        MapView {
+            v: self.v.as_slice(),
+            cmp: &self.cmp,
+        }
+    }
+
+    pub(crate) fn as_slice(&self) -> &[(K, V)] {
+        self.v.as_slice()
+    }
+
     pub fn retain<F>(&mut self, mut f: F)
-    where F: FnMut(&K, &V) -> bool
+    where
+        F: FnMut(&K, &V) -> bool,
     {
         self.v.retain(|(v, k)| f(v, k))
     }
-}
 
-impl<K: Ord, V> FlatMap<K, V> {
-    pub fn insert(&mut self, key: K, mut v: V) -> Option<V> {
-        match self.v[..].binary_search_by(|&(ref k, _)| k.cmp(&key)) {
-            Err(i) => {
-                self.v.insert(i, (key, v));
-                None
-            }
-            Ok(i) => {
-                let &mut (_, ref mut value) = &mut self.v[i];
-                swap(value, &mut v);
-                Some(v)
-            }
-        }
+    /// Keeps only the entries for which `f` returns `true`, passing each
+    /// value mutably so the kept ones can be updated in the same pass.
+    ///
+    /// If `f` panics, the entries it has not yet kept or rejected are kept,
+    /// and the map stays sorted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use flat_map::FlatMap;
+    ///
+    /// let mut refs: FlatMap<_, _> = vec![("a", 1), ("b", 2), ("c", 1)].into_iter().collect();
+    /// refs.retain_mut(|_, count| {
+    ///     *count -= 1;
+    ///     *count > 0
+    /// });
+    /// assert!(refs.into_iter().eq(vec![("b", 1)]));
+    /// ```
+    pub fn retain_mut<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        self.v.retain(|(k, v)| f(k, v))
+    }
+
+    /// Returns the entry at position `i` in key order, if any, in O(1).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use flat_map::FlatMap;
+    ///
+    /// let map: FlatMap<_, _> = (1..=100).map(|i| (i, i * 10)).collect();
+    /// // The 90th percentile.
+    /// assert_eq!(map.get_index(map.len() * 9 / 10), Some((&91, &910)));
+    /// assert_eq!(map.get_index(100), None);
+    /// ```
+    pub fn get_index(&self, i: usize) -> Option<(&K, &V)> {
+        self.v.as_slice().get(i).map(|(k, v)| (k, v))
+    }
+
+    /// Returns the entry at position `i` in key order with a mutable
+    /// reference to its value, if any.
+    pub fn get_index_mut(&mut self, i: usize) -> Option<(&K, &mut V)> {
+        self.v.as_mut_slice().get_mut(i).map(|(k, v)| (&*k, v))
+    }
+
+    /// Removes and returns the entry at position `i` in key order, if any.
+    ///
+    /// This shifts every later entry down by one, so it is O(n).
+    pub fn remove_index(&mut self, i: usize) -> Option<(K, V)> {
+        if i < self.v.as_slice().len() {
+            Some(self.v.remove(i))
+        } else {
+            None
+        }
+    }
+
+    /// Returns a double-ended iterator over the entries at the positions in
+    /// `range`, such as one page of the map.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is out of bounds or its start is past its end,
+    /// as slicing would.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use flat_map::FlatMap;
+    ///
+    /// let map: FlatMap<_, _> = (0..100).map(|i| (i * 2, i)).collect();
+    /// let page: Vec<_> = map.index_range(20..23).map(|(&k, _)| k).collect();
+    /// assert_eq!(page, [40, 42, 44]);
+    /// ```
+    pub fn index_range<R: RangeBounds<usize>>(&self, range: R) -> Range<'_, K, V> {
+        let bounds = (range.start_bound().cloned(), range.end_bound().cloned());
+        Range {
+            inner: Iter {
+                inner: self.v.as_slice()[bounds].iter(),
+            },
+        }
+    }
+
+    /// Returns a double-ended iterator over the entries at the positions in
+    /// `range`, with mutable references to the values.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as `index_range`.
+    pub fn index_range_mut<R: RangeBounds<usize>>(&mut self, range: R) -> RangeMut<'_, K, V> {
+        let bounds = (range.start_bound().cloned(), range.end_bound().cloned());
+        RangeMut {
+            inner: IterMut {
+                inner: self.v.as_mut_slice()[bounds].iter_mut(),
+            },
+        }
+    }
+
+    /// Returns a cursor pointing at the first entry, or at the ghost
+    /// position if the map is empty.
+    pub fn cursor_front(&self) -> Cursor<'_, K, V> {
+        Cursor {
+            v: self.v.as_slice(),
+            index: 0,
+        }
+    }
+
+    /// Returns a cursor pointing at the last entry, or at the ghost
+    /// position if the map is empty.
+    pub fn cursor_back(&self) -> Cursor<'_, K, V> {
+        let len = self.v.as_slice().len();
+        Cursor {
+            v: self.v.as_slice(),
+            index: len.checked_sub(1).unwrap_or(len),
+        }
+    }
+
+    /// Returns a mutable cursor pointing at the first entry, or at the
+    /// ghost position if the map is empty.
+    pub fn cursor_front_mut(&mut self) -> CursorMut<'_, K, V, C, S> {
+        self.cursor_mut_at(0)
+    }
+
+    /// Returns a mutable cursor pointing at the last entry, or at the ghost
+    /// position if the map is empty.
+    pub fn cursor_back_mut(&mut self) -> CursorMut<'_, K, V, C, S> {
+        let len = self.v.as_slice().len();
+        self.cursor_mut_at(len.checked_sub(1).unwrap_or(len))
+    }
+
+    fn cursor_mut_at(&mut self, index: usize) -> CursorMut<'_, K, V, C, S> {
+        CursorMut {
+            v: &mut self.v,
+            cmp: &self.cmp,
+            index,
+            marker: PhantomData,
+        }
+    }
+
+    /// Returns the entry with the smallest key, if any.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use flat_map::FlatMap;
+    ///
+    /// let mut map = FlatMap::new();
+    /// assert_eq!(map.first_key_value(), None);
+    /// map.insert(2, "b");
+    /// map.insert(1, "a");
+    /// assert_eq!(map.first_key_value(), Some((&1, &"a")));
+    /// ```
+    pub fn first_key_value(&self) -> Option<(&K, &V)> {
+        self.v.as_slice().first().map(|(k, v)| (k, v))
+    }
+
+    /// Returns the entry with the largest key, if any.
+    pub fn last_key_value(&self) -> Option<(&K, &V)> {
+        self.v.as_slice().last().map(|(k, v)| (k, v))
+    }
+
+    /// Removes and returns the entry with the smallest key, if any.
+    ///
+    /// This shifts every remaining entry down by one, so it is O(n).
+    pub fn pop_first(&mut self) -> Option<(K, V)> {
+        if self.v.as_slice().is_empty() {
+            None
+        } else {
+            Some(self.v.remove(0))
+        }
+    }
+
+    /// Removes and returns the entry with the largest key, if any, in O(1).
+    pub fn pop_last(&mut self) -> Option<(K, V)> {
+        self.v.pop()
+    }
+
+    /// Returns the entry with the smallest key for in-place manipulation.
+    pub fn first_entry(&mut self) -> Option<OccupiedEntry<'_, K, V, S>> {
+        if self.v.as_slice().is_empty() {
+            None
+        } else {
+            Some(OccupiedEntry {
+                v: &mut self.v,
+                index: 0,
+                key: None,
+                marker: PhantomData,
+            })
+        }
+    }
+
+    /// Returns the entry with the largest key for in-place manipulation.
+    pub fn last_entry(&mut self) -> Option<OccupiedEntry<'_, K, V, S>> {
+        match self.v.as_slice().len() {
+            0 => None,
+            len => Some(OccupiedEntry {
+                v: &mut self.v,
+                index: len - 1,
+                key: None,
+                marker: PhantomData,
+            }),
+        }
+    }
+}
+
+impl<K, V, C: Comparator<K>, S: Storage<(K, V)>> FlatMapBy<K, V, C, S> {
+    /// Wraps a vector whose keys are already in strictly ascending order,
+    /// which is checked in O(n). This is the inverse of `into_inner`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use flat_map::FlatMap;
+    ///
+    /// let map = FlatMap::from_sorted_vec(vec![(1, 'a'), (2, 'b')]).unwrap();
+    /// assert_eq!(map[&2], 'b');
+    /// let err = FlatMap::from_sorted_vec(vec![(1, 'a'), (1, 'b')]).unwrap_err();
+    /// assert_eq!(err.index(), 1);
+    /// ```
+    pub fn from_sorted_vec(v: Vec<(K, V)>) -> Result<Self, NotSorted>
+    where
+        C: Default,
+    {
+        FlatMapBy::from_sorted_vec_with_comparator(v, C::default())
+    }
+
+    /// Like `from_sorted_vec`, checking the order under `cmp` and keeping it
+    /// as the map's comparator.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use flat_map::FlatMapBy;
+    ///
+    /// let by_len = |a: &&str, b: &&str| a.len().cmp(&b.len());
+    /// let v = vec![("a", 1), ("bb", 2)];
+    /// let map: FlatMapBy<_, _, _> = FlatMapBy::from_sorted_vec_with_comparator(v, by_len).unwrap();
+    /// assert_eq!(map.get(&"xx"), Some(&2));
+    /// ```
+    pub fn from_sorted_vec_with_comparator(v: Vec<(K, V)>, cmp: C) -> Result<Self, NotSorted> {
+        match v
+            .windows(2)
+            .position(|w| cmp.compare(&w[0].0, &w[1].0) != Ordering::Less)
+        {
+            Some(i) => Err(NotSorted { index: i + 1 }),
+            None => Ok(FlatMapBy::from_parts(S::from_vec(v), cmp)),
+        }
+    }
+
+    /// Builds a map ordered by `cmp` from `iter`. This is `FromIterator`
+    /// for comparators without a `Default`: if a key occurs more than once,
+    /// the last pair wins.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use flat_map::FlatMapBy;
+    ///
+    /// let caseless = |a: &&str, b: &&str| a.to_lowercase().cmp(&b.to_lowercase());
+    /// let pairs = vec![("b", 2), ("A", 1), ("B", 3)];
+    /// let map: FlatMapBy<_, _, _> = FlatMapBy::from_iter_with_comparator(pairs, caseless);
+    /// assert!(map.into_iter().eq(vec![("A", 1), ("B", 3)]));
+    /// ```
+    pub fn from_iter_with_comparator<I>(iter: I, cmp: C) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let mut vec: Vec<_> = iter.into_iter().collect();
+        sort_dedup_last(&mut vec, |a, b| cmp.compare(a, b));
+        FlatMapBy::from_parts(S::from_vec(vec), cmp)
+    }
+
+    /// Builds a map from `iter`, combining the values of duplicate keys with
+    /// `merge(key, old, new)`, where `old` is the value accumulated so far
+    /// and `new` is the next one in iteration order. The first occurrence of
+    /// each key is the one kept.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use flat_map::FlatMap;
+    ///
+    /// let words = vec![("a", 1), ("b", 1), ("a", 1)];
+    /// let counts = FlatMap::from_iter_merge(words, |_, old, new| old + new);
+    /// assert_eq!(counts[&"a"], 2);
+    /// assert_eq!(counts[&"b"], 1);
+    /// ```
+    pub fn from_iter_merge<I, F>(iter: I, merge: F) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        F: FnMut(&K, V, V) -> V,
+        C: Default,
+    {
+        FlatMapBy::from_iter_merge_with_comparator(iter, merge, C::default())
+    }
+
+    /// Like `from_iter_merge`, for a map ordered by `cmp`.
+    pub fn from_iter_merge_with_comparator<I, F>(iter: I, mut merge: F, cmp: C) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        F: FnMut(&K, V, V) -> V,
+    {
+        let mut vec: Vec<_> = iter.into_iter().collect();
+        vec.sort_by(|kv1, kv2| cmp.compare(&kv1.0, &kv2.0));
+        let mut v = Vec::with_capacity(vec.len());
+        let mut iter = vec.into_iter();
+        if let Some((mut key, mut acc)) = iter.next() {
+            for (next_key, value) in iter {
+                if cmp.compare(&next_key, &key) == Ordering::Equal {
+                    acc = merge(&key, acc, value);
+                } else {
+                    v.push((replace(&mut key, next_key), replace(&mut acc, value)));
+                }
+            }
+            v.push((key, acc));
+        }
+        FlatMapBy::from_parts(S::from_vec(v), cmp)
+    }
+
+    /// Builds a map from `iter`, failing if any key occurs more than once.
+    ///
+    /// The error lists every duplicated key once, in ascending order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use flat_map::FlatMap;
+    ///
+    /// let err = FlatMap::try_from_iter(vec![(2, 'a'), (1, 'b'), (2, 'c')]).unwrap_err();
+    /// assert_eq!(err.keys(), &[2]);
+    /// ```
+    pub fn try_from_iter<I>(iter: I) -> Result<Self, DuplicateKeys<K>>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        C: Default,
+    {
+        FlatMapBy::try_from_iter_with_comparator(iter, C::default())
+    }
+
+    /// Like `try_from_iter`, for a map ordered by `cmp`.
+    pub fn try_from_iter_with_comparator<I>(iter: I, cmp: C) -> Result<Self, DuplicateKeys<K>>
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let mut vec: Vec<_> = iter.into_iter().collect();
+        vec.sort_by(|kv1, kv2| cmp.compare(&kv1.0, &kv2.0));
+        if vec
+            .windows(2)
+            .all(|w| cmp.compare(&w[0].0, &w[1].0) != Ordering::Equal)
+        {
+            return Ok(FlatMapBy::from_parts(S::from_vec(vec), cmp));
+        }
+        let mut keys: Vec<K> = Vec::new();
+        let mut iter = vec.into_iter().peekable();
+        while let Some((key, _)) = iter.next() {
+            let mut duplicated = false;
+            while iter
+                .next_if(|next| cmp.compare(&next.0, &key) == Ordering::Equal)
+                .is_some()
+            {
+                duplicated = true;
+            }
+            if duplicated {
+                keys.push(key);
+            }
+        }
+        Err(DuplicateKeys { keys })
+    }
+
+    pub fn insert(&mut self, key: K, mut v: V) -> Option<V> {
+        match self.search(&key) {
+            Err(i) => {
+                self.v.insert(i, (key, v));
+                None
+            }
+            Ok(i) => {
+                let &mut (_, ref mut value) = &mut self.v.as_mut_slice()[i];
+                swap(value, &mut v);
+                Some(v)
+            }
+        }
+    }
+
+    /// Inserts `key` and `value` only if `key` is not in the map yet, and
+    /// returns a mutable reference to the inserted value.
+    ///
+    /// If the key is present, nothing is changed and the error holds the
+    /// existing entry along with the rejected key and value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use flat_map::FlatMap;
+    ///
+    /// let mut map = FlatMap::new();
+    /// assert_eq!(map.try_insert(1, "a").unwrap(), &"a");
+    /// let err = map.try_insert(1, "b").unwrap_err();
+    /// assert_eq!(err.entry().get(), &"a");
+    /// assert_eq!(err.into_key_value(), (1, "b"));
+    /// ```
+    pub fn try_insert(&mut self, key: K, value: V) -> Result<&mut V, OccupiedError<'_, K, V, S>> {
+        match self.search(&key) {
+            Err(i) => {
+                let entry = VacantEntry {
+                    v: &mut self.v,
+                    key,
+                    index: i,
+                    marker: PhantomData,
+                };
+                Ok(entry.insert(value))
+            }
+            Ok(i) => Err(OccupiedError {
+                entry: OccupiedEntry {
+                    v: &mut self.v,
+                    index: i,
+                    key: None,
+                    marker: PhantomData,
+                },
+                key,
+                value,
+            }),
+        }
+    }
+
+    /// Inserts `key` and `value` only if `key` is not in the map yet.
+    /// Returns whether they were inserted.
+    pub fn insert_new(&mut self, key: K, value: V) -> bool {
+        self.try_insert(key, value).is_ok()
+    }
+
+    /// Moves all elements from `other` into `self`, leaving `other` empty.
+    ///
+    /// If a key is present in both maps, the value from `other` wins. Both
+    /// maps are assumed to be ordered the same way; `other`'s comparator is
+    /// not consulted.
+    ///
+    /// The two sorted vectors are merged in place from the back in a single
+    /// O(n + m) pass. When every key in `other` sorts after every key in
+    /// `self` (or before), the vectors are simply concatenated.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use flat_map::FlatMap;
+    ///
+    /// let mut a: FlatMap<_, _> = vec![(1, "a"), (3, "c")].into_iter().collect();
+    /// let mut b: FlatMap<_, _> = vec![(2, "b"), (3, "C")].into_iter().collect();
+    /// a.append(&mut b);
+    /// assert!(b.is_empty());
+    /// assert!(a.into_iter().eq(vec![(1, "a"), (2, "b"), (3, "C")]));
+    /// ```
+    pub fn append(&mut self, other: &mut Self) {
+        let FlatMapBy { v, cmp, .. } = self;
+        other.v.with_vec(|src| {
+            v.with_vec(|dst| merge_sorted(dst, src, OnEqual::Replace, |a, b| cmp.compare(a, b)))
+        });
+    }
+
+    /// Inserts every pair from `iter` if none of their keys is in the map
+    /// or repeated in `iter`. Otherwise nothing is inserted, and the error
+    /// hands every pair back, with those whose keys conflict set apart.
+    ///
+    /// The pairs are sorted, each key is looked up with one binary search,
+    /// and then the pairs are merged into the map in one pass.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use flat_map::FlatMap;
+    ///
+    /// let mut map: FlatMap<_, _> = vec![(1, 'a')].into_iter().collect();
+    /// map.try_extend(vec![(3, 'c'), (2, 'b')]).unwrap();
+    /// let err = map.try_extend(vec![(4, 'd'), (2, 'x'), (5, 'e'), (5, 'f')]).unwrap_err();
+    /// assert_eq!(err.conflicting(), &[(2, 'x'), (5, 'e'), (5, 'f')]);
+    /// assert!(map.keys().eq(&[1, 2, 3]));
+    ///
+    /// let (_, rest) = err.into_parts();
+    /// map.try_extend(rest).unwrap();
+    /// assert!(map.keys().eq(&[1, 2, 3, 4]));
+    /// ```
+    pub fn try_extend<I>(&mut self, iter: I) -> Result<(), ExtendError<K, V>>
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let mut vec: Vec<_> = iter.into_iter().collect();
+        vec.sort_by(|kv1, kv2| self.cmp.compare(&kv1.0, &kv2.0));
+        let equal = |i: usize, j: usize| self.cmp.compare(&vec[i].0, &vec[j].0) == Ordering::Equal;
+        let conflicts: Vec<bool> = (0..vec.len())
+            .map(|i| {
+                (i > 0 && equal(i - 1, i))
+                    || (i + 1 < vec.len() && equal(i, i + 1))
+                    || self.search(&vec[i].0).is_ok()
+            })
+            .collect();
+        if !conflicts.contains(&true) {
+            let FlatMapBy { v, cmp, .. } = self;
+            v.with_vec(|dst| {
+                merge_sorted(dst, &mut vec, OnEqual::Replace, |a, b| cmp.compare(a, b))
+            });
+            return Ok(());
+        }
+        let mut conflicting = Vec::new();
+        let mut rest = Vec::new();
+        for (pair, conflict) in vec.into_iter().zip(conflicts) {
+            if conflict {
+                conflicting.push(pair);
+            } else {
+                rest.push(pair);
+            }
+        }
+        Err(ExtendError { conflicting, rest })
+    }
+
+    /// Splits the map in two at the given key. Returns everything at or
+    /// after `key`, leaving everything before it in `self`.
+    ///
+    /// `key` does not need to be present in the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use flat_map::FlatMap;
+    ///
+    /// let mut a: FlatMap<_, _> = vec![(1, "a"), (3, "c"), (5, "e")].into_iter().collect();
+    /// let b = a.split_off(&2);
+    /// assert!(a.into_iter().eq(vec![(1, "a")]));
+    /// assert!(b.into_iter().eq(vec![(3, "c"), (5, "e")]));
+    /// ```
+    pub fn split_off<Q>(&mut self, q: &Q) -> Self
+    where
+        K: Borrow<Q>,
+        C: Comparator<Q> + Clone,
+        Q: ?Sized,
+    {
+        let at = self.search(q).unwrap_or_else(|i| i);
+        self.split_off_index(at)
+    }
+
+    /// Borrows the map as two read-only views split at the given key. The
+    /// first holds everything before `key`, the second everything at or after
+    /// it.
+    pub fn split_at<Q>(&self, q: &Q) -> (MapView<'_, K, V, C>, MapView<'_, K, V, C>)
+    where
+        K: Borrow<Q>,
+        C: Comparator<Q>,
+        Q: ?Sized,
+    {
+        let (left, right) = self
+            .v
+            .as_slice()
+            .split_at(self.search(q).unwrap_or_else(|i| i));
+        let cmp = &self.cmp;
+        (MapView { v: left, cmp }, MapView { v: right, cmp })
+    }
+
+    pub fn get<Q>(&self, q: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        C: Comparator<Q>,
+        Q: ?Sized,
+    {
+        match self.search(q) {
+            Err(_) => None,
+            Ok(idx) => {
+                let (_, ref v) = self.v.as_slice()[idx];
+                Some(v)
+            }
+        }
+    }
+
+    /// Returns the key-value pair corresponding to the supplied key.
+    pub fn get_key_value<Q>(&self, q: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        C: Comparator<Q>,
+        Q: ?Sized,
+    {
+        match self.search(q) {
+            Err(_) => None,
+            Ok(idx) => {
+                let (ref k, ref v) = self.v.as_slice()[idx];
+                Some((k, v))
+            }
+        }
+    }
+
+    pub fn contains_key<Q>(&self, k: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        C: Comparator<Q>,
+        Q: ?Sized,
+    {
+        self.get(k).is_some()
+    }
+
+    /// Return Option<&mut V>.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use flat_map::FlatMap;
+    ///
+    /// let mut m = FlatMap::new();
+    /// m.insert(1, "foo".to_string());
+    /// m.get_mut(&1).unwrap().push_str("bar");
+    /// assert_eq!("foobar", m.get_mut(&1).unwrap());
+    /// ```
+    pub fn get_mut<Q>(&mut self, q: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        C: Comparator<Q>,
+        Q: ?Sized,
+    {
+        match self.search(q) {
+            Err(_) => None,
+            Ok(idx) => match self.v.as_mut_slice().get_mut(idx) {
+                Some(&mut (_, ref mut v)) => Some(v),
+                _ => None,
+            },
+        }
+    }
+
+    /// Like `retain_mut`, but only visits the entries whose keys fall in
+    /// `range`, which is located by binary search. The entries after the
+    /// range are shifted down once.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as `range`. If `f` panics, the
+    /// entries it has not yet kept or rejected are kept, and the map stays
+    /// sorted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use flat_map::FlatMap;
+    ///
+    /// let mut map: FlatMap<_, _> = (0..10).map(|i| (i, i)).collect();
+    /// map.retain_range(3..7, |&k, _| k % 2 == 0);
+    /// assert!(map.keys().eq(&[0, 1, 2, 4, 6, 7, 8, 9]));
+    /// ```
+    pub fn retain_range<Q, R, F>(&mut self, range: R, mut f: F)
+    where
+        K: Borrow<Q>,
+        C: Comparator<Q>,
+        Q: ?Sized,
+        R: RangeBounds<Q>,
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        let (start, end) = range_indices(self.v.as_slice(), &self.cmp, &range);
+        if start < end {
+            self.v.with_vec(|v| {
+                ExtractIf::new(v, start, end, |k: &K, v: &mut V| !f(k, v)).for_each(drop)
+            });
+        }
+    }
+
+    /// Returns a cursor pointing at the first entry above `bound`, or at the
+    /// ghost position if there is none.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use flat_map::FlatMap;
+    /// use std::ops::Bound::{Excluded, Included};
+    ///
+    /// let map: FlatMap<_, _> = vec![(1, 'a'), (2, 'b'), (3, 'c')].into_iter().collect();
+    /// let cursor = map.lower_bound(Included(&2));
+    /// assert_eq!(cursor.key(), Some(&2));
+    /// assert_eq!(cursor.peek_prev(), Some((&1, &'a')));
+    /// let cursor = map.lower_bound(Excluded(&3));
+    /// assert_eq!(cursor.key(), None);
+    /// assert_eq!(cursor.peek_next(), Some((&1, &'a')));
+    /// ```
+    pub fn lower_bound<Q>(&self, bound: Bound<&Q>) -> Cursor<'_, K, V>
+    where
+        K: Borrow<Q>,
+        C: Comparator<Q>,
+        Q: ?Sized,
+    {
+        Cursor {
+            v: self.v.as_slice(),
+            index: self.lower_bound_index(bound),
+        }
+    }
+
+    /// Returns a mutable cursor pointing at the first entry above `bound`,
+    /// or at the ghost position if there is none.
+    pub fn lower_bound_mut<Q>(&mut self, bound: Bound<&Q>) -> CursorMut<'_, K, V, C, S>
+    where
+        K: Borrow<Q>,
+        C: Comparator<Q>,
+        Q: ?Sized,
+    {
+        let index = self.lower_bound_index(bound);
+        self.cursor_mut_at(index)
+    }
+
+    /// Returns a cursor pointing at the last entry below `bound`, or at the
+    /// ghost position if there is none.
+    pub fn upper_bound<Q>(&self, bound: Bound<&Q>) -> Cursor<'_, K, V>
+    where
+        K: Borrow<Q>,
+        C: Comparator<Q>,
+        Q: ?Sized,
+    {
+        Cursor {
+            v: self.v.as_slice(),
+            index: self.upper_bound_index(bound),
+        }
+    }
+
+    /// Returns a mutable cursor pointing at the last entry below `bound`, or
+    /// at the ghost position if there is none.
+    pub fn upper_bound_mut<Q>(&mut self, bound: Bound<&Q>) -> CursorMut<'_, K, V, C, S>
+    where
+        K: Borrow<Q>,
+        C: Comparator<Q>,
+        Q: ?Sized,
+    {
+        let index = self.upper_bound_index(bound);
+        self.cursor_mut_at(index)
+    }
+
+    // The position of the first entry above `bound`, or the length.
+    fn lower_bound_index<Q>(&self, bound: Bound<&Q>) -> usize
+    where
+        K: Borrow<Q>,
+        C: Comparator<Q>,
+        Q: ?Sized,
+    {
+        match bound {
+            Bound::Included(q) => self.search(q).unwrap_or_else(|i| i),
+            Bound::Excluded(q) => self.search(q).map(|i| i + 1).unwrap_or_else(|i| i),
+            Bound::Unbounded => 0,
+        }
+    }
+
+    // The position of the last entry below `bound`, or the length.
+    fn upper_bound_index<Q>(&self, bound: Bound<&Q>) -> usize
+    where
+        K: Borrow<Q>,
+        C: Comparator<Q>,
+        Q: ?Sized,
+    {
+        let len = self.v.as_slice().len();
+        let end = match bound {
+            Bound::Included(q) => self.search(q).map(|i| i + 1).unwrap_or_else(|i| i),
+            Bound::Excluded(q) => self.search(q).unwrap_or_else(|i| i),
+            Bound::Unbounded => len,
+        };
+        end.checked_sub(1).unwrap_or(len)
+    }
+
+    /// Returns the position of `q` in key order, if it is in the map.
+    pub fn index_of<Q>(&self, q: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        C: Comparator<Q>,
+        Q: ?Sized,
+    {
+        self.search(q).ok()
+    }
+
+    /// Returns the number of keys in the map that sort before `q`, whether
+    /// or not `q` is present, in O(log n).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use flat_map::FlatMap;
+    ///
+    /// let map: FlatMap<_, _> = vec![(10, 'a'), (20, 'b'), (30, 'c')].into_iter().collect();
+    /// assert_eq!(map.rank(&20), 1);
+    /// assert_eq!(map.rank(&25), 2);
+    /// assert_eq!(map.index_of(&25), None);
+    /// ```
+    pub fn rank<Q>(&self, q: &Q) -> usize
+    where
+        K: Borrow<Q>,
+        C: Comparator<Q>,
+        Q: ?Sized,
+    {
+        self.search(q).unwrap_or_else(|i| i)
+    }
+
+    /// Returns a double-ended iterator over the entries whose keys fall in
+    /// `range`. Both ends are located by binary search.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the start of the range is greater than its end, or if both
+    /// ends are equal and excluded.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use flat_map::FlatMap;
+    ///
+    /// let map: FlatMap<_, _> = (0..10).map(|i| (i, i * 10)).collect();
+    /// let v: Vec<_> = map.range(3..6).map(|(&k, _)| k).collect();
+    /// assert_eq!(v, [3, 4, 5]);
+    /// ```
+    pub fn range<Q, R>(&self, range: R) -> Range<'_, K, V>
+    where
+        K: Borrow<Q>,
+        C: Comparator<Q>,
+        Q: ?Sized,
+        R: RangeBounds<Q>,
+    {
+        let (start, end) = range_indices(self.v.as_slice(), &self.cmp, &range);
+        Range {
+            inner: Iter {
+                inner: self.v.as_slice()[start..end].iter(),
+            },
+        }
+    }
+
+    /// Returns a double-ended iterator over the entries whose keys fall in
+    /// `range`, with mutable references to the values.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as `range`.
+    pub fn range_mut<Q, R>(&mut self, range: R) -> RangeMut<'_, K, V>
+    where
+        K: Borrow<Q>,
+        C: Comparator<Q>,
+        Q: ?Sized,
+        R: RangeBounds<Q>,
+    {
+        let (start, end) = range_indices(self.v.as_slice(), &self.cmp, &range);
+        RangeMut {
+            inner: IterMut {
+                inner: self.v.as_mut_slice()[start..end].iter_mut(),
+            },
+        }
+    }
+
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, S> {
+        match self.search(&key) {
+            Err(i) => Vacant(VacantEntry {
+                v: &mut self.v,
+                key,
+                index: i,
+                marker: PhantomData,
+            }),
+            Ok(i) => Occupied(OccupiedEntry {
+                v: &mut self.v,
+                index: i,
+                key: Some(key),
+                marker: PhantomData,
+            }),
+        }
+    }
+
+    pub fn remove<Q>(&mut self, q: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        C: Comparator<Q>,
+        Q: ?Sized,
+    {
+        match self.search(q) {
+            Err(_) => None,
+            Ok(i) => {
+                let (_, value) = self.v.remove(i);
+                Some(value)
+            }
+        }
+    }
+
+    /// Removes a key from the map, returning the stored key and value if the
+    /// key was previously in the map.
+    pub fn remove_entry<Q>(&mut self, q: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        C: Comparator<Q>,
+        Q: ?Sized,
+    {
+        match self.search(q) {
+            Err(_) => None,
+            Ok(i) => Some(self.v.remove(i)),
+        }
+    }
+
+    // Swaps in `key` for an equal key already in the map and returns the old
+    // one, or inserts `(key, value)` if there is none. Used by
+    // `FlatSet::replace`.
+    pub(crate) fn replace_key(&mut self, mut key: K, value: V) -> Option<K> {
+        match self.search(&key) {
+            Err(i) => {
+                self.v.insert(i, (key, value));
+                None
+            }
+            Ok(i) => {
+                swap(&mut self.v.as_mut_slice()[i].0, &mut key);
+                Some(key)
+            }
+        }
+    }
+
+    fn search<Q>(&self, q: &Q) -> Result<usize, usize>
+    where
+        K: Borrow<Q>,
+        C: Comparator<Q>,
+        Q: ?Sized,
+    {
+        search(self.v.as_slice(), &self.cmp, q)
+    }
+}
+
+impl<K: NumericKey, V, C: Comparator<K>, S: Storage<(K, V)>> FlatMapBy<K, V, C, S> {
+    /// Finds `key` by interpolation search, returning `Ok` with its position
+    /// or `Err` with the position it would be inserted at, exactly as a
+    /// binary search would.
+    ///
+    /// This is an opt-in alternative to the binary search behind `get` for
+    /// maps whose keys are spread close to evenly, where it needs far fewer
+    /// probes. It never takes more than about twice as many as a binary
+    /// search.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use flat_map::FlatMap;
+    ///
+    /// let map: FlatMap<u32, ()> = (0..1000).map(|i| (i * 3, ())).collect();
+    /// assert_eq!(map.interpolation_search(&300), Ok(100));
+    /// assert_eq!(map.interpolation_search(&301), Err(101));
+    /// ```
+    pub fn interpolation_search(&self, key: &K) -> Result<usize, usize> {
+        interpolation_search(self.v.as_slice(), |(k, _)| k, &self.cmp, key)
+    }
+
+    /// Like `get`, but locates the key with `interpolation_search`.
+    pub fn get_interpolated(&self, key: &K) -> Option<&V> {
+        match self.interpolation_search(key) {
+            Err(_) => None,
+            Ok(idx) => {
+                let (_, ref v) = self.v.as_slice()[idx];
+                Some(v)
+            }
+        }
+    }
+
+    /// Like `get_mut`, but locates the key with `interpolation_search`.
+    pub fn get_mut_interpolated(&mut self, key: &K) -> Option<&mut V> {
+        match self.interpolation_search(key) {
+            Err(_) => None,
+            Ok(idx) => Some(&mut self.v.as_mut_slice()[idx].1),
+        }
+    }
+}
+
+impl<'a, K, V, S: Storage<(K, V)>> Entry<'a, K, V, S> {
+    /// Returns the position of the entry's key in the map, or where it
+    /// would be inserted.
+    pub fn index(&self) -> usize {
+        match *self {
+            Occupied(ref entry) => entry.index(),
+            Vacant(ref entry) => entry.index(),
+        }
+    }
+
+    /// Returns the entry's key: the one in the map if there is one, or else
+    /// the one passed to `entry`.
+    pub fn key(&self) -> &K {
+        match *self {
+            Occupied(ref entry) => entry.key(),
+            Vacant(ref entry) => entry.key(),
+        }
+    }
+
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        match self {
+            Occupied(entry) => entry.into_mut(),
+            Vacant(entry) => entry.insert(default),
+        }
+    }
+
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Occupied(entry) => entry.into_mut(),
+            Vacant(entry) => entry.insert(default()),
+        }
+    }
+
+    /// Like `or_insert_with`, but `default` is given the key so that it
+    /// need not be cloned beforehand.
+    pub fn or_insert_with_key<F: FnOnce(&K) -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Occupied(entry) => entry.into_mut(),
+            Vacant(entry) => {
+                let value = default(entry.key());
+                entry.insert(value)
+            }
+        }
+    }
+
+    /// Inserts `V::default()` if the entry is vacant, and returns a mutable
+    /// reference to the value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use flat_map::FlatMap;
+    ///
+    /// let mut groups: FlatMap<_, Vec<_>> = FlatMap::new();
+    /// for word in ["apple", "avocado", "banana"] {
+    ///     groups.entry(word.as_bytes()[0]).or_default().push(word);
+    /// }
+    /// assert_eq!(groups[&b'a'], ["apple", "avocado"]);
+    /// ```
+    pub fn or_default(self) -> &'a mut V
+    where
+        V: Default,
+    {
+        self.or_insert_with(V::default)
+    }
+
+    /// Runs `f` on the value if the entry is occupied, and returns the entry
+    /// for further chaining.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use flat_map::FlatMap;
+    ///
+    /// let mut counts = FlatMap::new();
+    /// for word in ["a", "b", "a"] {
+    ///     counts.entry(word).and_modify(|n| *n += 1).or_insert(1);
+    /// }
+    /// assert_eq!(counts[&"a"], 2);
+    /// assert_eq!(counts[&"b"], 1);
+    /// ```
+    pub fn and_modify<F: FnOnce(&mut V)>(self, f: F) -> Self {
+        match self {
+            Occupied(mut entry) => {
+                f(entry.get_mut());
+                Occupied(entry)
+            }
+            vacant => vacant,
+        }
+    }
+
+    /// Sets the value of the entry, inserting it if vacant, and returns the
+    /// occupied entry.
+    pub fn insert_entry(self, value: V) -> OccupiedEntry<'a, K, V, S> {
+        match self {
+            Occupied(mut entry) => {
+                entry.insert(value);
+                entry
+            }
+            Vacant(entry) => entry.insert_entry(value),
+        }
+    }
+}
+
+impl<'a, K, V, S: Storage<(K, V)>> VacantEntry<'a, K, V, S> {
+    /// Returns the position the key will be inserted at.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Returns the key that was passed to `entry`.
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Takes back the key that was passed to `entry`, leaving the map as it
+    /// was.
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    pub fn insert(self, value: V) -> &'a mut V {
+        self.insert_entry(value).into_mut()
+    }
+
+    /// Inserts the entry with `value` and returns it as occupied.
+    pub fn insert_entry(self, value: V) -> OccupiedEntry<'a, K, V, S> {
+        self.v.insert(self.index, (self.key, value));
+        OccupiedEntry {
+            v: self.v,
+            index: self.index,
+            key: None,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, K, V, S: Storage<(K, V)>> OccupiedEntry<'a, K, V, S> {
+    /// Returns the position of the entry in the map.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn key(&self) -> &K {
+        let (ref key, _) = self.v.as_slice()[self.index];
+        key
+    }
+
+    pub fn get(&self) -> &V {
+        let (_, ref value) = self.v.as_slice()[self.index];
+        value
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        let (_, ref mut value) = self.v.as_mut_slice()[self.index];
+        value
+    }
+
+    pub fn into_mut(self) -> &'a mut V {
+        let &mut (_, ref mut value) = &mut self.v.as_mut_slice()[self.index];
+        value
+    }
+
+    pub fn insert(&mut self, mut value: V) -> V {
+        let &mut (_, ref mut old_value) = &mut self.v.as_mut_slice()[self.index];
+        swap(old_value, &mut value);
+        value
+    }
+
+    pub fn remove(self) -> V {
+        self.remove_entry().1
+    }
+
+    /// Removes the entry from the map, returning its key and value.
+    pub fn remove_entry(self) -> (K, V) {
+        self.v.remove(self.index)
+    }
+
+    /// Puts the key that was passed to `entry` in the map in place of the
+    /// equal key already there, and returns the old key. This matters for
+    /// keys that compare equal but are still distinguishable, such as
+    /// strings compared case-insensitively, or to free the old key's
+    /// allocation.
+    ///
+    /// Entries from `first_entry`, `last_entry` or `insert_entry` have no
+    /// key to put in, so for those the map is left alone and this returns
+    /// `None`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use flat_map::{FlatMap, Occupied};
+    /// use std::rc::Rc;
+    ///
+    /// let shared: Rc<str> = Rc::from("key");
+    /// let mut map = FlatMap::new();
+    /// map.insert(Rc::from("key"), 1);
+    /// if let Occupied(entry) = map.entry(shared.clone()) {
+    ///     assert!(entry.replace_key().is_some());
+    /// }
+    /// assert_eq!(Rc::strong_count(&shared), 2);
+    /// assert_eq!(map.first_entry().unwrap().replace_key(), None);
+    /// ```
+    pub fn replace_key(self) -> Option<K> {
+        let key = self.key?;
+        Some(replace(&mut self.v.as_mut_slice()[self.index].0, key))
+    }
+}
+
+impl<'a, K: Debug, V: Debug, S: Storage<(K, V)>> Debug for Entry<'a, K, V, S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Vacant(ref entry) => f.debug_tuple("Entry").field(entry).finish(),
+            Occupied(ref entry) => f.debug_tuple("Entry").field(entry).finish(),
+        }
+    }
+}
+
+impl<'a, K: Debug, V, S: Storage<(K, V)>> Debug for VacantEntry<'a, K, V, S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("VacantEntry").field(self.key()).finish()
+    }
+}
+
+impl<'a, K: Debug, V: Debug, S: Storage<(K, V)>> Debug for OccupiedEntry<'a, K, V, S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("OccupiedEntry")
+            .field("key", self.key())
+            .field("value", self.get())
+            .finish()
+    }
+}
+
+impl NotSorted {
+    /// The position of the first entry whose key was not greater than the
+    /// key before it.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+}
+
+impl fmt::Display for NotSorted {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "entry {} is out of order", self.index)
+    }
+}
+
+#[cfg(feature = "std")]
+impl ::std::error::Error for NotSorted {}
+
+impl<K, V> FlatMapBuilder<K, V> {
+    pub fn new() -> FlatMapBuilder<K, V> {
+        FlatMapBuilder::with_comparator(Natural)
+    }
+
+    pub fn with_capacity(capacity: usize) -> FlatMapBuilder<K, V> {
+        FlatMapBuilder {
+            v: Vec::with_capacity(capacity),
+            cmp: Natural,
+        }
+    }
+}
+
+impl<K, V, C> FlatMapBuilder<K, V, C> {
+    /// Creates a builder for a map ordered by `cmp`.
+    pub fn with_comparator(cmp: C) -> Self {
+        FlatMapBuilder { v: Vec::new(), cmp }
+    }
+
+    pub fn len(&self) -> usize {
+        self.v.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.v.is_empty()
+    }
+
+    pub fn build(self) -> FlatMapBy<K, V, C> {
+        FlatMapBy::from_parts(self.v, self.cmp)
+    }
+}
+
+impl<K, V, C: Comparator<K>> FlatMapBuilder<K, V, C> {
+    /// Appends an entry, which must have a key greater than every key pushed
+    /// so far. Out of order entries are not added: the error hands them back
+    /// along with the position they would have taken.
+    pub fn push(&mut self, key: K, value: V) -> Result<(), (NotSorted, (K, V))> {
+        match self.v.last() {
+            Some((last, _)) if self.cmp.compare(last, &key) != Ordering::Less => {
+                let err = NotSorted {
+                    index: self.v.len(),
+                };
+                Err((err, (key, value)))
+            }
+            _ => {
+                self.v.push((key, value));
+                Ok(())
+            }
+        }
+    }
+}
+
+impl<K> DuplicateKeys<K> {
+    /// The keys that occurred more than once, in ascending order.
+    pub fn keys(&self) -> &[K] {
+        &self.keys
+    }
+
+    pub fn into_keys(self) -> Vec<K> {
+        self.keys
+    }
+}
+
+impl<K> fmt::Display for DuplicateKeys<K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} key(s) occur more than once", self.keys.len())
+    }
+}
+
+#[cfg(feature = "std")]
+impl<K: Debug> ::std::error::Error for DuplicateKeys<K> {}
+
+impl<K, V> ExtendError<K, V> {
+    /// The pairs whose keys were repeated in the input or already in the
+    /// map, sorted by key. Pairs with equal keys keep their input order.
+    pub fn conflicting(&self) -> &[(K, V)] {
+        &self.conflicting
+    }
+
+    /// The pairs that could have been inserted, sorted by key.
+    pub fn rest(&self) -> &[(K, V)] {
+        &self.rest
+    }
+
+    /// Returns the conflicting pairs and the rest.
+    pub fn into_parts(self) -> (Pairs<K, V>, Pairs<K, V>) {
+        (self.conflicting, self.rest)
+    }
+}
+
+impl<K, V> fmt::Display for ExtendError<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} pair(s) have keys that are repeated or already in the map",
+            self.conflicting.len()
+        )
+    }
+}
+
+#[cfg(feature = "std")]
+impl<K: Debug, V: Debug> ::std::error::Error for ExtendError<K, V> {}
+
+impl<'a, K, V, S: Storage<(K, V)>> OccupiedError<'a, K, V, S> {
+    /// The entry already in the map.
+    pub fn entry(&self) -> &OccupiedEntry<'a, K, V, S> {
+        &self.entry
+    }
+
+    /// The rejected key.
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// The rejected value.
+    pub fn value(&self) -> &V {
+        &self.value
+    }
+
+    /// Returns the entry already in the map along with the rejected value.
+    /// The entry's `replace_key` puts the rejected key in the map, and its
+    /// `insert` can store the value.
+    pub fn into_entry(self) -> (OccupiedEntry<'a, K, V, S>, V) {
+        let entry = OccupiedEntry {
+            key: Some(self.key),
+            ..self.entry
+        };
+        (entry, self.value)
+    }
+
+    /// Returns the rejected key and value.
+    pub fn into_key_value(self) -> (K, V) {
+        (self.key, self.value)
+    }
+}
+
+impl<'a, K: Debug, V: Debug, S: Storage<(K, V)>> Debug for OccupiedError<'a, K, V, S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("OccupiedError")
+            .field("key", self.key())
+            .field("old_value", self.entry.get())
+            .field("new_value", &self.value)
+            .finish()
+    }
+}
+
+impl<'a, K: Debug, V: Debug, S: Storage<(K, V)>> fmt::Display for OccupiedError<'a, K, V, S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "failed to insert {:?}, key {:?} already exists with value {:?}",
+            self.value,
+            self.key(),
+            self.entry.get()
+        )
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'a, K: Debug, V: Debug, S: Storage<(K, V)>> ::std::error::Error
+    for OccupiedError<'a, K, V, S>
+{
+}
+
+impl<'a, K, V, C> MapView<'a, K, V, C> {
+    pub fn len(&self) -> usize {
+        self.v.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.v.is_empty()
+    }
+
+    pub fn iter(&self) -> Iter<'a, K, V> {
+        Iter {
+            inner: self.v.iter(),
+        }
+    }
+
+    pub fn keys(&self) -> Keys<'a, K, V> {
+        self.iter().into_keys()
+    }
+
+    pub fn values(&self) -> Values<'a, K, V> {
+        self.iter().into_values()
     }
 
-    pub fn append(&mut self, other: &mut Self) {
-        self.v.reserve(other.len());
-        for (k, v) in other.v.drain(..) {
-            self.insert(k, v);
-        }
+    pub fn first_key_value(&self) -> Option<(&'a K, &'a V)> {
+        self.v.first().map(|(k, v)| (k, v))
     }
 
-    pub fn split_off(&mut self, key: &K) -> Self {
-        match self.v[..].binary_search_by(|&(ref k, _)| k.cmp(key)) {
-            Err(_) => Self::new(),
-            Ok(at) => {
-                let v = self.v.split_off(at);
-                FlatMap { v: v }
-            }
-        }
+    pub fn last_key_value(&self) -> Option<(&'a K, &'a V)> {
+        self.v.last().map(|(k, v)| (k, v))
     }
+}
 
-    pub fn get<Q: ?Sized>(&self, q: &Q) -> Option<&V>
+impl<'a, K, V, C: Comparator<K>> MapView<'a, K, V, C> {
+    pub fn get<Q>(&self, q: &Q) -> Option<&'a V>
     where
         K: Borrow<Q>,
-        Q: Ord,
+        C: Comparator<Q>,
+        Q: ?Sized,
     {
-        match self.v[..].binary_search_by(|&(ref k, _)| k.borrow().cmp(q)) {
+        match search(self.v, self.cmp, q) {
             Err(_) => None,
-            Ok(idx) => {
-                let (_, ref v) = self.v[idx];
-                Some(v)
-            }
+            Ok(idx) => Some(&self.v[idx].1),
         }
     }
 
-    pub fn contains_key<Q: ?Sized>(&self, k: &Q) -> bool
+    pub fn contains_key<Q>(&self, q: &Q) -> bool
     where
         K: Borrow<Q>,
-        Q: Ord,
+        C: Comparator<Q>,
+        Q: ?Sized,
     {
-        self.get(k).is_some()
+        self.get(q).is_some()
     }
 
-    /// Return Option<&mut V>.
-    ///
-    /// # Example
+    /// Returns an iterator over the entries of the view whose keys fall in
+    /// `range`.
     ///
-    /// ```
-    /// use flat_map::FlatMap;
+    /// # Panics
     ///
-    /// let mut m = FlatMap::new();
-    /// m.insert(1, "foo".to_string());
-    /// m.get_mut(&1).unwrap().push_str("bar");
-    /// assert_eq!("foobar", m.get_mut(&1).unwrap());
-    /// ```
-    pub fn get_mut<Q: ?Sized>(&mut self, q: &Q) -> Option<&mut V>
+    /// Panics under the same conditions as `FlatMap::range`.
+    pub fn range<Q, R>(&self, range: R) -> Range<'a, K, V>
     where
         K: Borrow<Q>,
-        Q: Ord,
+        C: Comparator<Q>,
+        Q: ?Sized,
+        R: RangeBounds<Q>,
     {
-        match self.v[..].binary_search_by(|&(ref k, _)| k.borrow().cmp(q)) {
-            Err(_) => None,
-            Ok(idx) => match self.v.get_mut(idx) {
-                Some(&mut (_, ref mut v)) => Some(v),
-                _ => None,
+        let (start, end) = range_indices(self.v, self.cmp, &range);
+        Range {
+            inner: Iter {
+                inner: self.v[start..end].iter(),
             },
         }
     }
+}
 
-    pub fn entry(&mut self, key: K) -> Entry<K, V> {
-        match self.v[..].binary_search_by(|&(ref k, _)| k.cmp(&key)) {
-            Err(i) => Vacant(VacantEntry {
-                v: &mut self.v,
-                key: key,
-                index: i,
-            }),
-            Ok(i) => Occupied(OccupiedEntry {
-                v: &mut self.v,
-                index: i,
-            }),
-        }
+impl<'a, K, V, C> Clone for MapView<'a, K, V, C> {
+    fn clone(&self) -> MapView<'a, K, V, C> {
+        *self
     }
+}
 
-    pub fn remove<Q: ?Sized>(&mut self, q: &Q) -> Option<V>
-    where
-        K: Borrow<Q>,
-        Q: Ord,
-    {
-        match self.v[..].binary_search_by(|&(ref k, _)| k.borrow().cmp(q)) {
-            Err(_) => None,
-            Ok(i) => {
-                let (_, value) = self.v.remove(i);
-                Some(value)
-            }
+impl<'a, K, V, C> Copy for MapView<'a, K, V, C> {}
+
+impl<'a, K, V> Cursor<'a, K, V> {
+    /// Moves to the next entry, or from the last entry to the ghost
+    /// position, or from the ghost position to the first entry.
+    pub fn move_next(&mut self) {
+        self.index = next_index(self.index, self.v.len());
+    }
+
+    /// Moves to the previous entry, or from the first entry to the ghost
+    /// position, or from the ghost position to the last entry.
+    pub fn move_prev(&mut self) {
+        self.index = match self.index {
+            0 => self.v.len(),
+            i => i - 1,
+        };
+    }
+
+    /// Returns the position of the current entry, or `None` at the ghost
+    /// position.
+    pub fn index(&self) -> Option<usize> {
+        if self.index < self.v.len() {
+            Some(self.index)
+        } else {
+            None
         }
     }
+
+    pub fn key(&self) -> Option<&'a K> {
+        self.key_value().map(|(k, _)| k)
+    }
+
+    pub fn value(&self) -> Option<&'a V> {
+        self.key_value().map(|(_, v)| v)
+    }
+
+    pub fn key_value(&self) -> Option<(&'a K, &'a V)> {
+        self.v.get(self.index).map(|(k, v)| (k, v))
+    }
+
+    /// Returns the entry the cursor would move to with `move_next`, or
+    /// `None` if that is the ghost position.
+    pub fn peek_next(&self) -> Option<(&'a K, &'a V)> {
+        self.v
+            .get(next_index(self.index, self.v.len()))
+            .map(|(k, v)| (k, v))
+    }
+
+    /// Returns the entry the cursor would move to with `move_prev`, or
+    /// `None` if that is the ghost position.
+    pub fn peek_prev(&self) -> Option<(&'a K, &'a V)> {
+        self.v
+            .get(prev_index(self.index, self.v.len()))
+            .map(|(k, v)| (k, v))
+    }
 }
 
-impl<'a, K: Ord, V> Entry<'a, K, V> {
-    pub fn or_insert(self, default: V) -> &'a mut V {
-        match self {
-            Occupied(entry) => entry.into_mut(),
-            Vacant(entry) => entry.insert(default),
-        }
+impl<'a, K, V> Clone for Cursor<'a, K, V> {
+    fn clone(&self) -> Cursor<'a, K, V> {
+        *self
     }
+}
 
-    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V {
-        match self {
-            Occupied(entry) => entry.into_mut(),
-            Vacant(entry) => entry.insert(default()),
-        }
+impl<'a, K, V> Copy for Cursor<'a, K, V> {}
+
+impl<'a, K: Debug, V: Debug> Debug for Cursor<'a, K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Cursor").field(&self.key_value()).finish()
     }
 }
 
-impl<'a, K: Ord, V> VacantEntry<'a, K, V> {
-    pub fn insert(self, value: V) -> &'a mut V {
-        self.v.insert(self.index, (self.key, value));
-        let &mut (_, ref mut value) = &mut self.v[self.index];
-        value
+// The position after `index` among `len` entries, where `len` is the ghost.
+fn next_index(index: usize, len: usize) -> usize {
+    if index == len {
+        0
+    } else {
+        index + 1
     }
 }
 
-impl<'a, K: Ord, V> OccupiedEntry<'a, K, V> {
-    pub fn key(&self) -> &K {
-        let (ref key, _) = self.v[self.index];
-        key
+// The position before `index` among `len` entries, where `len` is the ghost
+// and anything past the end means there is none.
+fn prev_index(index: usize, len: usize) -> usize {
+    match index {
+        0 => len,
+        i if i == len => len.wrapping_sub(1),
+        i => i - 1,
     }
+}
 
-    pub fn get(&self) -> &V {
-        let (_, ref value) = self.v[self.index];
-        value
+impl<'a, K, V, C, S: Storage<(K, V)>> CursorMut<'a, K, V, C, S> {
+    fn len(&self) -> usize {
+        self.v.as_slice().len()
     }
 
-    pub fn get_mut(&mut self) -> &mut V {
-        let (_, ref mut value) = self.v[self.index];
-        value
+    /// Moves to the next entry, or from the last entry to the ghost
+    /// position, or from the ghost position to the first entry.
+    pub fn move_next(&mut self) {
+        self.index = next_index(self.index, self.len());
     }
 
-    pub fn into_mut(self) -> &'a mut V {
-        let &mut (_, ref mut value) = &mut self.v[self.index];
-        value
+    /// Moves to the previous entry, or from the first entry to the ghost
+    /// position, or from the ghost position to the last entry.
+    pub fn move_prev(&mut self) {
+        self.index = match self.index {
+            0 => self.len(),
+            i => i - 1,
+        };
     }
 
-    pub fn insert(&mut self, mut value: V) -> V {
-        let &mut (_, ref mut old_value) = &mut self.v[self.index];
-        swap(old_value, &mut value);
-        value
+    /// Returns the position of the current entry, or `None` at the ghost
+    /// position.
+    pub fn index(&self) -> Option<usize> {
+        self.as_cursor().index()
     }
 
-    pub fn remove(self) -> V {
-        let (_, value) = self.v.remove(self.index);
-        value
+    pub fn key(&self) -> Option<&K> {
+        self.as_cursor().key()
     }
-}
 
-impl<'a, K, V> Iterator for Iter<'a, K, V> {
-    type Item = (&'a K, &'a V);
+    pub fn value(&self) -> Option<&V> {
+        self.as_cursor().value()
+    }
+
+    pub fn value_mut(&mut self) -> Option<&mut V> {
+        self.key_value_mut().map(|(_, v)| v)
+    }
+
+    pub fn key_value_mut(&mut self) -> Option<(&K, &mut V)> {
+        let index = self.index;
+        self.v.as_mut_slice().get_mut(index).map(|(k, v)| (&*k, v))
+    }
+
+    /// Returns the entry the cursor would move to with `move_next`, or
+    /// `None` if that is the ghost position.
+    pub fn peek_next(&mut self) -> Option<(&K, &mut V)> {
+        let next = next_index(self.index, self.len());
+        self.v.as_mut_slice().get_mut(next).map(|(k, v)| (&*k, v))
+    }
+
+    /// Returns the entry the cursor would move to with `move_prev`, or
+    /// `None` if that is the ghost position.
+    pub fn peek_prev(&mut self) -> Option<(&K, &mut V)> {
+        let prev = prev_index(self.index, self.len());
+        self.v.as_mut_slice().get_mut(prev).map(|(k, v)| (&*k, v))
+    }
 
-    fn next(&mut self) -> Option<(&'a K, &'a V)> {
-        match self.inner.next() {
-            Some(&(ref k, ref v)) => Some((k, v)),
-            None => None,
+    /// Returns a read-only cursor at the same position, borrowing this one.
+    pub fn as_cursor(&self) -> Cursor<'_, K, V> {
+        Cursor {
+            v: self.v.as_slice(),
+            index: self.index,
         }
     }
-    fn size_hint(&self) -> (usize, Option<usize>) {
-        self.inner.size_hint()
+
+    /// Inserts an entry right after the current one, or at the front of the
+    /// map at the ghost position. The cursor stays where it is.
+    ///
+    /// # Safety
+    ///
+    /// `key` must sort strictly between the current entry and the next one.
+    /// Lookups, inserts and every other ordered operation give unspecified
+    /// results otherwise.
+    pub unsafe fn insert_after_unchecked(&mut self, key: K, value: V) {
+        let len = self.len();
+        if self.index == len {
+            self.v.insert(0, (key, value));
+            self.index += 1;
+        } else {
+            self.v.insert(self.index + 1, (key, value));
+        }
     }
-}
 
-impl<'a, K, V> Clone for Iter<'a, K, V> {
-    fn clone(&self) -> Iter<'a, K, V> {
-        Iter {
-            inner: self.inner.clone(),
+    /// Inserts an entry right before the current one, or at the back of the
+    /// map at the ghost position. The cursor stays where it is.
+    ///
+    /// # Safety
+    ///
+    /// `key` must sort strictly between the previous entry and the current
+    /// one. Lookups, inserts and every other ordered operation give
+    /// unspecified results otherwise.
+    pub unsafe fn insert_before_unchecked(&mut self, key: K, value: V) {
+        self.v.insert(self.index, (key, value));
+        self.index += 1;
+    }
+
+    /// Removes the current entry and moves to the next one. Does nothing at
+    /// the ghost position.
+    pub fn remove_current(&mut self) -> Option<(K, V)> {
+        if self.index == self.len() {
+            None
+        } else {
+            Some(self.v.remove(self.index))
+        }
+    }
+
+    /// Removes the current entry and moves to the previous one. Does
+    /// nothing at the ghost position.
+    pub fn remove_current_and_move_back(&mut self) -> Option<(K, V)> {
+        let removed = self.remove_current();
+        if removed.is_some() {
+            self.move_prev();
         }
+        removed
     }
 }
 
-impl<'a, K, V> DoubleEndedIterator for Iter<'a, K, V> {
-    fn next_back(&mut self) -> Option<(&'a K, &'a V)> {
-        match self.inner.next_back() {
-            Some(&(ref k, ref v)) => Some((k, v)),
-            None => None,
+impl<'a, K, V, C: Comparator<K>, S: Storage<(K, V)>> CursorMut<'a, K, V, C, S> {
+    /// Inserts an entry right after the current one, or at the front of the
+    /// map at the ghost position. The cursor stays where it is.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `key` does not sort strictly between the current entry and
+    /// the next one.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use flat_map::FlatMap;
+    ///
+    /// let mut map: FlatMap<_, _> = vec![(1, 'a'), (4, 'd')].into_iter().collect();
+    /// let mut cursor = map.cursor_front_mut();
+    /// cursor.insert_after(3, 'c');
+    /// cursor.insert_after(2, 'b');
+    /// cursor.move_next();
+    /// assert_eq!(cursor.remove_current(), Some((2, 'b')));
+    /// assert_eq!(cursor.key(), Some(&3));
+    /// assert!(map.keys().eq(&[1, 3, 4]));
+    /// ```
+    pub fn insert_after(&mut self, key: K, value: V) {
+        let at = next_index(self.index, self.len());
+        self.check_order(at, &key);
+        unsafe { self.insert_after_unchecked(key, value) }
+    }
+
+    /// Inserts an entry right before the current one, or at the back of the
+    /// map at the ghost position. The cursor stays where it is.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `key` does not sort strictly between the previous entry
+    /// and the current one.
+    pub fn insert_before(&mut self, key: K, value: V) {
+        self.check_order(self.index, &key);
+        unsafe { self.insert_before_unchecked(key, value) }
+    }
+
+    // Checks that `key` may be inserted at position `at`.
+    fn check_order(&self, at: usize, key: &K) {
+        let v = self.v.as_slice();
+        if let Some((prev, _)) = at.checked_sub(1).map(|i| &v[i]) {
+            assert!(
+                self.cmp.compare(prev, key) == Ordering::Less,
+                "key does not sort after the entry before it"
+            );
+        }
+        if let Some((next, _)) = v.get(at) {
+            assert!(
+                self.cmp.compare(key, next) == Ordering::Less,
+                "key does not sort before the entry after it"
+            );
         }
     }
 }
 
-impl<'a, K, V> ExactSizeIterator for Iter<'a, K, V> {}
+impl<'a, K: Debug, V: Debug, C, S: Storage<(K, V)>> Debug for CursorMut<'a, K, V, C, S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("CursorMut")
+            .field(&self.as_cursor().key_value())
+            .finish()
+    }
+}
 
-impl<'a, K, V> Iterator for IterMut<'a, K, V> {
-    type Item = (&'a K, &'a mut V);
+impl<'a, K, V, C> IntoIterator for MapView<'a, K, V, C> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
 
-    fn next(&mut self) -> Option<(&'a K, &'a mut V)> {
-        match self.inner.next() {
-            Some(&mut (ref k, ref mut v)) => Some((k, v)),
-            None => None,
-        }
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+impl<'a, K: Debug, V: Debug, C> Debug for MapView<'a, K, V, C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K, V, S: IntoIterator<Item = (K, V)>> Iterator for IntoIter<K, V, S> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<(K, V)> {
+        self.inner.next()
     }
     fn size_hint(&self) -> (usize, Option<usize>) {
         self.inner.size_hint()
     }
 }
 
-impl<'a, K, V> DoubleEndedIterator for IterMut<'a, K, V> {
-    fn next_back(&mut self) -> Option<(&'a K, &'a mut V)> {
-        match self.inner.next_back() {
-            Some(&mut (ref k, ref mut v)) => Some((k, v)),
-            None => None,
-        }
+impl<K, V, S> DoubleEndedIterator for IntoIter<K, V, S>
+where
+    S: IntoIterator<Item = (K, V)>,
+    S::IntoIter: DoubleEndedIterator,
+{
+    fn next_back(&mut self) -> Option<(K, V)> {
+        self.inner.next_back()
     }
 }
 
-impl<'a, K, V> ExactSizeIterator for IterMut<'a, K, V> {}
+impl<K, V, S> ExactSizeIterator for IntoIter<K, V, S>
+where
+    S: IntoIterator<Item = (K, V)>,
+    S::IntoIter: ExactSizeIterator,
+{
+}
 
-impl<K, V> Iterator for IntoIter<K, V> {
+impl<'a, K, V> Iterator for Drain<'a, K, V> {
     type Item = (K, V);
 
     fn next(&mut self) -> Option<(K, V)> {
         self.inner.next()
     }
+
     fn size_hint(&self) -> (usize, Option<usize>) {
         self.inner.size_hint()
     }
 }
 
-impl<K, V> DoubleEndedIterator for IntoIter<K, V> {
+impl<'a, K, V> DoubleEndedIterator for Drain<'a, K, V> {
     fn next_back(&mut self) -> Option<(K, V)> {
         self.inner.next_back()
     }
 }
 
-impl<K, V> ExactSizeIterator for IntoIter<K, V> {}
+impl<'a, K, V> ExactSizeIterator for Drain<'a, K, V> {}
+
+impl<'a, K, V, F> ExtractIf<'a, K, V, F> {
+    // Tests the entries in `v[start..end]`.
+    fn new(v: &'a mut Vec<(K, V)>, start: usize, end: usize, pred: F) -> Self {
+        let old_len = v.len();
+        // Until the iterator is dropped the vector only owns the entries
+        // before the ones being tested, so a leaked iterator leaks entries
+        // rather than exposing moved-out ones.
+        unsafe { v.set_len(start) };
+        ExtractIf {
+            v,
+            pred,
+            index: start,
+            end,
+            removed: 0,
+            old_len,
+        }
+    }
+}
+
+impl<'a, K, V, F> Iterator for ExtractIf<'a, K, V, F>
+where
+    F: FnMut(&K, &mut V) -> bool,
+{
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<(K, V)> {
+        unsafe {
+            let base = self.v.as_mut_ptr();
+            while self.index < self.end {
+                let i = self.index;
+                let entry = &mut *base.add(i);
+                // If `pred` panics, `drop` keeps this entry and the rest.
+                let extract = (self.pred)(&entry.0, &mut entry.1);
+                self.index += 1;
+                if extract {
+                    self.removed += 1;
+                    return Some(ptr::read(base.add(i)));
+                } else if self.removed > 0 {
+                    ptr::copy_nonoverlapping(base.add(i), base.add(i - self.removed), 1);
+                }
+            }
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.end - self.index))
+    }
+}
+
+impl<'a, K, V, F> Drop for ExtractIf<'a, K, V, F> {
+    fn drop(&mut self) {
+        unsafe {
+            let base = self.v.as_mut_ptr();
+            if self.removed > 0 {
+                ptr::copy(
+                    base.add(self.index),
+                    base.add(self.index - self.removed),
+                    self.old_len - self.index,
+                );
+            }
+            self.v.set_len(self.old_len - self.removed);
+        }
+    }
+}
 
-impl<K, V> IntoIterator for FlatMap<K, V> {
+impl<K, V, C, S: Storage<(K, V)>> IntoIterator for FlatMapBy<K, V, C, S> {
     type Item = (K, V);
-    type IntoIter = IntoIter<K, V>;
+    type IntoIter = IntoIter<K, V, S>;
 
-    fn into_iter(self) -> IntoIter<K, V> {
+    fn into_iter(self) -> IntoIter<K, V, S> {
         IntoIter {
             inner: self.v.into_iter(),
         }
     }
 }
 
-impl<'a, K, V> IntoIterator for &'a FlatMap<K, V> {
+impl<'a, K, V, C, S: Storage<(K, V)>> IntoIterator for &'a FlatMapBy<K, V, C, S> {
     type Item = (&'a K, &'a V);
     type IntoIter = Iter<'a, K, V>;
 
     fn into_iter(self) -> Iter<'a, K, V> {
         Iter {
-            inner: self.v.iter(),
+            inner: self.v.as_slice().iter(),
         }
     }
 }
 
-impl<'a, K, V> IntoIterator for &'a mut FlatMap<K, V> {
+impl<'a, K, V, C, S: Storage<(K, V)>> IntoIterator for &'a mut FlatMapBy<K, V, C, S> {
     type Item = (&'a K, &'a mut V);
     type IntoIter = IterMut<'a, K, V>;
 
     fn into_iter(self) -> IterMut<'a, K, V> {
         IterMut {
-            inner: self.v.iter_mut(),
+            inner: self.v.as_mut_slice().iter_mut(),
         }
     }
 }
 
-impl<'a, K, V> Clone for Keys<'a, K, V> {
-    fn clone(&self) -> Keys<'a, K, V> {
-        Keys {
-            inner: self.inner.clone(),
-        }
+impl<K, V, C, S> FromIterator<(K, V)> for FlatMapBy<K, V, C, S>
+where
+    C: Comparator<K> + Default,
+    S: Storage<(K, V)>,
+{
+    /// Builds a map from `iter`. If a key occurs more than once, the last
+    /// pair wins, as with `BTreeMap`.
+    fn from_iter<T: IntoIterator<Item = (K, V)>>(iter: T) -> Self {
+        FlatMapBy::from_iter_with_comparator(iter, C::default())
     }
 }
 
-impl<'a, K, V> Iterator for Keys<'a, K, V> {
-    type Item = &'a K;
+// Extending by at most this many elements inserts them one at a time rather
+// than sorting and merging.
+pub(crate) const EXTEND_INSERT_THRESHOLD: usize = 8;
 
-    fn next(&mut self) -> Option<&'a K> {
-        self.inner.next()
-    }
-    fn size_hint(&self) -> (usize, Option<usize>) {
-        self.inner.size_hint()
+impl<K, V, C: Comparator<K>, S: Storage<(K, V)>> Extend<(K, V)> for FlatMapBy<K, V, C, S> {
+    /// Inserts every pair from `iter`, with later pairs winning on equal keys.
+    /// As with `insert`, a key already in the map keeps its stored key and
+    /// only has its value replaced.
+    ///
+    /// Small batches are inserted one by one. Larger ones are collected,
+    /// stably sorted, deduplicated and merged into the map in one pass, for a
+    /// total of O(n + m log m).
+    fn extend<T: IntoIterator<Item = (K, V)>>(&mut self, iter: T) {
+        let iter = iter.into_iter();
+        match iter.size_hint() {
+            (_, Some(upper)) if upper <= EXTEND_INSERT_THRESHOLD => {
+                for (k, v) in iter {
+                    self.insert(k, v);
+                }
+            }
+            _ => self.merge_batch(iter, OnEqual::ReplaceValue),
+        }
     }
 }
 
-impl<'a, K, V> DoubleEndedIterator for Keys<'a, K, V> {
-    fn next_back(&mut self) -> Option<&'a K> {
-        self.inner.next_back()
+impl<'a, K: Copy, V: Copy, C, S> Extend<(&'a K, &'a V)> for FlatMapBy<K, V, C, S>
+where
+    C: Comparator<K>,
+    S: Storage<(K, V)>,
+{
+    fn extend<I: IntoIterator<Item = (&'a K, &'a V)>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().map(|(&key, &value)| (key, value)));
     }
 }
 
-impl<'a, K, V> ExactSizeIterator for Keys<'a, K, V> {}
-
-impl<'a, K, V> Clone for Values<'a, K, V> {
-    fn clone(&self) -> Values<'a, K, V> {
-        Values {
-            inner: self.inner.clone(),
+impl<K, V, C: Comparator<K>, S: Storage<(K, V)>> FlatMapBy<K, V, C, S> {
+    // Sorts the pairs from `iter` and merges them into the map in one pass,
+    // the large-batch half of `extend`. With `OnEqual::Keep` the first of
+    // several equal keys in `iter` is kept, otherwise the last.
+    pub(crate) fn merge_batch<I>(&mut self, iter: I, on_equal: OnEqual)
+    where
+        I: Iterator<Item = (K, V)>,
+    {
+        let FlatMapBy { v, cmp, .. } = self;
+        let mut vec: Vec<_> = iter.collect();
+        if on_equal == OnEqual::Keep {
+            vec.sort_by(|kv1, kv2| cmp.compare(&kv1.0, &kv2.0));
+            vec.dedup_by(|later, kept| cmp.compare(&later.0, &kept.0) == Ordering::Equal);
+        } else {
+            sort_dedup_last(&mut vec, |a, b| cmp.compare(a, b));
         }
+        v.with_vec(|dst| merge_sorted(dst, &mut vec, on_equal, |a, b| cmp.compare(a, b)));
     }
 }
 
-impl<'a, K, V> Iterator for Values<'a, K, V> {
-    type Item = &'a V;
-
-    fn next(&mut self) -> Option<&'a V> {
-        self.inner.next()
-    }
-    fn size_hint(&self) -> (usize, Option<usize>) {
-        self.inner.size_hint()
-    }
+// Stably sorts `vec` by key and removes duplicate keys, keeping the last
+// entry of each run so that later pairs win, as they would with `insert`.
+pub(crate) fn sort_dedup_last<K, V, F>(vec: &mut Vec<(K, V)>, mut cmp: F)
+where
+    F: FnMut(&K, &K) -> Ordering,
+{
+    vec.sort_by(|kv1, kv2| cmp(&kv1.0, &kv2.0));
+    vec.dedup_by(|later, kept| {
+        if cmp(&later.0, &kept.0) == Ordering::Equal {
+            swap(later, kept);
+            true
+        } else {
+            false
+        }
+    });
 }
 
-impl<'a, K, V> DoubleEndedIterator for Values<'a, K, V> {
-    fn next_back(&mut self) -> Option<&'a V> {
-        self.inner.next_back()
-    }
+// What `merge_sorted` does with an entry from `src` whose key is already in
+// `dst`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum OnEqual {
+    // The entry from `src` replaces the one in `dst`, as with `append`.
+    Replace,
+    // Only the value is taken from `src`, and `dst` keeps its key, as with
+    // `insert`.
+    ReplaceValue,
+    // The entry in `dst` is kept and the one from `src` is dropped.
+    Keep,
 }
 
-impl<'a, K, V> ExactSizeIterator for Values<'a, K, V> {}
-
-impl<K: Ord, V> FromIterator<(K, V)> for FlatMap<K, V> {
-    fn from_iter<T: IntoIterator<Item = (K, V)>>(iter: T) -> FlatMap<K, V> {
-        let mut vec: Vec<_> = iter.into_iter().collect();
-        vec.sort_by(|kv1, kv2| kv1.0.cmp(&kv2.0));
-        vec.dedup_by(|kv1, kv2| kv1.0 == kv2.0);
-        Self { v: vec }
+// Merges the sorted, duplicate-free `src` into the sorted, duplicate-free
+// `dst`, leaving `src` empty. Equal keys are resolved by `on_equal`.
+//
+// The merge runs backwards from the end of `dst`'s spare capacity, so nothing
+// is moved more than once. Equal keys leave a gap at the front of the merged
+// run which is closed with a single copy at the end.
+fn merge_sorted<K, V, F>(
+    dst: &mut Vec<(K, V)>,
+    src: &mut Vec<(K, V)>,
+    on_equal: OnEqual,
+    mut cmp: F,
+) where
+    F: FnMut(&K, &K) -> Ordering,
+{
+    match (dst.last(), src.first()) {
+        (_, None) => return,
+        (None, _) => return swap(dst, src),
+        (Some(last), Some(first)) if cmp(&last.0, &first.0) == Ordering::Less => {
+            return dst.append(src)
+        }
+        _ => {}
+    }
+    if let (Some(first), Some(last)) = (dst.first(), src.last()) {
+        if cmp(&last.0, &first.0) == Ordering::Less {
+            src.append(dst);
+            return swap(dst, src);
+        }
     }
-}
 
-impl<K: Ord, V> Extend<(K, V)> for FlatMap<K, V> {
-    fn extend<T: IntoIterator<Item = (K, V)>>(&mut self, iter: T) {
-        for (k, v) in iter {
-            self.insert(k, v);
+    // While merging, `dst[..i]` and `src[..j]` hold the entries still to be
+    // merged and `dst[d..end]` holds the merged tail. Everything else is
+    // uninitialized. If a comparison or a drop panics, the guard closes the
+    // gap so both vectors are left sorted and nothing is leaked or dropped
+    // twice.
+    struct Merge<'a, T: 'a> {
+        dst: &'a mut Vec<T>,
+        src: &'a mut Vec<T>,
+        i: usize,
+        j: usize,
+        d: usize,
+        end: usize,
+    }
+
+    impl<'a, T> Drop for Merge<'a, T> {
+        fn drop(&mut self) {
+            unsafe {
+                let base = self.dst.as_mut_ptr();
+                ptr::copy(base.add(self.d), base.add(self.i), self.end - self.d);
+                self.dst.set_len(self.i + self.end - self.d);
+                self.src.set_len(self.j);
+            }
         }
     }
-}
 
-impl<'a, K: Ord + Copy, V: Copy> Extend<(&'a K, &'a V)> for FlatMap<K, V> {
-    fn extend<I: IntoIterator<Item = (&'a K, &'a V)>>(&mut self, iter: I) {
-        self.extend(iter.into_iter().map(|(&key, &value)| (key, value)));
+    dst.reserve(src.len());
+    let end = dst.len() + src.len();
+    let mut m = Merge {
+        i: dst.len(),
+        j: src.len(),
+        d: end,
+        end,
+        dst,
+        src,
+    };
+    unsafe {
+        m.dst.set_len(0);
+        m.src.set_len(0);
+        let a = m.dst.as_mut_ptr();
+        let b = m.src.as_mut_ptr();
+        while m.i > 0 && m.j > 0 {
+            let order = cmp(&(*a.add(m.i - 1)).0, &(*b.add(m.j - 1)).0);
+            match order {
+                Ordering::Greater => {
+                    m.i -= 1;
+                    m.d -= 1;
+                    ptr::copy(a.add(m.i), a.add(m.d), 1);
+                }
+                Ordering::Less => {
+                    m.j -= 1;
+                    m.d -= 1;
+                    ptr::copy_nonoverlapping(b.add(m.j), a.add(m.d), 1);
+                }
+                Ordering::Equal if on_equal == OnEqual::Replace => {
+                    m.i -= 1;
+                    let replaced = ptr::read(a.add(m.i));
+                    m.j -= 1;
+                    m.d -= 1;
+                    ptr::copy_nonoverlapping(b.add(m.j), a.add(m.d), 1);
+                    drop(replaced);
+                }
+                Ordering::Equal => {
+                    m.j -= 1;
+                    let mut rejected = ptr::read(b.add(m.j));
+                    if on_equal == OnEqual::ReplaceValue {
+                        swap(&mut (*a.add(m.i - 1)).1, &mut rejected.1);
+                    }
+                    m.i -= 1;
+                    m.d -= 1;
+                    ptr::copy(a.add(m.i), a.add(m.d), 1);
+                    drop(rejected);
+                }
+            }
+        }
+        m.d -= m.j;
+        ptr::copy_nonoverlapping(b, a.add(m.d), m.j);
+        m.j = 0;
     }
 }
 
-impl<K: Hash, V: Hash> Hash for FlatMap<K, V> {
+impl<K: Hash, V: Hash, C, S: Storage<(K, V)>> Hash for FlatMapBy<K, V, C, S> {
     fn hash<H: Hasher>(&self, state: &mut H) {
         for elt in self {
             elt.hash(state);
@@ -527,36 +2551,37 @@ impl<K: Hash, V: Hash> Hash for FlatMap<K, V> {
     }
 }
 
-impl<K: Ord, V: Ord> Ord for FlatMap<K, V> {
-    fn cmp(&self, other: &FlatMap<K, V>) -> Ordering {
+impl<K: Ord, V: Ord, C, S: Storage<(K, V)>> Ord for FlatMapBy<K, V, C, S> {
+    fn cmp(&self, other: &Self) -> Ordering {
         self.iter().cmp(other.iter())
     }
 }
 
-impl<K: PartialEq, V: PartialEq> PartialEq for FlatMap<K, V> {
-    fn eq(&self, other: &FlatMap<K, V>) -> bool {
+impl<K: PartialEq, V: PartialEq, C, S: Storage<(K, V)>> PartialEq for FlatMapBy<K, V, C, S> {
+    fn eq(&self, other: &Self) -> bool {
         self.len() == other.len() && self.iter().zip(other).all(|(a, b)| a == b)
     }
 }
 
-impl<K: Eq, V: Eq> Eq for FlatMap<K, V> {}
+impl<K: Eq, V: Eq, C, S: Storage<(K, V)>> Eq for FlatMapBy<K, V, C, S> {}
 
-impl<K: PartialOrd, V: PartialOrd> PartialOrd for FlatMap<K, V> {
-    fn partial_cmp(&self, other: &FlatMap<K, V>) -> Option<Ordering> {
+impl<K: PartialOrd, V: PartialOrd, C, S: Storage<(K, V)>> PartialOrd for FlatMapBy<K, V, C, S> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         self.iter().partial_cmp(other.iter())
     }
 }
 
-impl<K: Debug, V: Debug> Debug for FlatMap<K, V> {
+impl<K: Debug, V: Debug, C, S: Storage<(K, V)>> Debug for FlatMapBy<K, V, C, S> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_map().entries(self.iter()).finish()
     }
 }
 
-impl<'a, K: Ord, Q: ?Sized, V> Index<&'a Q> for FlatMap<K, V>
+impl<K, Q: ?Sized, V, C, S> Index<&Q> for FlatMapBy<K, V, C, S>
 where
+    S: Storage<(K, V)>,
     K: Borrow<Q>,
-    Q: Ord,
+    C: Comparator<K> + Comparator<Q>,
 {
     type Output = V;
 
@@ -565,10 +2590,11 @@ where
     }
 }
 
-impl<'a, K: Ord, Q: ?Sized, V> IndexMut<&'a Q> for FlatMap<K, V>
+impl<K, Q: ?Sized, V, C, S> IndexMut<&Q> for FlatMapBy<K, V, C, S>
 where
+    S: Storage<(K, V)>,
     K: BorrowMut<Q>,
-    Q: Ord,
+    C: Comparator<K> + Comparator<Q>,
 {
     // type Output = &V;
 
@@ -577,30 +2603,6 @@ where
     }
 }
 
-impl<'a, K, V> Iterator for ValuesMut<'a, K, V> {
-    type Item = &'a mut V;
-
-    fn next(&mut self) -> Option<&'a mut V> {
-        self.inner.next().map(|(_, v)| v)
-    }
-
-    fn size_hint(&self) -> (usize, Option<usize>) {
-        self.inner.size_hint()
-    }
-}
-
-impl<'a, K, V> DoubleEndedIterator for ValuesMut<'a, K, V> {
-    fn next_back(&mut self) -> Option<&'a mut V> {
-        self.inner.next_back().map(|(_, v)| v)
-    }
-}
-
-impl<'a, K, V> ExactSizeIterator for ValuesMut<'a, K, V> {
-    fn len(&self) -> usize {
-        self.inner.len()
-    }
-}
-
 #[cfg(feature = "serde1")]
 mod serde_impl {
     // the serde serialization/deserialization is manually handled to
@@ -611,16 +2613,20 @@ mod serde_impl {
     // and not
     // {"v": [["k1", "v1"],["k2", "v2"]]}
 
-    use super::FlatMap;
+    use super::{sort_dedup_last, FlatMapBy};
+    use crate::compare::Comparator;
+    use crate::storage::Storage;
+    use alloc::vec::Vec;
+    use core::fmt;
+    use core::marker::PhantomData;
     use serde::de::{Deserialize, Deserializer, MapAccess, Visitor};
     use serde::ser::SerializeMap;
     use serde::{Serialize, Serializer};
-    use std::fmt;
-    use std::marker::PhantomData;
 
-    impl<K, V> Serialize for FlatMap<K, V>
+    impl<K, V, C, T> Serialize for FlatMapBy<K, V, C, T>
     where
-        K: Ord + Serialize,
+        T: Storage<(K, V)>,
+        K: Serialize,
         V: Serialize,
     {
         fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
@@ -635,24 +2641,28 @@ mod serde_impl {
         }
     }
 
-    struct FlatMapVisitor<K, V> {
-        marker: PhantomData<fn() -> FlatMap<K, V>>,
+    struct FlatMapVisitor<K, V, C, T> {
+        cmp: C,
+        marker: PhantomData<FlatMapBy<K, V, C, T>>,
     }
 
-    impl<K, V> FlatMapVisitor<K, V> {
-        fn new() -> Self {
+    impl<K, V, C, T> FlatMapVisitor<K, V, C, T> {
+        fn new(cmp: C) -> Self {
             FlatMapVisitor {
+                cmp,
                 marker: PhantomData,
             }
         }
     }
 
-    impl<'de, K: Ord, V> Visitor<'de> for FlatMapVisitor<K, V>
+    impl<'de, K, V, C, T> Visitor<'de> for FlatMapVisitor<K, V, C, T>
     where
         K: Deserialize<'de>,
         V: Deserialize<'de>,
+        C: Comparator<K>,
+        T: Storage<(K, V)>,
     {
-        type Value = FlatMap<K, V>;
+        type Value = FlatMapBy<K, V, C, T>;
 
         fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
             formatter.write_str("a flat_map")
@@ -662,24 +2672,48 @@ mod serde_impl {
         where
             M: MapAccess<'de>,
         {
-            let mut map = FlatMap::with_capacity(access.size_hint().unwrap_or(0));
-            while let Some((key, value)) = access.next_entry()? {
-                map.insert(key, value);
+            let cmp = self.cmp;
+            let mut vec = Vec::with_capacity(access.size_hint().unwrap_or(0));
+            while let Some(entry) = access.next_entry()? {
+                vec.push(entry);
             }
-            Ok(map)
+            sort_dedup_last(&mut vec, |a, b| cmp.compare(a, b));
+            Ok(FlatMapBy::from_parts(T::from_vec(vec), cmp))
         }
     }
 
-    impl<'de, K: Ord, V> Deserialize<'de> for FlatMap<K, V>
+    impl<'de, K, V, C, T> Deserialize<'de> for FlatMapBy<K, V, C, T>
     where
         K: Deserialize<'de>,
         V: Deserialize<'de>,
+        C: Comparator<K> + Default,
+        T: Storage<(K, V)>,
     {
         fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
         where
             D: Deserializer<'de>,
         {
-            deserializer.deserialize_map(FlatMapVisitor::new())
+            deserializer.deserialize_map(FlatMapVisitor::new(C::default()))
+        }
+    }
+
+    impl<K, V, C, T> FlatMapBy<K, V, C, T>
+    where
+        C: Comparator<K>,
+        T: Storage<(K, V)>,
+    {
+        /// Deserializes a map ordered by `cmp`, for comparators without a
+        /// `Default`. As with `Deserialize`, later entries win on equal keys.
+        pub fn deserialize_with_comparator<'de, D>(
+            deserializer: D,
+            cmp: C,
+        ) -> Result<Self, D::Error>
+        where
+            D: Deserializer<'de>,
+            K: Deserialize<'de>,
+            V: Deserialize<'de>,
+        {
+            deserializer.deserialize_map(FlatMapVisitor::new(cmp))
         }
     }
 }
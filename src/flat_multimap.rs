@@ -0,0 +1,526 @@
+use crate::flat_map::EXTEND_INSERT_THRESHOLD;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::fmt;
+use core::fmt::Debug;
+use core::iter::FromIterator;
+use core::mem;
+use core::ops;
+use core::slice;
+
+/// A sorted map that can hold several values for the same key.
+///
+/// Keys and values live in two parallel vectors sorted by key, so the values
+/// of each key are contiguous and can be borrowed as a slice. Values for equal
+/// keys are kept in insertion order.
+///
+/// # Examples
+///
+/// ```
+/// use flat_map::FlatMultiMap;
+///
+/// let mut tags = FlatMultiMap::new();
+/// tags.insert("fruit", "apple");
+/// tags.insert("veg", "leek");
+/// tags.insert("fruit", "pear");
+/// assert_eq!(tags.count("fruit"), 2);
+/// assert!(tags.get_all("fruit").eq(&["apple", "pear"]));
+/// ```
+#[derive(Clone, Default, Hash, PartialEq, Eq)]
+pub struct FlatMultiMap<K, V> {
+    keys: Vec<K>,
+    values: Vec<V>,
+}
+
+pub struct Iter<'a, K: 'a, V: 'a> {
+    keys: slice::Iter<'a, K>,
+    values: slice::Iter<'a, V>,
+}
+
+pub struct IntoIter<K, V> {
+    keys: vec::IntoIter<K>,
+    values: vec::IntoIter<V>,
+}
+
+/// The values stored under one key, in insertion order.
+pub struct GetAll<'a, V: 'a> {
+    inner: slice::Iter<'a, V>,
+}
+
+/// Each distinct key once, in ascending order.
+pub struct KeysUnique<'a, K: 'a> {
+    keys: &'a [K],
+}
+
+/// Each distinct key together with the slice of its values.
+pub struct Groups<'a, K: 'a, V: 'a> {
+    keys: &'a [K],
+    values: &'a [V],
+}
+
+impl<K, V> FlatMultiMap<K, V> {
+    pub fn new() -> FlatMultiMap<K, V> {
+        FlatMultiMap {
+            keys: vec![],
+            values: vec![],
+        }
+    }
+
+    pub fn with_capacity(capacity: usize) -> FlatMultiMap<K, V> {
+        FlatMultiMap {
+            keys: Vec::with_capacity(capacity),
+            values: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// Returns the total number of values, counting every value of a
+    /// repeated key.
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.keys.clear();
+        self.values.clear();
+    }
+
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            keys: self.keys.iter(),
+            values: self.values.iter(),
+        }
+    }
+
+    /// Returns every key in order, repeated once per value.
+    pub fn keys(&self) -> slice::Iter<'_, K> {
+        self.keys.iter()
+    }
+
+    /// Returns every value, ordered by key and then by insertion.
+    pub fn values(&self) -> slice::Iter<'_, V> {
+        self.values.iter()
+    }
+
+    pub fn keys_unique(&self) -> KeysUnique<'_, K>
+    where
+        K: PartialEq,
+    {
+        KeysUnique { keys: &self.keys }
+    }
+
+    /// Iterates over each distinct key and the values stored under it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use flat_map::FlatMultiMap;
+    ///
+    /// let map: FlatMultiMap<_, _> = vec![(2, 'b'), (1, 'a'), (2, 'c')].into_iter().collect();
+    /// let groups: Vec<_> = map.groups().collect();
+    /// assert_eq!(groups, [(&1, &['a'][..]), (&2, &['b', 'c'][..])]);
+    /// ```
+    pub fn groups(&self) -> Groups<'_, K, V>
+    where
+        K: PartialEq,
+    {
+        Groups {
+            keys: &self.keys,
+            values: &self.values,
+        }
+    }
+}
+
+impl<K: Ord, V> FlatMultiMap<K, V> {
+    /// Adds a value under `key`, after any values already stored under an
+    /// equal key.
+    pub fn insert(&mut self, key: K, value: V) {
+        let i = self.keys.partition_point(|k| *k <= key);
+        // Reserve first so that a failed allocation cannot leave the key
+        // inserted without its value.
+        self.keys.reserve(1);
+        self.values.reserve(1);
+        self.keys.insert(i, key);
+        self.values.insert(i, value);
+    }
+
+    /// Returns the half-open range of positions holding entries for `q`. The
+    /// range is empty, and starts where `q` would be inserted, if there are
+    /// none.
+    pub fn equal_range<Q>(&self, q: &Q) -> ops::Range<usize>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let start = self.keys.partition_point(|k| k.borrow() < q);
+        let len = self.keys[start..].partition_point(|k| k.borrow() == q);
+        start..start + len
+    }
+
+    /// Returns the values stored under `q`, in insertion order.
+    pub fn get_all<Q>(&self, q: &Q) -> GetAll<'_, V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        GetAll {
+            inner: self.values[self.equal_range(q)].iter(),
+        }
+    }
+
+    /// Returns the first value stored under `q`, if any.
+    pub fn get<Q>(&self, q: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.get_all(q).next()
+    }
+
+    pub fn contains_key<Q>(&self, q: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.get(q).is_some()
+    }
+
+    /// Returns the number of values stored under `q`.
+    pub fn count<Q>(&self, q: &Q) -> usize
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.equal_range(q).len()
+    }
+
+    /// Removes every value stored under `q` and returns them in insertion
+    /// order.
+    pub fn remove_all<Q>(&mut self, q: &Q) -> Vec<V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let range = self.equal_range(q);
+        self.keys.drain(range.clone());
+        self.values.drain(range).collect()
+    }
+}
+
+impl<'a, V> GetAll<'a, V> {
+    /// Returns the remaining values as a slice.
+    pub fn as_slice(&self) -> &'a [V] {
+        self.inner.as_slice()
+    }
+}
+
+impl<'a, V> Iterator for GetAll<'a, V> {
+    type Item = &'a V;
+
+    fn next(&mut self) -> Option<&'a V> {
+        self.inner.next()
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, V> DoubleEndedIterator for GetAll<'a, V> {
+    fn next_back(&mut self) -> Option<&'a V> {
+        self.inner.next_back()
+    }
+}
+
+impl<'a, V> ExactSizeIterator for GetAll<'a, V> {}
+
+impl<'a, V> Clone for GetAll<'a, V> {
+    fn clone(&self) -> GetAll<'a, V> {
+        GetAll {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<'a, K: PartialEq> Iterator for KeysUnique<'a, K> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<&'a K> {
+        let first = self.keys.first()?;
+        let len = self.keys.iter().take_while(|k| *k == first).count();
+        self.keys = &self.keys[len..];
+        Some(first)
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.keys.len().min(1), Some(self.keys.len()))
+    }
+}
+
+impl<'a, K> Clone for KeysUnique<'a, K> {
+    fn clone(&self) -> KeysUnique<'a, K> {
+        KeysUnique { keys: self.keys }
+    }
+}
+
+impl<'a, K: PartialEq, V> Iterator for Groups<'a, K, V> {
+    type Item = (&'a K, &'a [V]);
+
+    fn next(&mut self) -> Option<(&'a K, &'a [V])> {
+        let first = self.keys.first()?;
+        let len = self.keys.iter().take_while(|k| *k == first).count();
+        let (values, rest) = self.values.split_at(len);
+        self.keys = &self.keys[len..];
+        self.values = rest;
+        Some((first, values))
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.keys.len().min(1), Some(self.keys.len()))
+    }
+}
+
+impl<'a, K, V> Clone for Groups<'a, K, V> {
+    fn clone(&self) -> Groups<'a, K, V> {
+        Groups {
+            keys: self.keys,
+            values: self.values,
+        }
+    }
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        Some((self.keys.next()?, self.values.next()?))
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.keys.size_hint()
+    }
+}
+
+impl<'a, K, V> DoubleEndedIterator for Iter<'a, K, V> {
+    fn next_back(&mut self) -> Option<(&'a K, &'a V)> {
+        Some((self.keys.next_back()?, self.values.next_back()?))
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for Iter<'a, K, V> {}
+
+impl<'a, K, V> Clone for Iter<'a, K, V> {
+    fn clone(&self) -> Iter<'a, K, V> {
+        Iter {
+            keys: self.keys.clone(),
+            values: self.values.clone(),
+        }
+    }
+}
+
+impl<K, V> Iterator for IntoIter<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<(K, V)> {
+        Some((self.keys.next()?, self.values.next()?))
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.keys.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for IntoIter<K, V> {
+    fn next_back(&mut self) -> Option<(K, V)> {
+        Some((self.keys.next_back()?, self.values.next_back()?))
+    }
+}
+
+impl<K, V> ExactSizeIterator for IntoIter<K, V> {}
+
+impl<K, V> IntoIterator for FlatMultiMap<K, V> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+
+    fn into_iter(self) -> IntoIter<K, V> {
+        IntoIter {
+            keys: self.keys.into_iter(),
+            values: self.values.into_iter(),
+        }
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a FlatMultiMap<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+impl<K: Ord, V> FromIterator<(K, V)> for FlatMultiMap<K, V> {
+    /// Builds a multimap from `iter`. Values for equal keys keep the order in
+    /// which they were yielded.
+    fn from_iter<T: IntoIterator<Item = (K, V)>>(iter: T) -> FlatMultiMap<K, V> {
+        let mut vec: Vec<_> = iter.into_iter().collect();
+        vec.sort_by(|kv1, kv2| kv1.0.cmp(&kv2.0));
+        let (keys, values) = vec.into_iter().unzip();
+        FlatMultiMap { keys, values }
+    }
+}
+
+impl<K: Ord, V> Extend<(K, V)> for FlatMultiMap<K, V> {
+    /// Adds every pair from `iter`, each after the values already stored
+    /// under an equal key and after earlier pairs with an equal key.
+    ///
+    /// Small batches are inserted one by one. Larger ones are collected,
+    /// stably sorted and spliced into the map in one pass, for a total of
+    /// O(n + m log m).
+    fn extend<T: IntoIterator<Item = (K, V)>>(&mut self, iter: T) {
+        let iter = iter.into_iter();
+        match iter.size_hint() {
+            (_, Some(upper)) if upper <= EXTEND_INSERT_THRESHOLD => {
+                for (k, v) in iter {
+                    self.insert(k, v);
+                }
+            }
+            _ => {
+                let mut batch: Vec<_> = iter.collect();
+                batch.sort_by(|kv1, kv2| kv1.0.cmp(&kv2.0));
+                let mut i = 0;
+                let batch = batch
+                    .into_iter()
+                    .map(|(k, v)| {
+                        i += self.keys[i..].partition_point(|e| *e <= k);
+                        (i, k, v)
+                    })
+                    .collect();
+                splice_columns(&mut self.keys, &mut self.values, batch);
+            }
+        }
+    }
+}
+
+// Moves each `(i, k, v)` of `batch` into the parallel `keys` and `values`,
+// before the entry that was at index `i`. The indices must not decrease.
+//
+// Nothing here compares keys, so short of a failed allocation it cannot
+// panic and leave the two vectors out of step.
+pub(crate) fn splice_columns<K, V>(
+    keys: &mut Vec<K>,
+    values: &mut Vec<V>,
+    batch: Vec<(usize, K, V)>,
+) {
+    if batch.is_empty() {
+        return;
+    }
+    let len = keys.len() + batch.len();
+    let mut new_keys = Vec::with_capacity(len);
+    let mut new_values = Vec::with_capacity(len);
+    let mut old_keys = mem::take(keys).into_iter();
+    let mut old_values = mem::take(values).into_iter();
+    let mut copied = 0;
+    for (i, k, v) in batch {
+        new_keys.extend(old_keys.by_ref().take(i - copied));
+        new_values.extend(old_values.by_ref().take(i - copied));
+        new_keys.push(k);
+        new_values.push(v);
+        copied = i;
+    }
+    new_keys.extend(old_keys);
+    new_values.extend(old_values);
+    *keys = new_keys;
+    *values = new_values;
+}
+
+impl<K: Debug, V: Debug> Debug for FlatMultiMap<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(feature = "serde1")]
+mod serde_impl {
+    // serialized as a map from each distinct key to the sequence of its
+    // values, so the output never repeats a key:
+    // { "k1": ["v1", "v2"], "k2": ["v3"] }
+
+    use super::FlatMultiMap;
+    use alloc::vec::Vec;
+    use core::fmt;
+    use core::marker::PhantomData;
+    use serde::de::{Deserialize, Deserializer, MapAccess, Visitor};
+    use serde::ser::SerializeMap;
+    use serde::{Serialize, Serializer};
+
+    impl<K, V> Serialize for FlatMultiMap<K, V>
+    where
+        K: PartialEq + Serialize,
+        V: Serialize,
+    {
+        fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: Serializer,
+        {
+            let mut map = serializer.serialize_map(Some(self.keys_unique().count()))?;
+            for (k, values) in self.groups() {
+                map.serialize_entry(k, values)?;
+            }
+            map.end()
+        }
+    }
+
+    struct FlatMultiMapVisitor<K, V> {
+        marker: PhantomData<fn() -> FlatMultiMap<K, V>>,
+    }
+
+    impl<K, V> FlatMultiMapVisitor<K, V> {
+        fn new() -> Self {
+            FlatMultiMapVisitor {
+                marker: PhantomData,
+            }
+        }
+    }
+
+    impl<'de, K, V> Visitor<'de> for FlatMultiMapVisitor<K, V>
+    where
+        K: Ord + Clone + Deserialize<'de>,
+        V: Deserialize<'de>,
+    {
+        type Value = FlatMultiMap<K, V>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a flat_multimap")
+        }
+
+        fn visit_map<M>(self, mut access: M) -> Result<Self::Value, M::Error>
+        where
+            M: MapAccess<'de>,
+        {
+            // Each key is stored once per value, so it is cloned for all but
+            // the last of its values.
+            let mut pairs = Vec::with_capacity(access.size_hint().unwrap_or(0));
+            while let Some((key, values)) = access.next_entry::<K, Vec<V>>()? {
+                let mut values = values.into_iter();
+                if let Some(last) = values.next_back() {
+                    pairs.extend(values.map(|value| (key.clone(), value)));
+                    pairs.push((key, last));
+                }
+            }
+            Ok(pairs.into_iter().collect())
+        }
+    }
+
+    impl<'de, K, V> Deserialize<'de> for FlatMultiMap<K, V>
+    where
+        K: Ord + Clone + Deserialize<'de>,
+        V: Deserialize<'de>,
+    {
+        fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            deserializer.deserialize_map(FlatMultiMapVisitor::new())
+        }
+    }
+}
@@ -0,0 +1,749 @@
+use crate::flat_map::{self, Entry, FlatMap, Keys, OnEqual, EXTEND_INSERT_THRESHOLD};
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::cmp;
+use core::cmp::Ordering;
+use core::fmt;
+use core::fmt::Debug;
+use core::iter::FromIterator;
+use core::ops::{BitAnd, BitOr, BitXor, RangeBounds, Sub};
+
+/// A set stored as a sorted vector of values.
+///
+/// This is a thin wrapper around `FlatMap<T, ()>`, so it shares the map's
+/// binary search, iterators and memory layout.
+#[derive(Clone, Default, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FlatSet<T> {
+    map: FlatMap<T, ()>,
+}
+
+pub struct Iter<'a, T: 'a> {
+    inner: Keys<'a, T, ()>,
+}
+
+pub struct IntoIter<T> {
+    inner: flat_map::IntoIter<T, ()>,
+}
+
+pub struct Range<'a, T: 'a> {
+    inner: flat_map::Range<'a, T, ()>,
+}
+
+pub struct Union<'a, T: 'a> {
+    a: &'a [(T, ())],
+    b: &'a [(T, ())],
+}
+
+pub struct Intersection<'a, T: 'a> {
+    small: &'a [(T, ())],
+    large: &'a [(T, ())],
+    gallop: bool,
+}
+
+pub struct Difference<'a, T: 'a> {
+    a: &'a [(T, ())],
+    b: &'a [(T, ())],
+}
+
+pub struct SymmetricDifference<'a, T: 'a> {
+    a: &'a [(T, ())],
+    b: &'a [(T, ())],
+}
+
+// Once one side of an intersection is this many times larger than the other,
+// we gallop through the larger side instead of stepping through it.
+const GALLOP_RATIO: usize = 16;
+
+impl<T> FlatSet<T> {
+    pub fn new() -> FlatSet<T> {
+        FlatSet {
+            map: FlatMap::new(),
+        }
+    }
+
+    pub fn with_capacity(capacity: usize) -> FlatSet<T> {
+        FlatSet {
+            map: FlatMap::with_capacity(capacity),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.map.capacity()
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        self.map.reserve(additional)
+    }
+
+    pub fn reserve_exact(&mut self, additional: usize) {
+        self.map.reserve_exact(additional)
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        self.map.shrink_to_fit()
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            inner: self.map.keys(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.map.clear()
+    }
+
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&T) -> bool,
+    {
+        self.map.retain(|t, _| f(t))
+    }
+
+    /// Returns the smallest value in the set, if any.
+    pub fn first(&self) -> Option<&T> {
+        self.map.first_key_value().map(|(t, _)| t)
+    }
+
+    /// Returns the largest value in the set, if any.
+    pub fn last(&self) -> Option<&T> {
+        self.map.last_key_value().map(|(t, _)| t)
+    }
+
+    /// Removes and returns the smallest value in the set, if any.
+    pub fn pop_first(&mut self) -> Option<T> {
+        self.map.pop_first().map(|(t, _)| t)
+    }
+
+    /// Removes and returns the largest value in the set, if any.
+    pub fn pop_last(&mut self) -> Option<T> {
+        self.map.pop_last().map(|(t, _)| t)
+    }
+}
+
+impl<T: Ord> FlatSet<T> {
+    /// Adds a value to the set, returning whether it was newly inserted.
+    ///
+    /// If the set already held an equal value it is left untouched.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use flat_map::FlatSet;
+    ///
+    /// let mut set = FlatSet::new();
+    /// assert!(set.insert(2));
+    /// assert!(!set.insert(2));
+    /// assert_eq!(set.len(), 1);
+    /// ```
+    pub fn insert(&mut self, value: T) -> bool {
+        match self.map.entry(value) {
+            Entry::Occupied(_) => false,
+            Entry::Vacant(entry) => {
+                entry.insert(());
+                true
+            }
+        }
+    }
+
+    /// Adds a value to the set, replacing and returning an equal value that
+    /// was already present.
+    pub fn replace(&mut self, value: T) -> Option<T> {
+        self.map.replace_key(value, ())
+    }
+
+    pub fn contains<Q>(&self, value: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.map.contains_key(value)
+    }
+
+    /// Returns a reference to the value in the set equal to `value`, if any.
+    pub fn get<Q>(&self, value: &Q) -> Option<&T>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.map.get_key_value(value).map(|(t, _)| t)
+    }
+
+    /// Removes a value from the set, returning whether it was present.
+    pub fn remove<Q>(&mut self, value: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.map.remove(value).is_some()
+    }
+
+    /// Removes and returns the value in the set equal to `value`, if any.
+    pub fn take<Q>(&mut self, value: &Q) -> Option<T>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.map.remove_entry(value).map(|(t, _)| t)
+    }
+
+    /// Returns a double-ended iterator over the values that fall in `range`.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as `FlatMap::range`.
+    pub fn range<Q, R>(&self, range: R) -> Range<'_, T>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        Range {
+            inner: self.map.range(range),
+        }
+    }
+
+    /// Returns true if every value in `self` is also in `other`.
+    ///
+    /// Both sets are walked once in order, so this is O(n + m).
+    pub fn is_subset(&self, other: &FlatSet<T>) -> bool {
+        if self.len() > other.len() {
+            return false;
+        }
+        let mut theirs = other.iter();
+        self.iter()
+            .all(|x| theirs.by_ref().find(|y| *y >= x) == Some(x))
+    }
+
+    /// Returns true if every value in `other` is also in `self`.
+    pub fn is_superset(&self, other: &FlatSet<T>) -> bool {
+        other.is_subset(self)
+    }
+
+    /// Returns true if `self` and `other` have no values in common.
+    pub fn is_disjoint(&self, other: &FlatSet<T>) -> bool {
+        self.intersection(other).next().is_none()
+    }
+
+    /// Returns a lazy iterator over the values in `self` or `other`, in
+    /// ascending order and without duplicates.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use flat_map::FlatSet;
+    ///
+    /// let a: FlatSet<_> = vec![1, 2].into_iter().collect();
+    /// let b: FlatSet<_> = vec![2, 3].into_iter().collect();
+    /// let v: Vec<_> = a.union(&b).cloned().collect();
+    /// assert_eq!(v, [1, 2, 3]);
+    /// ```
+    pub fn union<'a>(&'a self, other: &'a FlatSet<T>) -> Union<'a, T> {
+        Union {
+            a: self.map.as_slice(),
+            b: other.map.as_slice(),
+        }
+    }
+
+    /// Returns a lazy iterator over the values in both `self` and `other`, in
+    /// ascending order.
+    ///
+    /// When one set is much smaller than the other, each of its values is
+    /// located in the larger set by exponential search, so the cost is
+    /// O(n log(m / n)) rather than O(n + m).
+    pub fn intersection<'a>(&'a self, other: &'a FlatSet<T>) -> Intersection<'a, T> {
+        let (a, b) = (self.map.as_slice(), other.map.as_slice());
+        let (small, large) = if a.len() <= b.len() { (a, b) } else { (b, a) };
+        Intersection {
+            small,
+            large,
+            gallop: small.len() < large.len() / GALLOP_RATIO,
+        }
+    }
+
+    /// Returns a lazy iterator over the values in `self` but not in `other`,
+    /// in ascending order.
+    pub fn difference<'a>(&'a self, other: &'a FlatSet<T>) -> Difference<'a, T> {
+        Difference {
+            a: self.map.as_slice(),
+            b: other.map.as_slice(),
+        }
+    }
+
+    /// Returns a lazy iterator over the values in exactly one of `self` and
+    /// `other`, in ascending order.
+    pub fn symmetric_difference<'a>(&'a self, other: &'a FlatSet<T>) -> SymmetricDifference<'a, T> {
+        SymmetricDifference {
+            a: self.map.as_slice(),
+            b: other.map.as_slice(),
+        }
+    }
+
+    pub fn append(&mut self, other: &mut Self) {
+        self.map.append(&mut other.map)
+    }
+
+    /// Splits the set in two at `value`, which need not be present. Returns
+    /// everything at or after `value`.
+    pub fn split_off<Q>(&mut self, value: &Q) -> Self
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        FlatSet {
+            map: self.map.split_off(value),
+        }
+    }
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        self.inner.next()
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, T> Clone for Iter<'a, T> {
+    fn clone(&self) -> Iter<'a, T> {
+        Iter {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<'a, T> DoubleEndedIterator for Iter<'a, T> {
+    fn next_back(&mut self) -> Option<&'a T> {
+        self.inner.next_back()
+    }
+}
+
+impl<'a, T> ExactSizeIterator for Iter<'a, T> {}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.inner.next().map(|(t, _)| t)
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<T> DoubleEndedIterator for IntoIter<T> {
+    fn next_back(&mut self) -> Option<T> {
+        self.inner.next_back().map(|(t, _)| t)
+    }
+}
+
+impl<T> ExactSizeIterator for IntoIter<T> {}
+
+impl<'a, T> Iterator for Range<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        self.inner.next().map(|(t, _)| t)
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, T> Clone for Range<'a, T> {
+    fn clone(&self) -> Range<'a, T> {
+        Range {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<'a, T> DoubleEndedIterator for Range<'a, T> {
+    fn next_back(&mut self) -> Option<&'a T> {
+        self.inner.next_back().map(|(t, _)| t)
+    }
+}
+
+impl<'a, T> ExactSizeIterator for Range<'a, T> {}
+
+// Returns the index of the first value in `s` that is not less than `x`,
+// probing at exponentially growing distances from the front.
+fn gallop<T: Ord>(s: &[(T, ())], x: &T) -> usize {
+    let mut bound = 1;
+    while bound < s.len() && s[bound].0 < *x {
+        bound *= 2;
+    }
+    let lo = bound / 2;
+    let hi = cmp::min(bound + 1, s.len());
+    lo + s[lo..hi].partition_point(|(t, _)| t < x)
+}
+
+impl<'a, T: Ord> Iterator for Union<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        match (self.a.split_first(), self.b.split_first()) {
+            (None, None) => None,
+            (Some((x, a)), None) => {
+                self.a = a;
+                Some(&x.0)
+            }
+            (None, Some((y, b))) => {
+                self.b = b;
+                Some(&y.0)
+            }
+            (Some((x, a)), Some((y, b))) => match x.0.cmp(&y.0) {
+                Ordering::Less => {
+                    self.a = a;
+                    Some(&x.0)
+                }
+                Ordering::Greater => {
+                    self.b = b;
+                    Some(&y.0)
+                }
+                Ordering::Equal => {
+                    self.a = a;
+                    self.b = b;
+                    Some(&x.0)
+                }
+            },
+        }
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (a, b) = (self.a.len(), self.b.len());
+        (cmp::max(a, b), Some(a + b))
+    }
+}
+
+impl<'a, T> Clone for Union<'a, T> {
+    fn clone(&self) -> Union<'a, T> {
+        Union {
+            a: self.a,
+            b: self.b,
+        }
+    }
+}
+
+impl<'a, T: Ord> Iterator for Intersection<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        loop {
+            let (x, small) = self.small.split_first()?;
+            if self.gallop {
+                self.small = small;
+                self.large = &self.large[gallop(self.large, &x.0)..];
+                let (y, large) = self.large.split_first()?;
+                if x.0 == y.0 {
+                    self.large = large;
+                    return Some(&x.0);
+                }
+            } else {
+                let (y, large) = self.large.split_first()?;
+                match x.0.cmp(&y.0) {
+                    Ordering::Less => self.small = small,
+                    Ordering::Greater => self.large = large,
+                    Ordering::Equal => {
+                        self.small = small;
+                        self.large = large;
+                        return Some(&x.0);
+                    }
+                }
+            }
+        }
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.small.len()))
+    }
+}
+
+impl<'a, T> Clone for Intersection<'a, T> {
+    fn clone(&self) -> Intersection<'a, T> {
+        Intersection {
+            small: self.small,
+            large: self.large,
+            gallop: self.gallop,
+        }
+    }
+}
+
+impl<'a, T: Ord> Iterator for Difference<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        loop {
+            let (x, a) = self.a.split_first()?;
+            match self.b.split_first() {
+                None => {
+                    self.a = a;
+                    return Some(&x.0);
+                }
+                Some((y, b)) => match x.0.cmp(&y.0) {
+                    Ordering::Less => {
+                        self.a = a;
+                        return Some(&x.0);
+                    }
+                    Ordering::Greater => self.b = b,
+                    Ordering::Equal => {
+                        self.a = a;
+                        self.b = b;
+                    }
+                },
+            }
+        }
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (a, b) = (self.a.len(), self.b.len());
+        (a.saturating_sub(b), Some(a))
+    }
+}
+
+impl<'a, T> Clone for Difference<'a, T> {
+    fn clone(&self) -> Difference<'a, T> {
+        Difference {
+            a: self.a,
+            b: self.b,
+        }
+    }
+}
+
+impl<'a, T: Ord> Iterator for SymmetricDifference<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        loop {
+            match (self.a.split_first(), self.b.split_first()) {
+                (None, None) => return None,
+                (Some((x, a)), None) => {
+                    self.a = a;
+                    return Some(&x.0);
+                }
+                (None, Some((y, b))) => {
+                    self.b = b;
+                    return Some(&y.0);
+                }
+                (Some((x, a)), Some((y, b))) => match x.0.cmp(&y.0) {
+                    Ordering::Less => {
+                        self.a = a;
+                        return Some(&x.0);
+                    }
+                    Ordering::Greater => {
+                        self.b = b;
+                        return Some(&y.0);
+                    }
+                    Ordering::Equal => {
+                        self.a = a;
+                        self.b = b;
+                    }
+                },
+            }
+        }
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.a.len() + self.b.len()))
+    }
+}
+
+impl<'a, T> Clone for SymmetricDifference<'a, T> {
+    fn clone(&self) -> SymmetricDifference<'a, T> {
+        SymmetricDifference {
+            a: self.a,
+            b: self.b,
+        }
+    }
+}
+
+impl<T> IntoIterator for FlatSet<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter {
+            inner: self.map.into_iter(),
+        }
+    }
+}
+
+impl<'a, T> IntoIterator for &'a FlatSet<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<T: Ord> FromIterator<T> for FlatSet<T> {
+    /// Builds a set from `iter`. Of several equal values, the last is kept,
+    /// as with `BTreeSet` and `FlatMap`.
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> FlatSet<T> {
+        FlatSet {
+            map: iter.into_iter().map(|t| (t, ())).collect(),
+        }
+    }
+}
+
+impl<T: Ord> Extend<T> for FlatSet<T> {
+    /// Adds every value from `iter` that is not in the set yet. As with
+    /// `insert`, a value already in the set is left untouched.
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        let iter = iter.into_iter();
+        match iter.size_hint() {
+            (_, Some(upper)) if upper <= EXTEND_INSERT_THRESHOLD => {
+                for t in iter {
+                    self.insert(t);
+                }
+            }
+            _ => self.map.merge_batch(iter.map(|t| (t, ())), OnEqual::Keep),
+        }
+    }
+}
+
+impl<'a, T: Ord + Copy> Extend<&'a T> for FlatSet<T> {
+    fn extend<I: IntoIterator<Item = &'a T>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().cloned());
+    }
+}
+
+impl<T: Clone> FlatSet<T> {
+    // Clones the values of a set operation into a new set. They already come
+    // in strictly ascending order, so there is nothing to sort.
+    fn from_ascending<'a, I: Iterator<Item = &'a T>>(iter: I) -> FlatSet<T>
+    where
+        T: 'a,
+    {
+        let v: Vec<_> = iter.map(|t| (t.clone(), ())).collect();
+        // Safe: the set operations yield each value once, in ascending order.
+        FlatSet {
+            map: unsafe { FlatMap::from_sorted_vec_unchecked(v) },
+        }
+    }
+}
+
+impl<T: Ord + Clone> BitOr<&FlatSet<T>> for &FlatSet<T> {
+    type Output = FlatSet<T>;
+
+    /// Returns the union of `self` and `rhs` as a new set.
+    fn bitor(self, rhs: &FlatSet<T>) -> FlatSet<T> {
+        FlatSet::from_ascending(self.union(rhs))
+    }
+}
+
+impl<T: Ord + Clone> BitAnd<&FlatSet<T>> for &FlatSet<T> {
+    type Output = FlatSet<T>;
+
+    /// Returns the intersection of `self` and `rhs` as a new set.
+    fn bitand(self, rhs: &FlatSet<T>) -> FlatSet<T> {
+        FlatSet::from_ascending(self.intersection(rhs))
+    }
+}
+
+impl<T: Ord + Clone> Sub<&FlatSet<T>> for &FlatSet<T> {
+    type Output = FlatSet<T>;
+
+    /// Returns the difference of `self` and `rhs` as a new set.
+    fn sub(self, rhs: &FlatSet<T>) -> FlatSet<T> {
+        FlatSet::from_ascending(self.difference(rhs))
+    }
+}
+
+impl<T: Ord + Clone> BitXor<&FlatSet<T>> for &FlatSet<T> {
+    type Output = FlatSet<T>;
+
+    /// Returns the symmetric difference of `self` and `rhs` as a new set.
+    fn bitxor(self, rhs: &FlatSet<T>) -> FlatSet<T> {
+        FlatSet::from_ascending(self.symmetric_difference(rhs))
+    }
+}
+
+impl<T: Debug> Debug for FlatSet<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(feature = "serde1")]
+mod serde_impl {
+    // serialized as a plain sequence of values, like BTreeSet
+
+    use super::FlatSet;
+    use alloc::vec::Vec;
+    use core::fmt;
+    use core::marker::PhantomData;
+    use serde::de::{Deserialize, Deserializer, SeqAccess, Visitor};
+    use serde::ser::SerializeSeq;
+    use serde::{Serialize, Serializer};
+
+    impl<T> Serialize for FlatSet<T>
+    where
+        T: Serialize,
+    {
+        fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: Serializer,
+        {
+            let mut seq = serializer.serialize_seq(Some(self.len()))?;
+            for t in self {
+                seq.serialize_element(t)?;
+            }
+            seq.end()
+        }
+    }
+
+    struct FlatSetVisitor<T> {
+        marker: PhantomData<fn() -> FlatSet<T>>,
+    }
+
+    impl<T> FlatSetVisitor<T> {
+        fn new() -> Self {
+            FlatSetVisitor {
+                marker: PhantomData,
+            }
+        }
+    }
+
+    impl<'de, T: Ord> Visitor<'de> for FlatSetVisitor<T>
+    where
+        T: Deserialize<'de>,
+    {
+        type Value = FlatSet<T>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a flat_set")
+        }
+
+        fn visit_seq<A>(self, mut access: A) -> Result<Self::Value, A::Error>
+        where
+            A: SeqAccess<'de>,
+        {
+            let mut vec = Vec::with_capacity(access.size_hint().unwrap_or(0));
+            while let Some(value) = access.next_element()? {
+                vec.push(value);
+            }
+            Ok(vec.into_iter().collect())
+        }
+    }
+
+    impl<'de, T: Ord> Deserialize<'de> for FlatSet<T>
+    where
+        T: Deserialize<'de>,
+    {
+        fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            deserializer.deserialize_seq(FlatSetVisitor::new())
+        }
+    }
+}
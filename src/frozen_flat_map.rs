@@ -0,0 +1,429 @@
+use crate::compare::Natural;
+use crate::flat_map::FlatMap;
+use crate::sorted::check_range;
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::fmt;
+use core::fmt::Debug;
+use core::ops::{Bound, Index, RangeBounds};
+use core::ptr;
+
+/// A read-only map laid out for fast lookups, built with `FlatMap::freeze`.
+///
+/// The entries are stored in Eytzinger order: the array is an implicit,
+/// complete binary search tree in which the children of the node at 1-based
+/// position `k` sit at `2k` and `2k + 1`. A search walks down from the root
+/// without branching on the comparison, and the top levels of the tree stay
+/// hot in cache, so lookups in maps larger than the cache beat a plain binary
+/// search. Iteration is still in ascending key order.
+///
+/// # Examples
+///
+/// ```
+/// use flat_map::FlatMap;
+///
+/// let map: FlatMap<_, _> = (0..100).map(|i| (i, i * i)).collect();
+/// let frozen = map.freeze();
+/// assert_eq!(frozen.get(&7), Some(&49));
+/// assert!(frozen.range(3..6).map(|(&k, _)| k).eq(3..6));
+/// let map = frozen.thaw();
+/// assert_eq!(map.len(), 100);
+/// ```
+#[derive(Clone)]
+pub struct FrozenFlatMap<K, V> {
+    v: Vec<(K, V)>,
+}
+
+/// An in-order iterator over a `FrozenFlatMap`.
+pub struct Iter<'a, K: 'a, V: 'a> {
+    v: &'a [(K, V)],
+    front: usize,
+    back: usize,
+    len: usize,
+}
+
+/// An in-order iterator over a sub-range of a `FrozenFlatMap`.
+pub struct Range<'a, K: 'a, V: 'a> {
+    v: &'a [(K, V)],
+    front: usize,
+    back: usize,
+}
+
+impl<K, V> FlatMap<K, V> {
+    /// Re-lays the entries in Eytzinger order for read-mostly use. This is
+    /// O(n) and does not reallocate more than once.
+    pub fn freeze(self) -> FrozenFlatMap<K, V> {
+        let sorted = self.into_inner();
+        let n = sorted.len();
+        let mut v: Vec<(K, V)> = Vec::with_capacity(n);
+        let mut k = first(n);
+        unsafe {
+            // Safe: walking the tree in order visits each of the `n` slots
+            // exactly once, so every slot is written before `set_len`.
+            for entry in sorted {
+                ptr::write(v.as_mut_ptr().add(k - 1), entry);
+                k = next(k, n);
+            }
+            v.set_len(n);
+        }
+        FrozenFlatMap { v }
+    }
+}
+
+impl<K, V> FrozenFlatMap<K, V> {
+    /// Converts back into a `FlatMap`, restoring sorted order in O(n).
+    pub fn thaw(self) -> FlatMap<K, V> {
+        let mut v = self.v;
+        let n = v.len();
+        let mut sorted = Vec::with_capacity(n);
+        unsafe {
+            // Safe: the entries are moved out in order, each exactly once,
+            // and `v` forgets them first so they cannot be dropped twice.
+            // Nothing in between can panic.
+            v.set_len(0);
+            let mut k = first(n);
+            while k != 0 {
+                sorted.push(ptr::read(v.as_ptr().add(k - 1)));
+                k = next(k, n);
+            }
+            FlatMap::from_sorted_vec_unchecked(sorted)
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.v.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.v.is_empty()
+    }
+
+    /// Iterates over the entries in ascending key order.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        let n = self.v.len();
+        Iter {
+            v: &self.v,
+            front: first(n),
+            back: last(n),
+            len: n,
+        }
+    }
+
+    /// Returns the entry with the smallest key, if any.
+    pub fn first_key_value(&self) -> Option<(&K, &V)> {
+        self.entry_at(first(self.v.len()))
+    }
+
+    /// Returns the entry with the largest key, if any.
+    pub fn last_key_value(&self) -> Option<(&K, &V)> {
+        self.entry_at(last(self.v.len()))
+    }
+
+    fn entry_at(&self, k: usize) -> Option<(&K, &V)> {
+        match k {
+            0 => None,
+            k => {
+                let (ref key, ref value) = self.v[k - 1];
+                Some((key, value))
+            }
+        }
+    }
+
+    // Returns the 1-based position of the first entry for which `below`
+    // is false, or 0 if there is none. `below` must be true for a prefix of
+    // the entries in key order.
+    fn lower_bound<F>(&self, below: F) -> usize
+    where
+        F: Fn(&K) -> bool,
+    {
+        let v = &self.v[..];
+        let mut k = 1;
+        while k <= v.len() {
+            prefetch(v.as_ptr().wrapping_add(k * PREFETCH_STRIDE - 1));
+            k = 2 * k + below(&v[k - 1].0) as usize;
+        }
+        // The set bits of `k` record the path taken, one per level, with a 1
+        // for each step right. The last left step was at the answer, so drop
+        // the trailing right steps and that left step.
+        k >> (k.trailing_ones() + 1)
+    }
+}
+
+impl<K: Ord, V> FrozenFlatMap<K, V> {
+    pub fn get<Q>(&self, q: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.get_key_value(q).map(|(_, v)| v)
+    }
+
+    pub fn get_key_value<Q>(&self, q: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let k = self.lower_bound(|key| key.borrow() < q);
+        self.entry_at(k).filter(|(key, _)| (*key).borrow() == q)
+    }
+
+    pub fn contains_key<Q>(&self, q: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.get(q).is_some()
+    }
+
+    /// Returns a double-ended iterator over the entries whose keys fall in
+    /// `range`, in ascending order.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the start of the range is greater than its end, or if both
+    /// ends are equal and excluded.
+    pub fn range<Q, R>(&self, range: R) -> Range<'_, K, V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        check_range(&Natural, &range);
+        let n = self.v.len();
+        let front = match range.start_bound() {
+            Bound::Unbounded => first(n),
+            Bound::Included(q) => self.lower_bound(|key| key.borrow() < q),
+            Bound::Excluded(q) => self.lower_bound(|key| key.borrow() <= q),
+        };
+        // The last entry in range is the one before the first entry past it.
+        let past = match range.end_bound() {
+            Bound::Unbounded => 0,
+            Bound::Included(q) => self.lower_bound(|key| key.borrow() <= q),
+            Bound::Excluded(q) => self.lower_bound(|key| key.borrow() < q),
+        };
+        let back = match past {
+            0 => last(n),
+            k => prev(k, n),
+        };
+        let empty = match (self.entry_at(front), self.entry_at(back)) {
+            (Some((a, _)), Some((b, _))) => a > b,
+            _ => true,
+        };
+        if empty {
+            Range {
+                v: &self.v,
+                front: 0,
+                back: 0,
+            }
+        } else {
+            Range {
+                v: &self.v,
+                front,
+                back,
+            }
+        }
+    }
+}
+
+// How far ahead to prefetch: the descendants of `k` four levels down start
+// at `16k`, so while one comparison is in flight the cache line holding
+// those 16 nodes is already on its way.
+const PREFETCH_STRIDE: usize = 16;
+
+#[inline(always)]
+fn prefetch<T>(p: *const T) {
+    #[cfg(all(target_arch = "x86_64", target_feature = "sse"))]
+    unsafe {
+        use core::arch::x86_64::{_mm_prefetch, _MM_HINT_T0};
+        _mm_prefetch::<_MM_HINT_T0>(p as *const i8);
+    }
+    #[cfg(not(all(target_arch = "x86_64", target_feature = "sse")))]
+    let _ = p;
+}
+
+// In-order navigation over an Eytzinger array of `n` entries, using 1-based
+// positions and 0 for "none".
+
+fn first(n: usize) -> usize {
+    if n == 0 {
+        return 0;
+    }
+    let mut k = 1;
+    while 2 * k <= n {
+        k *= 2;
+    }
+    k
+}
+
+fn last(n: usize) -> usize {
+    if n == 0 {
+        return 0;
+    }
+    let mut k = 1;
+    while 2 * k < n {
+        k = 2 * k + 1;
+    }
+    k
+}
+
+fn next(mut k: usize, n: usize) -> usize {
+    if 2 * k < n {
+        // Leftmost node of the right subtree.
+        k = 2 * k + 1;
+        while 2 * k <= n {
+            k *= 2;
+        }
+        k
+    } else {
+        // Climb while we are a right child, then once more.
+        k >> (k.trailing_ones() + 1)
+    }
+}
+
+fn prev(mut k: usize, n: usize) -> usize {
+    if 2 * k <= n {
+        // Rightmost node of the left subtree.
+        k *= 2;
+        while 2 * k < n {
+            k = 2 * k + 1;
+        }
+        k
+    } else {
+        // Climb while we are a left child, then once more.
+        k >> (k.trailing_zeros() + 1)
+    }
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        if self.len == 0 {
+            return None;
+        }
+        self.len -= 1;
+        let (ref k, ref v) = self.v[self.front - 1];
+        self.front = next(self.front, self.v.len());
+        Some((k, v))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<'a, K, V> DoubleEndedIterator for Iter<'a, K, V> {
+    fn next_back(&mut self) -> Option<(&'a K, &'a V)> {
+        if self.len == 0 {
+            return None;
+        }
+        self.len -= 1;
+        let (ref k, ref v) = self.v[self.back - 1];
+        self.back = prev(self.back, self.v.len());
+        Some((k, v))
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for Iter<'a, K, V> {}
+
+impl<'a, K, V> Clone for Iter<'a, K, V> {
+    fn clone(&self) -> Iter<'a, K, V> {
+        Iter { ..*self }
+    }
+}
+
+impl<'a, K, V> Iterator for Range<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        if self.front == 0 {
+            return None;
+        }
+        let (ref k, ref v) = self.v[self.front - 1];
+        if self.front == self.back {
+            self.front = 0;
+            self.back = 0;
+        } else {
+            self.front = next(self.front, self.v.len());
+        }
+        Some((k, v))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self.front {
+            0 => (0, Some(0)),
+            _ => (1, Some(self.v.len())),
+        }
+    }
+}
+
+impl<'a, K, V> DoubleEndedIterator for Range<'a, K, V> {
+    fn next_back(&mut self) -> Option<(&'a K, &'a V)> {
+        if self.back == 0 {
+            return None;
+        }
+        let (ref k, ref v) = self.v[self.back - 1];
+        if self.front == self.back {
+            self.front = 0;
+            self.back = 0;
+        } else {
+            self.back = prev(self.back, self.v.len());
+        }
+        Some((k, v))
+    }
+}
+
+impl<'a, K, V> Clone for Range<'a, K, V> {
+    fn clone(&self) -> Range<'a, K, V> {
+        Range { ..*self }
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a FrozenFlatMap<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+impl<K, V> From<FlatMap<K, V>> for FrozenFlatMap<K, V> {
+    fn from(map: FlatMap<K, V>) -> FrozenFlatMap<K, V> {
+        map.freeze()
+    }
+}
+
+impl<K, V> From<FrozenFlatMap<K, V>> for FlatMap<K, V> {
+    fn from(map: FrozenFlatMap<K, V>) -> FlatMap<K, V> {
+        map.thaw()
+    }
+}
+
+impl<K: PartialEq, V: PartialEq> PartialEq for FrozenFlatMap<K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        // Equal lengths mean equal shapes, so comparing the arrays compares
+        // the entries in order.
+        self.v == other.v
+    }
+}
+
+impl<K: Eq, V: Eq> Eq for FrozenFlatMap<K, V> {}
+
+impl<K: Debug, V: Debug> Debug for FrozenFlatMap<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K, Q: ?Sized, V> Index<&Q> for FrozenFlatMap<K, V>
+where
+    K: Ord + Borrow<Q>,
+    Q: Ord,
+{
+    type Output = V;
+
+    fn index(&self, key: &Q) -> &V {
+        self.get(key).expect("no entry found for key")
+    }
+}
@@ -0,0 +1,261 @@
+use crate::compare::{Comparator, Natural};
+use crate::flat_map::{FlatMapBy, Iter, Keys, Range, Values};
+use crate::numeric::NumericKey;
+use crate::sorted::search;
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::cmp::Ordering;
+use core::fmt;
+use core::fmt::Debug;
+use core::ops::{Index, RangeBounds};
+
+/// A read-only map of numeric keys with a learned index over its sorted
+/// entries, built with `FlatMapBy::freeze_learned`.
+///
+/// The index is a piecewise-linear model of key position, in the style of a
+/// PGM index: each segment predicts where a key sits to within `epsilon`
+/// entries, so a lookup is a search over the few segments followed by a
+/// binary search of a window of `2 * epsilon + 3` entries. Keys that follow
+/// a few straight lines, such as ids or timestamps, need only a handful of
+/// segments.
+///
+/// Every window is checked against its neighbours before it is searched, and
+/// the lookup falls back to a full binary search if the model missed, so
+/// results always match those of the underlying map.
+///
+/// # Examples
+///
+/// ```
+/// use flat_map::FlatMap;
+///
+/// let map: FlatMap<u64, u64> = (0..10_000).map(|i| (i * 10, i)).collect();
+/// let frozen = map.freeze_learned(8);
+/// assert_eq!(frozen.segments(), 1);
+/// assert_eq!(frozen.get(&5000), Some(&500));
+/// assert_eq!(frozen.search(&5001), Err(501));
+/// ```
+#[derive(Clone)]
+pub struct LearnedFlatMap<K, V, C = Natural> {
+    map: FlatMapBy<K, V, C>,
+    segments: Vec<Segment>,
+    epsilon: usize,
+}
+
+// A line through (`x`, `start`) predicting the position of every key from
+// `x` up to the next segment's `x`.
+#[derive(Clone, Copy, Debug)]
+struct Segment {
+    x: f64,
+    start: usize,
+    slope: f64,
+}
+
+impl<K: NumericKey, V, C> FlatMapBy<K, V, C> {
+    /// Freezes the map behind a learned index whose predictions are off by
+    /// at most `epsilon` entries. A larger `epsilon` needs fewer segments but
+    /// searches a wider window. Building the index is a single O(n) pass.
+    pub fn freeze_learned(self, epsilon: usize) -> LearnedFlatMap<K, V, C> {
+        let segments = fit_segments(self.as_slice().iter().map(|(k, _)| k.to_f64()), epsilon);
+        LearnedFlatMap {
+            map: self,
+            segments,
+            epsilon,
+        }
+    }
+}
+
+// Fits the fewest segments it can in one pass with the "shrinking cone"
+// method: a segment keeps the range of slopes that would still predict
+// every point seen so far to within `epsilon`, and a new segment starts at
+// the first point that would leave the range empty. Keys that do not map to
+// a finite number end the current segment and are left uncovered.
+fn fit_segments<I: Iterator<Item = f64>>(xs: I, epsilon: usize) -> Vec<Segment> {
+    fn close(segments: &mut Vec<Segment>, x: f64, start: usize, min: f64, max: f64) {
+        let slope = if max.is_finite() {
+            (min + max) / 2.0
+        } else {
+            min
+        };
+        segments.push(Segment { x, start, slope });
+    }
+
+    let eps = epsilon as f64;
+    let mut segments = Vec::new();
+    // The open segment: its origin and the range of slopes still allowed.
+    let mut open: Option<(f64, usize, f64, f64)> = None;
+    for (i, x) in xs.enumerate() {
+        if let Some((x0, i0, min, max)) = open {
+            let dx = x - x0;
+            let di = (i - i0) as f64;
+            let (lo, hi) = if dx > 0.0 {
+                (min.max((di - eps) / dx), max.min((di + eps) / dx))
+            } else if dx == 0.0 && di <= eps {
+                (min, max)
+            } else {
+                (1.0, 0.0)
+            };
+            if lo <= hi && x.is_finite() {
+                open = Some((x0, i0, lo, hi));
+                continue;
+            }
+            close(&mut segments, x0, i0, min, max);
+            open = None;
+        }
+        if x.is_finite() {
+            open = Some((x, i, 0.0, f64::INFINITY));
+        }
+    }
+    if let Some((x0, i0, min, max)) = open {
+        close(&mut segments, x0, i0, min, max);
+    }
+    segments
+}
+
+impl<K, V, C> LearnedFlatMap<K, V, C> {
+    /// Drops the index and returns the underlying map.
+    pub fn thaw(self) -> FlatMapBy<K, V, C> {
+        self.map
+    }
+
+    /// Returns the most entries a prediction may be off by.
+    pub fn epsilon(&self) -> usize {
+        self.epsilon
+    }
+
+    /// Returns the number of linear segments in the index.
+    pub fn segments(&self) -> usize {
+        self.segments.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        self.map.iter()
+    }
+
+    pub fn keys(&self) -> Keys<'_, K, V> {
+        self.map.keys()
+    }
+
+    pub fn values(&self) -> Values<'_, K, V> {
+        self.map.values()
+    }
+
+    /// Returns the entry with the smallest key, if any.
+    pub fn first_key_value(&self) -> Option<(&K, &V)> {
+        self.map.first_key_value()
+    }
+
+    /// Returns the entry with the largest key, if any.
+    pub fn last_key_value(&self) -> Option<(&K, &V)> {
+        self.map.last_key_value()
+    }
+}
+
+impl<K: NumericKey, V, C: Comparator<K>> LearnedFlatMap<K, V, C> {
+    /// Finds `key` through the learned index, returning `Ok` with its
+    /// position or `Err` with the position it would be inserted at, exactly
+    /// as a binary search would.
+    pub fn search(&self, key: &K) -> Result<usize, usize> {
+        let v = self.map.as_slice();
+        let cmp = self.map.comparator();
+        if let Some((lo, hi)) = self.window(key.to_f64(), v.len()) {
+            let below = lo == 0 || cmp.compare(&v[lo - 1].0, key) == Ordering::Less;
+            let above = hi == v.len() || cmp.compare(&v[hi].0, key) == Ordering::Greater;
+            if below && above {
+                return match search(&v[lo..hi], cmp, key) {
+                    Ok(i) => Ok(lo + i),
+                    Err(i) => Err(lo + i),
+                };
+            }
+        }
+        search(v, cmp, key)
+    }
+
+    // Returns the span of positions the model says `key` must fall in.
+    fn window(&self, x: f64, len: usize) -> Option<(usize, usize)> {
+        if !x.is_finite() || self.segments.is_empty() {
+            return None;
+        }
+        let s = self.segments.partition_point(|s| s.x <= x).max(1);
+        let seg = self.segments[s - 1];
+        // `as` saturates, so a prediction before the start is 0. One extra
+        // entry on each side absorbs rounding, and one more on the right
+        // covers a missing key whose insertion point follows its
+        // predecessor's position.
+        let predicted = (seg.start as f64 + seg.slope * (x - seg.x)) as usize;
+        let lo = predicted.saturating_sub(self.epsilon + 1).min(len);
+        let hi = predicted.saturating_add(self.epsilon + 2).min(len).max(lo);
+        Some((lo, hi))
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.get_key_value(key).map(|(_, v)| v)
+    }
+
+    pub fn get_key_value(&self, key: &K) -> Option<(&K, &V)> {
+        match self.search(key) {
+            Err(_) => None,
+            Ok(i) => {
+                let (ref k, ref v) = self.map.as_slice()[i];
+                Some((k, v))
+            }
+        }
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.search(key).is_ok()
+    }
+
+    /// Returns a double-ended iterator over the entries whose keys fall in
+    /// `range`. The ends are found by binary search, as with `FlatMap`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the start of the range is greater than its end, or if both
+    /// ends are equal and excluded.
+    pub fn range<Q, R>(&self, range: R) -> Range<'_, K, V>
+    where
+        K: Borrow<Q>,
+        C: Comparator<Q>,
+        Q: ?Sized,
+        R: RangeBounds<Q>,
+    {
+        self.map.range(range)
+    }
+}
+
+impl<K, V, C> From<LearnedFlatMap<K, V, C>> for FlatMapBy<K, V, C> {
+    fn from(map: LearnedFlatMap<K, V, C>) -> FlatMapBy<K, V, C> {
+        map.thaw()
+    }
+}
+
+impl<'a, K, V, C> IntoIterator for &'a LearnedFlatMap<K, V, C> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+impl<K: Debug, V: Debug, C> Debug for LearnedFlatMap<K, V, C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K: NumericKey, V, C: Comparator<K>> Index<&K> for LearnedFlatMap<K, V, C> {
+    type Output = V;
+
+    fn index(&self, key: &K) -> &V {
+        self.get(key).expect("no entry found for key")
+    }
+}
@@ -1,19 +1,58 @@
-#![cfg_attr(not(feature = "std"), no_std)]
-#![cfg_attr(not(feature = "std"), feature(alloc))]
+#![no_std]
 
-#[cfg(feature = "serde1")]
-extern crate serde;
+#[cfg(feature = "std")]
+extern crate std;
 
-#[cfg(not(feature = "std"))]
-#[macro_use]
-pub extern crate alloc;
+#[cfg(feature = "alloc")]
+extern crate alloc;
 
-#[cfg(not(feature = "std"))]
-mod std {
-    pub use alloc::*;
-    pub use core::{borrow, cmp, fmt, hash, iter, marker, mem, ops, slice};
-}
+#[cfg(feature = "serde1")]
+extern crate serde;
+#[cfg(feature = "smallvec")]
+extern crate smallvec;
 
+pub mod array_flat_map;
+#[cfg(feature = "alloc")]
+pub mod buffered_flat_map;
+pub mod compare;
+#[cfg(feature = "alloc")]
 pub mod flat_map;
+#[cfg(feature = "alloc")]
+pub mod flat_multimap;
+#[cfg(feature = "alloc")]
+pub mod flat_set;
+#[cfg(feature = "alloc")]
+pub mod frozen_flat_map;
+#[cfg(feature = "alloc")]
+pub mod learned_flat_map;
+pub mod numeric;
+mod primitive;
+#[cfg(feature = "alloc")]
+pub mod soa_flat_map;
+mod sorted;
+#[cfg(feature = "alloc")]
+pub mod storage;
+pub use crate::array_flat_map::ArrayFlatMap;
+#[cfg(feature = "alloc")]
+pub use crate::buffered_flat_map::BufferedFlatMap;
+pub use crate::compare::{Comparator, Natural, Primitive, Reversed};
+#[cfg(feature = "alloc")]
 pub use crate::flat_map::Entry::*;
-pub use crate::flat_map::FlatMap;
+#[cfg(feature = "smallvec")]
+pub use crate::flat_map::SmallFlatMap;
+#[cfg(feature = "alloc")]
+pub use crate::flat_map::{BoxedFlatMap, FlatMap, FlatMapBuilder, FlatMapBy};
+#[cfg(feature = "alloc")]
+pub use crate::flat_multimap::FlatMultiMap;
+#[cfg(feature = "alloc")]
+pub use crate::flat_set::FlatSet;
+#[cfg(feature = "alloc")]
+pub use crate::frozen_flat_map::FrozenFlatMap;
+#[cfg(feature = "alloc")]
+pub use crate::learned_flat_map::LearnedFlatMap;
+pub use crate::numeric::NumericKey;
+pub use crate::primitive::PrimitiveKey;
+#[cfg(feature = "alloc")]
+pub use crate::soa_flat_map::SoaFlatMap;
+#[cfg(feature = "alloc")]
+pub use crate::storage::Storage;
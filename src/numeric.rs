@@ -0,0 +1,82 @@
+#[cfg(feature = "alloc")]
+use crate::compare::Comparator;
+#[cfg(feature = "alloc")]
+use core::cmp::Ordering;
+
+/// A key that can be placed on the real line, letting a search guess where
+/// in the map it sits instead of always bisecting.
+///
+/// `to_f64` should be non-decreasing in the order the map sorts its keys by.
+/// It only steers the search: every decision is still made by the map's
+/// comparator, so results are exactly those of a binary search even when
+/// the mapping is lossy (as it is for 64-bit integers) or not monotone at
+/// all. A poor mapping only costs probes.
+///
+/// Implemented for the primitive integers, and for `f32` and `f64` as
+/// ordered by `total_cmp`.
+pub trait NumericKey {
+    fn to_f64(&self) -> f64;
+}
+
+macro_rules! impl_numeric_key {
+    ($($t:ty)*) => {$(
+        impl NumericKey for $t {
+            #[inline]
+            fn to_f64(&self) -> f64 {
+                *self as f64
+            }
+        }
+    )*};
+}
+
+impl_numeric_key!(u8 u16 u32 u64 u128 usize i8 i16 i32 i64 i128 isize f32 f64);
+
+// Searches the sorted `v` for `q` like `binary_search_by`, choosing probes
+// by linear interpolation between the keys at either end of the remaining
+// range. Whenever a probe fails to at least halve the range the next probe
+// bisects, so the worst case stays within twice the probes of a binary
+// search while near-uniform keys are found in O(log log n).
+#[cfg(feature = "alloc")]
+pub(crate) fn interpolation_search<T, K, C, F>(
+    v: &[T],
+    key_of: F,
+    cmp: &C,
+    q: &K,
+) -> Result<usize, usize>
+where
+    K: NumericKey,
+    C: Comparator<K>,
+    F: Fn(&T) -> &K,
+{
+    let x = q.to_f64();
+    let (mut lo, mut hi) = (0, v.len());
+    let mut bisect = false;
+    while lo < hi {
+        let len = hi - lo;
+        let mid = if bisect {
+            lo + len / 2
+        } else {
+            lo + estimate(key_of(&v[lo]).to_f64(), key_of(&v[hi - 1]).to_f64(), x, len)
+        };
+        match cmp.compare(key_of(&v[mid]), q) {
+            Ordering::Less => lo = mid + 1,
+            Ordering::Greater => hi = mid,
+            Ordering::Equal => return Ok(mid),
+        }
+        bisect = !bisect && hi - lo > len / 2;
+    }
+    Err(lo)
+}
+
+// Guesses the offset of `x` among `len` keys spread linearly from `first` to
+// `last`, falling back to the middle when the guess is meaningless.
+#[cfg(feature = "alloc")]
+fn estimate(first: f64, last: f64, x: f64, len: usize) -> usize {
+    let t = (x - first) / (last - first);
+    if t.is_nan() {
+        return len / 2;
+    }
+    // `as` saturates, and NaN has been ruled out above.
+    let i = (t * (len - 1) as f64) as usize;
+    i.min(len - 1)
+}
@@ -0,0 +1,248 @@
+// SIMD scans for maps keyed by primitive integers.
+//
+// Every search in `sorted` bisects with a branchless binary search, which
+// compiles to conditional moves when the comparison is cheap. Maps ordered
+// by `Primitive` can instead count short runs of `u16`, `u32` and `u64` keys
+// with SIMD comparisons, when the `simd` feature is enabled on x86_64 or
+// aarch64 and the target enables the instructions.
+//
+// The kernels reach the keys through the same projection as the binary
+// search, copying each group into a local array before loading it, so they
+// never reinterpret the map's memory. When the keys are laid out back to
+// back the copies fold into plain vector loads.
+
+use self::sealed::Sealed;
+
+// Maps with at most this many keys are scanned rather than bisected when a
+// SIMD kernel is available.
+const LINEAR_SEARCH_MAX: usize = 64;
+
+/// A primitive integer key, which maps ordered by `Primitive` may search
+/// with SIMD comparisons.
+///
+/// This trait is sealed. It is implemented for the primitive integer types,
+/// and `Primitive` orders them exactly as their `Ord` impls do.
+pub trait PrimitiveKey: Copy + Ord + Sealed {}
+
+mod sealed {
+    pub trait Sealed: Copy + Ord {
+        // Counts the items whose key is below `q` with SIMD comparisons, if
+        // there is a kernel for this type on this target.
+        #[inline(always)]
+        fn simd_count_less<T, F>(_items: &[T], _key: F, _q: Self) -> Option<usize>
+        where
+            F: Fn(&T) -> &Self,
+        {
+            None
+        }
+    }
+}
+
+// Counts the items whose key is below `q`, or returns `None` if the search
+// should bisect instead.
+#[inline(always)]
+pub(crate) fn count_less<K, T, F>(items: &[T], key: F, q: K) -> Option<usize>
+where
+    K: PrimitiveKey,
+    F: Fn(&T) -> &K,
+{
+    if items.len() <= LINEAR_SEARCH_MAX {
+        K::simd_count_less(items, key, q)
+    } else {
+        None
+    }
+}
+
+macro_rules! impl_primitive_key {
+    ($($t:ty)*) => {$(
+        impl PrimitiveKey for $t {}
+    )*};
+}
+
+impl_primitive_key!(u8 u16 u32 u64 u128 usize i8 i16 i32 i64 i128 isize);
+
+impl Sealed for u8 {}
+impl Sealed for u128 {}
+impl Sealed for usize {}
+impl Sealed for i8 {}
+impl Sealed for i16 {}
+impl Sealed for i32 {}
+impl Sealed for i64 {}
+impl Sealed for i128 {}
+impl Sealed for isize {}
+
+impl Sealed for u16 {
+    #[cfg(any(
+        all(feature = "simd", target_arch = "x86_64", target_feature = "sse2"),
+        all(feature = "simd", target_arch = "aarch64", target_feature = "neon")
+    ))]
+    #[inline(always)]
+    fn simd_count_less<T, F>(items: &[T], key: F, q: u16) -> Option<usize>
+    where
+        F: Fn(&T) -> &u16,
+    {
+        Some(simd::count_less_u16(items, key, q))
+    }
+}
+
+impl Sealed for u32 {
+    #[cfg(any(
+        all(feature = "simd", target_arch = "x86_64", target_feature = "sse2"),
+        all(feature = "simd", target_arch = "aarch64", target_feature = "neon")
+    ))]
+    #[inline(always)]
+    fn simd_count_less<T, F>(items: &[T], key: F, q: u32) -> Option<usize>
+    where
+        F: Fn(&T) -> &u32,
+    {
+        Some(simd::count_less_u32(items, key, q))
+    }
+}
+
+impl Sealed for u64 {
+    #[cfg(any(
+        all(feature = "simd", target_arch = "x86_64", target_feature = "sse4.2"),
+        all(feature = "simd", target_arch = "aarch64", target_feature = "neon")
+    ))]
+    #[inline(always)]
+    fn simd_count_less<T, F>(items: &[T], key: F, q: u64) -> Option<usize>
+    where
+        F: Fn(&T) -> &u64,
+    {
+        Some(simd::count_less_u64(items, key, q))
+    }
+}
+
+// The scalar equivalent of the SIMD kernels, for the tail that does not fill
+// a whole vector.
+#[cfg(feature = "simd")]
+#[allow(dead_code)]
+#[inline(always)]
+fn scalar_count_less<K: PrimitiveKey, T, F: Fn(&T) -> &K>(items: &[T], key: F, q: K) -> usize {
+    items.iter().filter(|item| *key(item) < q).count()
+}
+
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+mod simd {
+    use super::scalar_count_less;
+    use core::arch::x86_64::*;
+    use core::array;
+
+    // SSE2 only has signed comparisons, so both sides are biased by the
+    // sign bit first, which maps unsigned order onto signed order.
+    //
+    // Safe: each kernel is only compiled when the target enables the
+    // instructions it uses, and loads only from its own local array.
+
+    #[cfg(target_feature = "sse2")]
+    pub fn count_less_u16<T, F: Fn(&T) -> &u16>(items: &[T], key: F, q: u16) -> usize {
+        let chunks = items.chunks_exact(8);
+        let rest = chunks.remainder();
+        let mut count = 0;
+        unsafe {
+            let bias = _mm_set1_epi16(i16::MIN);
+            let qv = _mm_xor_si128(_mm_set1_epi16(q as i16), bias);
+            for c in chunks {
+                let k: [u16; 8] = array::from_fn(|i| *key(&c[i]));
+                let k = _mm_xor_si128(_mm_loadu_si128(k.as_ptr() as *const __m128i), bias);
+                // Two mask bits per 16-bit lane.
+                count += _mm_movemask_epi8(_mm_cmplt_epi16(k, qv)).count_ones() as usize / 2;
+            }
+        }
+        count + scalar_count_less(rest, key, q)
+    }
+
+    #[cfg(target_feature = "sse2")]
+    pub fn count_less_u32<T, F: Fn(&T) -> &u32>(items: &[T], key: F, q: u32) -> usize {
+        let chunks = items.chunks_exact(4);
+        let rest = chunks.remainder();
+        let mut count = 0;
+        unsafe {
+            let bias = _mm_set1_epi32(i32::MIN);
+            let qv = _mm_xor_si128(_mm_set1_epi32(q as i32), bias);
+            for c in chunks {
+                let k: [u32; 4] = array::from_fn(|i| *key(&c[i]));
+                let k = _mm_xor_si128(_mm_loadu_si128(k.as_ptr() as *const __m128i), bias);
+                let lt = _mm_castsi128_ps(_mm_cmplt_epi32(k, qv));
+                count += _mm_movemask_ps(lt).count_ones() as usize;
+            }
+        }
+        count + scalar_count_less(rest, key, q)
+    }
+
+    #[cfg(target_feature = "sse4.2")]
+    pub fn count_less_u64<T, F: Fn(&T) -> &u64>(items: &[T], key: F, q: u64) -> usize {
+        let chunks = items.chunks_exact(2);
+        let rest = chunks.remainder();
+        let mut count = 0;
+        unsafe {
+            let bias = _mm_set1_epi64x(i64::MIN);
+            let qv = _mm_xor_si128(_mm_set1_epi64x(q as i64), bias);
+            for c in chunks {
+                let k: [u64; 2] = array::from_fn(|i| *key(&c[i]));
+                let k = _mm_xor_si128(_mm_loadu_si128(k.as_ptr() as *const __m128i), bias);
+                let lt = _mm_castsi128_pd(_mm_cmpgt_epi64(qv, k));
+                count += _mm_movemask_pd(lt).count_ones() as usize;
+            }
+        }
+        count + scalar_count_less(rest, key, q)
+    }
+}
+
+#[cfg(all(feature = "simd", target_arch = "aarch64", target_feature = "neon"))]
+mod simd {
+    use super::scalar_count_less;
+    use core::arch::aarch64::*;
+    use core::array;
+
+    // Each lane of a comparison is all ones or all zeros, so shifting the
+    // top bit down and summing the lanes counts the matches.
+    //
+    // Safe: this module is only compiled when the target enables NEON, and
+    // each kernel loads only from its own local array.
+
+    pub fn count_less_u16<T, F: Fn(&T) -> &u16>(items: &[T], key: F, q: u16) -> usize {
+        let chunks = items.chunks_exact(8);
+        let rest = chunks.remainder();
+        let mut count = 0;
+        unsafe {
+            let qv = vdupq_n_u16(q);
+            for c in chunks {
+                let k: [u16; 8] = array::from_fn(|i| *key(&c[i]));
+                let lt = vcltq_u16(vld1q_u16(k.as_ptr()), qv);
+                count += vaddvq_u16(vshrq_n_u16::<15>(lt)) as usize;
+            }
+        }
+        count + scalar_count_less(rest, key, q)
+    }
+
+    pub fn count_less_u32<T, F: Fn(&T) -> &u32>(items: &[T], key: F, q: u32) -> usize {
+        let chunks = items.chunks_exact(4);
+        let rest = chunks.remainder();
+        let mut count = 0;
+        unsafe {
+            let qv = vdupq_n_u32(q);
+            for c in chunks {
+                let k: [u32; 4] = array::from_fn(|i| *key(&c[i]));
+                let lt = vcltq_u32(vld1q_u32(k.as_ptr()), qv);
+                count += vaddvq_u32(vshrq_n_u32::<31>(lt)) as usize;
+            }
+        }
+        count + scalar_count_less(rest, key, q)
+    }
+
+    pub fn count_less_u64<T, F: Fn(&T) -> &u64>(items: &[T], key: F, q: u64) -> usize {
+        let chunks = items.chunks_exact(2);
+        let rest = chunks.remainder();
+        let mut count = 0;
+        unsafe {
+            let qv = vdupq_n_u64(q);
+            for c in chunks {
+                let k: [u64; 2] = array::from_fn(|i| *key(&c[i]));
+                let lt = vcltq_u64(vld1q_u64(k.as_ptr()), qv);
+                count += vaddvq_u64(vshrq_n_u64::<63>(lt)) as usize;
+            }
+        }
+        count + scalar_count_less(rest, key, q)
+    }
+}
@@ -0,0 +1,814 @@
+use self::Entry::*;
+use crate::compare::Natural;
+use crate::flat_map::{
+    sort_dedup_last, DuplicateKeys, FlatMap, NotSorted, EXTEND_INSERT_THRESHOLD,
+};
+use crate::flat_multimap::splice_columns;
+use crate::sorted::{key_range_indices, search_keys};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::borrow::{Borrow, BorrowMut};
+use core::cmp::Ordering;
+use core::fmt;
+use core::fmt::Debug;
+use core::iter::FromIterator;
+use core::mem::{swap, take};
+use core::ops::{Index, IndexMut, RangeBounds};
+use core::slice;
+
+/// A sorted map that keeps its keys and values in two separate vectors.
+///
+/// Lookups binary search a contiguous `Vec<K>` and only touch the value they
+/// find, so large values do not crowd the keys out of cache. Both halves can
+/// be borrowed as plain slices with `keys` and `values_mut`.
+///
+/// This map covers the lookup, insertion, removal, range and entry API of
+/// `FlatMap`, but not all of it. Keys are always ordered by their `Ord`
+/// impl, since there is no comparator parameter, and the columns are always
+/// `Vec`s. The cursor, positional and bound-search methods are missing too.
+/// A `SoaFlatMap` converts to and from a `FlatMap` with `From` when those are
+/// needed.
+///
+/// # Examples
+///
+/// ```
+/// use flat_map::SoaFlatMap;
+///
+/// let mut map = SoaFlatMap::new();
+/// map.insert(3, [0u8; 200]);
+/// map.insert(1, [1u8; 200]);
+/// assert_eq!(map.keys(), &[1, 3]);
+/// for value in map.values_mut() {
+///     value[0] = 9;
+/// }
+/// assert_eq!(map[&3][0], 9);
+/// ```
+#[derive(Clone, Hash, PartialEq, Eq)]
+pub struct SoaFlatMap<K, V> {
+    keys: Vec<K>,
+    values: Vec<V>,
+}
+
+pub enum Entry<'a, K: 'a, V: 'a> {
+    Vacant(VacantEntry<'a, K, V>),
+    Occupied(OccupiedEntry<'a, K, V>),
+}
+
+pub struct VacantEntry<'a, K: 'a, V: 'a> {
+    map: &'a mut SoaFlatMap<K, V>,
+    key: K,
+    index: usize,
+}
+
+pub struct OccupiedEntry<'a, K: 'a, V: 'a> {
+    map: &'a mut SoaFlatMap<K, V>,
+    index: usize,
+}
+
+pub struct Iter<'a, K: 'a, V: 'a> {
+    keys: slice::Iter<'a, K>,
+    values: slice::Iter<'a, V>,
+}
+
+pub struct IterMut<'a, K: 'a, V: 'a> {
+    keys: slice::Iter<'a, K>,
+    values: slice::IterMut<'a, V>,
+}
+
+pub struct IntoIter<K, V> {
+    keys: vec::IntoIter<K>,
+    values: vec::IntoIter<V>,
+}
+
+impl<K, V> SoaFlatMap<K, V> {
+    pub fn new() -> SoaFlatMap<K, V> {
+        SoaFlatMap {
+            keys: Vec::new(),
+            values: Vec::new(),
+        }
+    }
+
+    pub fn with_capacity(capacity: usize) -> SoaFlatMap<K, V> {
+        SoaFlatMap {
+            keys: Vec::with_capacity(capacity),
+            values: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// Returns the number of entries the map can hold without reallocating
+    /// either vector.
+    pub fn capacity(&self) -> usize {
+        self.keys.capacity().min(self.values.capacity())
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        self.keys.reserve(additional);
+        self.values.reserve(additional);
+    }
+
+    pub fn reserve_exact(&mut self, additional: usize) {
+        self.keys.reserve_exact(additional);
+        self.values.reserve_exact(additional);
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        self.keys.shrink_to_fit();
+        self.values.shrink_to_fit();
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            keys: self.keys.iter(),
+            values: self.values.iter(),
+        }
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        IterMut {
+            keys: self.keys.iter(),
+            values: self.values.iter_mut(),
+        }
+    }
+
+    /// Returns the keys in ascending order.
+    pub fn keys(&self) -> &[K] {
+        &self.keys
+    }
+
+    /// Returns the values, ordered by their keys.
+    pub fn values(&self) -> &[V] {
+        &self.values
+    }
+
+    /// Returns the values, ordered by their keys, as a mutable slice.
+    pub fn values_mut(&mut self) -> &mut [V] {
+        &mut self.values
+    }
+
+    pub fn clear(&mut self) {
+        self.keys.clear();
+        self.values.clear();
+    }
+
+    /// Returns the key and value vectors. This is the inverse of
+    /// `from_sorted_parts`.
+    pub fn into_parts(self) -> (Vec<K>, Vec<V>) {
+        (self.keys, self.values)
+    }
+
+    /// Splits the map in two at the given position. Returns the entries from
+    /// index `at` onwards, leaving the first `at` entries in `self`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `at > len`.
+    pub fn split_off_index(&mut self, at: usize) -> Self {
+        SoaFlatMap {
+            keys: self.keys.split_off(at),
+            values: self.values.split_off(at),
+        }
+    }
+
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&K, &V) -> bool,
+    {
+        // Kept entries are swapped down over removed ones as we go, so
+        // `[..i - deleted]` is kept, `[i - deleted..i]` is waiting to be
+        // dropped and `[i..]` is unvisited. The guard drops the middle run
+        // even if `f` panics, so the two vectors stay the same length and
+        // sorted.
+        struct Retain<'a, K: 'a, V: 'a> {
+            keys: &'a mut Vec<K>,
+            values: &'a mut Vec<V>,
+            i: usize,
+            deleted: usize,
+        }
+
+        impl<'a, K, V> Drop for Retain<'a, K, V> {
+            fn drop(&mut self) {
+                let removed = self.i - self.deleted..self.i;
+                self.keys.drain(removed.clone());
+                self.values.drain(removed);
+            }
+        }
+
+        let len = self.keys.len();
+        let mut r = Retain {
+            keys: &mut self.keys,
+            values: &mut self.values,
+            i: 0,
+            deleted: 0,
+        };
+        while r.i < len {
+            if !f(&r.keys[r.i], &r.values[r.i]) {
+                r.deleted += 1;
+            } else if r.deleted > 0 {
+                r.keys.swap(r.i - r.deleted, r.i);
+                r.values.swap(r.i - r.deleted, r.i);
+            }
+            r.i += 1;
+        }
+    }
+
+    /// Returns the entry with the smallest key, if any.
+    pub fn first_key_value(&self) -> Option<(&K, &V)> {
+        Some((self.keys.first()?, self.values.first()?))
+    }
+
+    /// Returns the entry with the largest key, if any.
+    pub fn last_key_value(&self) -> Option<(&K, &V)> {
+        Some((self.keys.last()?, self.values.last()?))
+    }
+
+    /// Removes and returns the entry with the smallest key, if any.
+    ///
+    /// This shifts every remaining entry down by one, so it is O(n).
+    pub fn pop_first(&mut self) -> Option<(K, V)> {
+        if self.keys.is_empty() {
+            None
+        } else {
+            Some(self.remove_at(0))
+        }
+    }
+
+    /// Removes and returns the entry with the largest key, if any, in O(1).
+    pub fn pop_last(&mut self) -> Option<(K, V)> {
+        Some((self.keys.pop()?, self.values.pop()?))
+    }
+
+    /// Returns the entry with the smallest key for in-place manipulation.
+    pub fn first_entry(&mut self) -> Option<OccupiedEntry<'_, K, V>> {
+        if self.keys.is_empty() {
+            None
+        } else {
+            Some(OccupiedEntry {
+                map: self,
+                index: 0,
+            })
+        }
+    }
+
+    /// Returns the entry with the largest key for in-place manipulation.
+    pub fn last_entry(&mut self) -> Option<OccupiedEntry<'_, K, V>> {
+        match self.keys.len() {
+            0 => None,
+            len => Some(OccupiedEntry {
+                map: self,
+                index: len - 1,
+            }),
+        }
+    }
+
+    fn insert_at(&mut self, index: usize, key: K, value: V) {
+        // Reserve first so that a failed allocation cannot leave the key
+        // inserted without its value.
+        self.values.reserve(1);
+        self.keys.insert(index, key);
+        self.values.insert(index, value);
+    }
+
+    fn remove_at(&mut self, index: usize) -> (K, V) {
+        (self.keys.remove(index), self.values.remove(index))
+    }
+}
+
+impl<K: Ord, V> SoaFlatMap<K, V> {
+    /// Wraps key and value vectors whose keys are already in strictly
+    /// ascending order, which is checked in O(n).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the vectors have different lengths.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use flat_map::SoaFlatMap;
+    ///
+    /// let map = SoaFlatMap::from_sorted_parts(vec![1, 2], vec!['a', 'b']).unwrap();
+    /// assert_eq!(map[&2], 'b');
+    /// let err = SoaFlatMap::from_sorted_parts(vec![2, 1], vec!['a', 'b']).unwrap_err();
+    /// assert_eq!(err.index(), 1);
+    /// ```
+    pub fn from_sorted_parts(keys: Vec<K>, values: Vec<V>) -> Result<Self, NotSorted> {
+        assert_eq!(
+            keys.len(),
+            values.len(),
+            "keys and values have different lengths"
+        );
+        match keys.windows(2).position(|w| w[0] >= w[1]) {
+            Some(i) => Err(NotSorted { index: i + 1 }),
+            None => Ok(SoaFlatMap { keys, values }),
+        }
+    }
+
+    /// Builds a map from `iter`, combining the values of duplicate keys with
+    /// `merge(key, old, new)`. See `FlatMap::from_iter_merge`.
+    pub fn from_iter_merge<I, F>(iter: I, merge: F) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        F: FnMut(&K, V, V) -> V,
+    {
+        FlatMap::from_iter_merge(iter, merge).into()
+    }
+
+    /// Builds a map from `iter`, failing if any key occurs more than once.
+    /// See `FlatMap::try_from_iter`.
+    pub fn try_from_iter<I>(iter: I) -> Result<Self, DuplicateKeys<K>>
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        FlatMap::try_from_iter(iter).map(SoaFlatMap::from)
+    }
+
+    pub fn insert(&mut self, key: K, mut v: V) -> Option<V> {
+        match self.search(&key) {
+            Err(i) => {
+                self.insert_at(i, key, v);
+                None
+            }
+            Ok(i) => {
+                swap(&mut self.values[i], &mut v);
+                Some(v)
+            }
+        }
+    }
+
+    /// Moves all elements from `other` into `self`, leaving `other` empty.
+    ///
+    /// If a key is present in both maps, the value from `other` wins.
+    pub fn append(&mut self, other: &mut Self) {
+        let mut map: FlatMap<K, V> = take(self).into();
+        map.append(&mut take(other).into());
+        *self = map.into();
+    }
+
+    /// Splits the map in two at the given key. Returns everything at or
+    /// after `key`, leaving everything before it in `self`.
+    pub fn split_off<Q>(&mut self, q: &Q) -> Self
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let at = self.search(q).unwrap_or_else(|i| i);
+        self.split_off_index(at)
+    }
+
+    pub fn get<Q>(&self, q: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.search(q).ok().map(|i| &self.values[i])
+    }
+
+    /// Returns the key-value pair corresponding to the supplied key.
+    pub fn get_key_value<Q>(&self, q: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.search(q)
+            .ok()
+            .map(|i| (&self.keys[i], &self.values[i]))
+    }
+
+    pub fn contains_key<Q>(&self, q: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.search(q).is_ok()
+    }
+
+    pub fn get_mut<Q>(&mut self, q: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        match self.search(q) {
+            Err(_) => None,
+            Ok(i) => Some(&mut self.values[i]),
+        }
+    }
+
+    /// Returns a double-ended iterator over the entries whose keys fall in
+    /// `range`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the start of the range is greater than its end, or if both
+    /// ends are equal and excluded.
+    pub fn range<Q, R>(&self, range: R) -> Iter<'_, K, V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        let (start, end) = key_range_indices(&self.keys, &Natural, &range);
+        Iter {
+            keys: self.keys[start..end].iter(),
+            values: self.values[start..end].iter(),
+        }
+    }
+
+    /// Returns a double-ended iterator over the entries whose keys fall in
+    /// `range`, with mutable references to the values.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as `range`.
+    pub fn range_mut<Q, R>(&mut self, range: R) -> IterMut<'_, K, V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        let (start, end) = key_range_indices(&self.keys, &Natural, &range);
+        IterMut {
+            keys: self.keys[start..end].iter(),
+            values: self.values[start..end].iter_mut(),
+        }
+    }
+
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V> {
+        match self.search(&key) {
+            Err(index) => Vacant(VacantEntry {
+                map: self,
+                key,
+                index,
+            }),
+            Ok(index) => Occupied(OccupiedEntry { map: self, index }),
+        }
+    }
+
+    pub fn remove<Q>(&mut self, q: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.remove_entry(q).map(|(_, v)| v)
+    }
+
+    /// Removes a key from the map, returning the stored key and value if the
+    /// key was previously in the map.
+    pub fn remove_entry<Q>(&mut self, q: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        match self.search(q) {
+            Err(_) => None,
+            Ok(i) => Some(self.remove_at(i)),
+        }
+    }
+
+    fn search<Q>(&self, q: &Q) -> Result<usize, usize>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        search_keys(&self.keys, &Natural, q)
+    }
+}
+
+impl<'a, K, V> Entry<'a, K, V> {
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        match self {
+            Occupied(entry) => entry.into_mut(),
+            Vacant(entry) => entry.insert(default),
+        }
+    }
+
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Occupied(entry) => entry.into_mut(),
+            Vacant(entry) => entry.insert(default()),
+        }
+    }
+}
+
+impl<'a, K, V> VacantEntry<'a, K, V> {
+    pub fn insert(self, value: V) -> &'a mut V {
+        self.map.insert_at(self.index, self.key, value);
+        &mut self.map.values[self.index]
+    }
+}
+
+impl<'a, K, V> OccupiedEntry<'a, K, V> {
+    pub fn key(&self) -> &K {
+        &self.map.keys[self.index]
+    }
+
+    pub fn get(&self) -> &V {
+        &self.map.values[self.index]
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        &mut self.map.values[self.index]
+    }
+
+    pub fn into_mut(self) -> &'a mut V {
+        &mut self.map.values[self.index]
+    }
+
+    pub fn insert(&mut self, mut value: V) -> V {
+        swap(self.get_mut(), &mut value);
+        value
+    }
+
+    pub fn remove(self) -> V {
+        self.map.remove_at(self.index).1
+    }
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        Some((self.keys.next()?, self.values.next()?))
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.keys.size_hint()
+    }
+}
+
+impl<'a, K, V> DoubleEndedIterator for Iter<'a, K, V> {
+    fn next_back(&mut self) -> Option<(&'a K, &'a V)> {
+        Some((self.keys.next_back()?, self.values.next_back()?))
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for Iter<'a, K, V> {}
+
+impl<'a, K, V> Clone for Iter<'a, K, V> {
+    fn clone(&self) -> Iter<'a, K, V> {
+        Iter {
+            keys: self.keys.clone(),
+            values: self.values.clone(),
+        }
+    }
+}
+
+impl<'a, K, V> Iterator for IterMut<'a, K, V> {
+    type Item = (&'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a mut V)> {
+        Some((self.keys.next()?, self.values.next()?))
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.keys.size_hint()
+    }
+}
+
+impl<'a, K, V> DoubleEndedIterator for IterMut<'a, K, V> {
+    fn next_back(&mut self) -> Option<(&'a K, &'a mut V)> {
+        Some((self.keys.next_back()?, self.values.next_back()?))
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for IterMut<'a, K, V> {}
+
+impl<K, V> Iterator for IntoIter<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<(K, V)> {
+        Some((self.keys.next()?, self.values.next()?))
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.keys.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for IntoIter<K, V> {
+    fn next_back(&mut self) -> Option<(K, V)> {
+        Some((self.keys.next_back()?, self.values.next_back()?))
+    }
+}
+
+impl<K, V> ExactSizeIterator for IntoIter<K, V> {}
+
+impl<K, V> IntoIterator for SoaFlatMap<K, V> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+
+    fn into_iter(self) -> IntoIter<K, V> {
+        IntoIter {
+            keys: self.keys.into_iter(),
+            values: self.values.into_iter(),
+        }
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a SoaFlatMap<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a mut SoaFlatMap<K, V> {
+    type Item = (&'a K, &'a mut V);
+    type IntoIter = IterMut<'a, K, V>;
+
+    fn into_iter(self) -> IterMut<'a, K, V> {
+        self.iter_mut()
+    }
+}
+
+impl<K: Ord, V> FromIterator<(K, V)> for SoaFlatMap<K, V> {
+    /// Builds a map from `iter`. If a key occurs more than once, the last
+    /// pair wins, as with `FlatMap`.
+    fn from_iter<T: IntoIterator<Item = (K, V)>>(iter: T) -> SoaFlatMap<K, V> {
+        FlatMap::from_iter(iter).into()
+    }
+}
+
+impl<K: Ord, V> Extend<(K, V)> for SoaFlatMap<K, V> {
+    /// Inserts every pair from `iter`, with later pairs winning on equal keys.
+    ///
+    /// Small batches are inserted one by one. Larger ones are collected,
+    /// stably sorted and deduplicated, and then either overwrite the values
+    /// of keys already present or are spliced into both columns in one pass,
+    /// for a total of O(n + m log m).
+    fn extend<T: IntoIterator<Item = (K, V)>>(&mut self, iter: T) {
+        let iter = iter.into_iter();
+        match iter.size_hint() {
+            (_, Some(upper)) if upper <= EXTEND_INSERT_THRESHOLD => {
+                for (k, v) in iter {
+                    self.insert(k, v);
+                }
+            }
+            _ => {
+                let mut batch: Vec<_> = iter.collect();
+                sort_dedup_last(&mut batch, Ord::cmp);
+                let mut inserts = Vec::with_capacity(batch.len());
+                let mut i = 0;
+                for (k, v) in batch {
+                    i += self.keys[i..].partition_point(|e| *e < k);
+                    match self.keys.get(i) {
+                        Some(e) if *e == k => self.values[i] = v,
+                        _ => inserts.push((i, k, v)),
+                    }
+                }
+                splice_columns(&mut self.keys, &mut self.values, inserts);
+            }
+        }
+    }
+}
+
+impl<'a, K: Ord + Copy, V: Copy> Extend<(&'a K, &'a V)> for SoaFlatMap<K, V> {
+    fn extend<I: IntoIterator<Item = (&'a K, &'a V)>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().map(|(&key, &value)| (key, value)));
+    }
+}
+
+impl<K, V> From<FlatMap<K, V>> for SoaFlatMap<K, V> {
+    fn from(map: FlatMap<K, V>) -> SoaFlatMap<K, V> {
+        let (keys, values) = map.into_iter().unzip();
+        SoaFlatMap { keys, values }
+    }
+}
+
+impl<K, V> From<SoaFlatMap<K, V>> for FlatMap<K, V> {
+    fn from(map: SoaFlatMap<K, V>) -> FlatMap<K, V> {
+        // Safe: the keys are already sorted and free of duplicates.
+        unsafe { FlatMap::from_sorted_vec_unchecked(map.into_iter().collect()) }
+    }
+}
+
+impl<K, V> Default for SoaFlatMap<K, V> {
+    fn default() -> SoaFlatMap<K, V> {
+        SoaFlatMap::new()
+    }
+}
+
+impl<K: Ord, V: Ord> Ord for SoaFlatMap<K, V> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.iter().cmp(other.iter())
+    }
+}
+
+impl<K: PartialOrd, V: PartialOrd> PartialOrd for SoaFlatMap<K, V> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.iter().partial_cmp(other.iter())
+    }
+}
+
+impl<K: Debug, V: Debug> Debug for SoaFlatMap<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K, Q: ?Sized, V> Index<&Q> for SoaFlatMap<K, V>
+where
+    K: Ord + Borrow<Q>,
+    Q: Ord,
+{
+    type Output = V;
+
+    fn index(&self, key: &Q) -> &V {
+        self.get(key).expect("no entry found for key")
+    }
+}
+
+impl<K, Q: ?Sized, V> IndexMut<&Q> for SoaFlatMap<K, V>
+where
+    K: Ord + BorrowMut<Q>,
+    Q: Ord,
+{
+    fn index_mut(&mut self, key: &Q) -> &mut V {
+        self.get_mut(key).expect("no entry found for key")
+    }
+}
+
+#[cfg(feature = "serde1")]
+mod serde_impl {
+    // serialized as a plain map, exactly like FlatMap:
+    // { "k1": "v1", "k2": "v2" }
+
+    use super::SoaFlatMap;
+    use alloc::vec::Vec;
+    use core::fmt;
+    use core::marker::PhantomData;
+    use serde::de::{Deserialize, Deserializer, MapAccess, Visitor};
+    use serde::ser::SerializeMap;
+    use serde::{Serialize, Serializer};
+
+    impl<K, V> Serialize for SoaFlatMap<K, V>
+    where
+        K: Serialize,
+        V: Serialize,
+    {
+        fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: Serializer,
+        {
+            let mut map = serializer.serialize_map(Some(self.len()))?;
+            for (k, v) in self {
+                map.serialize_entry(k, v)?;
+            }
+            map.end()
+        }
+    }
+
+    struct SoaFlatMapVisitor<K, V> {
+        marker: PhantomData<fn() -> SoaFlatMap<K, V>>,
+    }
+
+    impl<K, V> SoaFlatMapVisitor<K, V> {
+        fn new() -> Self {
+            SoaFlatMapVisitor {
+                marker: PhantomData,
+            }
+        }
+    }
+
+    impl<'de, K, V> Visitor<'de> for SoaFlatMapVisitor<K, V>
+    where
+        K: Ord + Deserialize<'de>,
+        V: Deserialize<'de>,
+    {
+        type Value = SoaFlatMap<K, V>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a flat_map")
+        }
+
+        fn visit_map<M>(self, mut access: M) -> Result<Self::Value, M::Error>
+        where
+            M: MapAccess<'de>,
+        {
+            let mut vec = Vec::with_capacity(access.size_hint().unwrap_or(0));
+            while let Some(entry) = access.next_entry()? {
+                vec.push(entry);
+            }
+            Ok(vec.into_iter().collect())
+        }
+    }
+
+    impl<'de, K, V> Deserialize<'de> for SoaFlatMap<K, V>
+    where
+        K: Ord + Deserialize<'de>,
+        V: Deserialize<'de>,
+    {
+        fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            deserializer.deserialize_map(SoaFlatMapVisitor::new())
+        }
+    }
+}
@@ -0,0 +1,369 @@
+// Iterators and binary searches over a sorted slice of entries. These need
+// nothing beyond `core`, so every map type shares them.
+
+use crate::compare::Comparator;
+use core::borrow::Borrow;
+use core::cmp::Ordering;
+use core::iter::Map;
+use core::ops::{Bound, RangeBounds};
+use core::slice;
+
+pub struct IterMut<'a, K: 'a, V: 'a> {
+    pub(crate) inner: slice::IterMut<'a, (K, V)>,
+}
+
+pub struct ValuesMut<'a, K: 'a, V: 'a> {
+    pub(crate) inner: IterMut<'a, K, V>,
+}
+
+pub struct Iter<'a, K: 'a, V: 'a> {
+    pub(crate) inner: slice::Iter<'a, (K, V)>,
+}
+
+pub struct Range<'a, K: 'a, V: 'a> {
+    pub(crate) inner: Iter<'a, K, V>,
+}
+
+pub struct RangeMut<'a, K: 'a, V: 'a> {
+    pub(crate) inner: IterMut<'a, K, V>,
+}
+
+type First<'a, K, V> = fn((&'a K, &'a V)) -> &'a K;
+type Second<'a, K, V> = fn((&'a K, &'a V)) -> &'a V;
+
+pub struct Keys<'a, K: 'a, V: 'a> {
+    inner: Map<Iter<'a, K, V>, First<'a, K, V>>,
+}
+
+pub struct Values<'a, K: 'a, V: 'a> {
+    inner: Map<Iter<'a, K, V>, Second<'a, K, V>>,
+}
+
+impl<'a, K, V> Iter<'a, K, V> {
+    pub(crate) fn into_keys(self) -> Keys<'a, K, V> {
+        fn first<A, B>((a, _): (A, B)) -> A {
+            a
+        }
+        let first: First<'a, K, V> = first; // coerce to fn pointer
+        Keys {
+            inner: self.map(first),
+        }
+    }
+
+    pub(crate) fn into_values(self) -> Values<'a, K, V> {
+        fn second<A, B>((_, b): (A, B)) -> B {
+            b
+        }
+        let second: Second<'a, K, V> = second; // coerce to fn pointer
+        Values {
+            inner: self.map(second),
+        }
+    }
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        match self.inner.next() {
+            Some((k, v)) => Some((k, v)),
+            None => None,
+        }
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, K, V> Clone for Iter<'a, K, V> {
+    fn clone(&self) -> Iter<'a, K, V> {
+        Iter {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<'a, K, V> DoubleEndedIterator for Iter<'a, K, V> {
+    fn next_back(&mut self) -> Option<(&'a K, &'a V)> {
+        match self.inner.next_back() {
+            Some((k, v)) => Some((k, v)),
+            None => None,
+        }
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for Iter<'a, K, V> {}
+
+impl<'a, K, V> Iterator for IterMut<'a, K, V> {
+    type Item = (&'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a mut V)> {
+        match self.inner.next() {
+            Some((k, v)) => Some((k, v)),
+            None => None,
+        }
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, K, V> DoubleEndedIterator for IterMut<'a, K, V> {
+    fn next_back(&mut self) -> Option<(&'a K, &'a mut V)> {
+        match self.inner.next_back() {
+            Some((k, v)) => Some((k, v)),
+            None => None,
+        }
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for IterMut<'a, K, V> {}
+
+impl<'a, K, V> Iterator for Range<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        self.inner.next()
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, K, V> Clone for Range<'a, K, V> {
+    fn clone(&self) -> Range<'a, K, V> {
+        Range {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<'a, K, V> DoubleEndedIterator for Range<'a, K, V> {
+    fn next_back(&mut self) -> Option<(&'a K, &'a V)> {
+        self.inner.next_back()
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for Range<'a, K, V> {}
+
+impl<'a, K, V> Iterator for RangeMut<'a, K, V> {
+    type Item = (&'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a mut V)> {
+        self.inner.next()
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, K, V> DoubleEndedIterator for RangeMut<'a, K, V> {
+    fn next_back(&mut self) -> Option<(&'a K, &'a mut V)> {
+        self.inner.next_back()
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for RangeMut<'a, K, V> {}
+
+impl<'a, K, V> Clone for Keys<'a, K, V> {
+    fn clone(&self) -> Keys<'a, K, V> {
+        Keys {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<'a, K, V> Iterator for Keys<'a, K, V> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<&'a K> {
+        self.inner.next()
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, K, V> DoubleEndedIterator for Keys<'a, K, V> {
+    fn next_back(&mut self) -> Option<&'a K> {
+        self.inner.next_back()
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for Keys<'a, K, V> {}
+
+impl<'a, K, V> Clone for Values<'a, K, V> {
+    fn clone(&self) -> Values<'a, K, V> {
+        Values {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<'a, K, V> Iterator for Values<'a, K, V> {
+    type Item = &'a V;
+
+    fn next(&mut self) -> Option<&'a V> {
+        self.inner.next()
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, K, V> DoubleEndedIterator for Values<'a, K, V> {
+    fn next_back(&mut self) -> Option<&'a V> {
+        self.inner.next_back()
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for Values<'a, K, V> {}
+
+// Binary searches `v` for `q` under `cmp`. Every ordered lookup goes through
+// here, letting comparators such as `Primitive` count short runs of keys
+// with SIMD comparisons instead.
+pub(crate) fn search<K, V, Q, C>(v: &[(K, V)], cmp: &C, q: &Q) -> Result<usize, usize>
+where
+    K: Borrow<Q>,
+    C: Comparator<Q>,
+    Q: ?Sized,
+{
+    search_by(v, |e: &(K, V)| e.0.borrow(), cmp, q)
+}
+
+// Like `search`, over a slice holding only the keys.
+#[cfg(feature = "alloc")]
+pub(crate) fn search_keys<K, Q, C>(keys: &[K], cmp: &C, q: &Q) -> Result<usize, usize>
+where
+    K: Borrow<Q>,
+    C: Comparator<Q>,
+    Q: ?Sized,
+{
+    search_by(keys, |k: &K| k.borrow(), cmp, q)
+}
+
+#[inline]
+fn search_by<T, Q, C, F>(items: &[T], key: F, cmp: &C, q: &Q) -> Result<usize, usize>
+where
+    C: Comparator<Q>,
+    Q: ?Sized,
+    F: Fn(&T) -> &Q,
+{
+    let i = match cmp.count_less(items, &key, q) {
+        Some(i) => i,
+        None => lower_bound(items.len(), |i| {
+            cmp.compare(key(&items[i]), q) == Ordering::Less
+        }),
+    };
+    match items.get(i) {
+        Some(item) if cmp.compare(key(item), q) == Ordering::Equal => Ok(i),
+        _ => Err(i),
+    }
+}
+
+// Returns the number of leading positions for which `below` holds, halving
+// the range each step without a data-dependent branch.
+#[inline(always)]
+fn lower_bound<F: Fn(usize) -> bool>(len: usize, below: F) -> usize {
+    if len == 0 {
+        return 0;
+    }
+    let mut base = 0;
+    let mut size = len;
+    while size > 1 {
+        let half = size / 2;
+        let mid = base + half;
+        base = if below(mid) { mid } else { base };
+        size -= half;
+    }
+    base + below(base) as usize
+}
+
+// Translates `range` into the half-open span of indices it covers in `v`,
+// panicking on the same malformed ranges as `BTreeMap::range`.
+pub(crate) fn range_indices<K, V, Q, C, R>(v: &[(K, V)], cmp: &C, range: &R) -> (usize, usize)
+where
+    K: Borrow<Q>,
+    C: Comparator<Q>,
+    Q: ?Sized,
+    R: RangeBounds<Q>,
+{
+    range_indices_by(v.len(), cmp, range, |q| search(v, cmp, q))
+}
+
+// Like `range_indices`, over a slice holding only the keys.
+#[cfg(feature = "alloc")]
+pub(crate) fn key_range_indices<K, Q, C, R>(keys: &[K], cmp: &C, range: &R) -> (usize, usize)
+where
+    K: Borrow<Q>,
+    C: Comparator<Q>,
+    Q: ?Sized,
+    R: RangeBounds<Q>,
+{
+    range_indices_by(keys.len(), cmp, range, |q| search_keys(keys, cmp, q))
+}
+
+// Panics on the same malformed ranges as `BTreeMap::range`.
+pub(crate) fn check_range<Q, C, R>(cmp: &C, range: &R)
+where
+    C: Comparator<Q>,
+    Q: ?Sized,
+    R: RangeBounds<Q>,
+{
+    match (range.start_bound(), range.end_bound()) {
+        (Bound::Excluded(s), Bound::Excluded(e)) if cmp.compare(s, e) == Ordering::Equal => {
+            panic!("range start and end are equal and excluded in FlatMap")
+        }
+        (Bound::Included(s) | Bound::Excluded(s), Bound::Included(e) | Bound::Excluded(e))
+            if cmp.compare(s, e) == Ordering::Greater =>
+        {
+            panic!("range start is greater than range end in FlatMap")
+        }
+        _ => {}
+    }
+}
+
+fn range_indices_by<Q, C, R, F>(len: usize, cmp: &C, range: &R, search: F) -> (usize, usize)
+where
+    C: Comparator<Q>,
+    Q: ?Sized,
+    R: RangeBounds<Q>,
+    F: Fn(&Q) -> Result<usize, usize>,
+{
+    check_range(cmp, range);
+    let start = match range.start_bound() {
+        Bound::Unbounded => 0,
+        Bound::Included(q) => search(q).unwrap_or_else(|i| i),
+        Bound::Excluded(q) => search(q).map(|i| i + 1).unwrap_or_else(|i| i),
+    };
+    let end = match range.end_bound() {
+        Bound::Unbounded => len,
+        Bound::Included(q) => search(q).map(|i| i + 1).unwrap_or_else(|i| i),
+        Bound::Excluded(q) => search(q).unwrap_or_else(|i| i),
+    };
+    (start, end)
+}
+
+impl<'a, K, V> Iterator for ValuesMut<'a, K, V> {
+    type Item = &'a mut V;
+
+    fn next(&mut self) -> Option<&'a mut V> {
+        self.inner.next().map(|(_, v)| v)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, K, V> DoubleEndedIterator for ValuesMut<'a, K, V> {
+    fn next_back(&mut self) -> Option<&'a mut V> {
+        self.inner.next_back().map(|(_, v)| v)
+    }
+}
+
+impl<'a, K, V> ExactSizeIterator for ValuesMut<'a, K, V> {
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+}
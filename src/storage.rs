@@ -0,0 +1,312 @@
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::mem;
+#[cfg(feature = "smallvec")]
+use core::ptr;
+
+#[cfg(feature = "smallvec")]
+use smallvec::{Array, SmallVec};
+
+/// The contiguous buffer a `FlatMapBy` keeps its sorted entries in.
+///
+/// Only the slice views and the conversions to and from `Vec` are required.
+/// Every mutation has a default that round-trips through a `Vec`, which is
+/// right for stores without spare capacity such as `Box<[T]>`; growable
+/// stores override them with their own in-place versions.
+///
+/// Bulk operations like `extend` and `append` always run on a `Vec` borrowed
+/// through `with_vec`.
+pub trait Storage<T>: Default + IntoIterator<Item = T> {
+    fn from_vec(v: Vec<T>) -> Self;
+
+    fn into_vec(self) -> Vec<T>;
+
+    fn as_slice(&self) -> &[T];
+
+    fn as_mut_slice(&mut self) -> &mut [T];
+
+    /// Runs `f` on the contents as a `Vec`, storing the result back.
+    ///
+    /// The `Vec` is stored back even if `f` panics, so the store keeps
+    /// whatever `f` left in it.
+    fn with_vec<R, F>(&mut self, f: F) -> R
+    where
+        F: FnOnce(&mut Vec<T>) -> R,
+    {
+        let v = mem::take(self).into_vec();
+        let mut w = WriteBack { store: self, v };
+        f(&mut w.v)
+    }
+
+    fn with_capacity(capacity: usize) -> Self {
+        Self::from_vec(Vec::with_capacity(capacity))
+    }
+
+    fn capacity(&self) -> usize {
+        self.as_slice().len()
+    }
+
+    fn reserve(&mut self, _additional: usize) {}
+
+    fn reserve_exact(&mut self, _additional: usize) {}
+
+    fn shrink_to_fit(&mut self) {}
+
+    fn insert(&mut self, index: usize, element: T) {
+        self.with_vec(|v| v.insert(index, element))
+    }
+
+    fn remove(&mut self, index: usize) -> T {
+        self.with_vec(|v| v.remove(index))
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        self.with_vec(|v| v.pop())
+    }
+
+    fn truncate(&mut self, len: usize) {
+        self.with_vec(|v| v.truncate(len))
+    }
+
+    fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    fn retain<F>(&mut self, f: F)
+    where
+        F: FnMut(&mut T) -> bool,
+    {
+        self.with_vec(|v| v.retain_mut(f))
+    }
+
+    /// Splits the store in two, returning the elements from `at` onwards.
+    fn split_off(&mut self, at: usize) -> Self {
+        Self::from_vec(self.with_vec(|v| v.split_off(at)))
+    }
+}
+
+// Holds the `Vec` borrowed by the default `with_vec`, and stores it back
+// when dropped, whether `f` returned or panicked.
+struct WriteBack<'a, T, S: 'a + Storage<T>> {
+    store: &'a mut S,
+    v: Vec<T>,
+}
+
+impl<'a, T, S: Storage<T>> Drop for WriteBack<'a, T, S> {
+    fn drop(&mut self) {
+        *self.store = S::from_vec(mem::take(&mut self.v));
+    }
+}
+
+impl<T> Storage<T> for Vec<T> {
+    fn from_vec(v: Vec<T>) -> Self {
+        v
+    }
+
+    fn into_vec(self) -> Vec<T> {
+        self
+    }
+
+    fn as_slice(&self) -> &[T] {
+        self
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [T] {
+        self
+    }
+
+    fn with_vec<R, F>(&mut self, f: F) -> R
+    where
+        F: FnOnce(&mut Vec<T>) -> R,
+    {
+        f(self)
+    }
+
+    fn with_capacity(capacity: usize) -> Self {
+        Vec::with_capacity(capacity)
+    }
+
+    fn capacity(&self) -> usize {
+        Vec::capacity(self)
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        Vec::reserve(self, additional)
+    }
+
+    fn reserve_exact(&mut self, additional: usize) {
+        Vec::reserve_exact(self, additional)
+    }
+
+    fn shrink_to_fit(&mut self) {
+        Vec::shrink_to_fit(self)
+    }
+
+    fn insert(&mut self, index: usize, element: T) {
+        Vec::insert(self, index, element)
+    }
+
+    fn remove(&mut self, index: usize) -> T {
+        Vec::remove(self, index)
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        Vec::pop(self)
+    }
+
+    fn truncate(&mut self, len: usize) {
+        Vec::truncate(self, len)
+    }
+
+    fn clear(&mut self) {
+        Vec::clear(self)
+    }
+
+    fn retain<F>(&mut self, f: F)
+    where
+        F: FnMut(&mut T) -> bool,
+    {
+        Vec::retain_mut(self, f)
+    }
+
+    fn split_off(&mut self, at: usize) -> Self {
+        Vec::split_off(self, at)
+    }
+}
+
+/// A frozen store with no spare capacity. Lookups cost the same as with a
+/// `Vec`, but every insert or removal reallocates.
+impl<T> Storage<T> for Box<[T]> {
+    fn from_vec(v: Vec<T>) -> Self {
+        v.into_boxed_slice()
+    }
+
+    fn into_vec(self) -> Vec<T> {
+        <[T]>::into_vec(self)
+    }
+
+    fn as_slice(&self) -> &[T] {
+        self
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [T] {
+        self
+    }
+}
+
+/// An inline-first store: up to `A::size()` entries live inside the map
+/// itself, and only larger maps allocate.
+#[cfg(feature = "smallvec")]
+impl<A: Array> Storage<A::Item> for SmallVec<A> {
+    fn from_vec(v: Vec<A::Item>) -> Self {
+        SmallVec::from_vec(v)
+    }
+
+    fn into_vec(self) -> Vec<A::Item> {
+        SmallVec::into_vec(self)
+    }
+
+    fn as_slice(&self) -> &[A::Item] {
+        self
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [A::Item] {
+        self
+    }
+
+    fn with_capacity(capacity: usize) -> Self {
+        SmallVec::with_capacity(capacity)
+    }
+
+    fn capacity(&self) -> usize {
+        SmallVec::capacity(self)
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        SmallVec::reserve(self, additional)
+    }
+
+    fn reserve_exact(&mut self, additional: usize) {
+        SmallVec::reserve_exact(self, additional)
+    }
+
+    fn shrink_to_fit(&mut self) {
+        SmallVec::shrink_to_fit(self)
+    }
+
+    fn insert(&mut self, index: usize, element: A::Item) {
+        SmallVec::insert(self, index, element)
+    }
+
+    fn remove(&mut self, index: usize) -> A::Item {
+        SmallVec::remove(self, index)
+    }
+
+    fn pop(&mut self) -> Option<A::Item> {
+        SmallVec::pop(self)
+    }
+
+    fn truncate(&mut self, len: usize) {
+        SmallVec::truncate(self, len)
+    }
+
+    fn clear(&mut self) {
+        SmallVec::clear(self)
+    }
+
+    // `SmallVec::retain` swaps each kept element forwards, which leaves the
+    // elements out of order if `f` panics, so this compacts them the way
+    // `Vec::retain` does instead.
+    fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&mut A::Item) -> bool,
+    {
+        // `v[..kept]` holds the kept elements and `v[next..len]` those still
+        // to be tested. If `f` or a drop panics, the guard moves the untested
+        // elements down to close the gap.
+        struct Retain<'a, A: 'a + Array> {
+            v: &'a mut SmallVec<A>,
+            kept: usize,
+            next: usize,
+            len: usize,
+        }
+
+        impl<'a, A: Array> Drop for Retain<'a, A> {
+            fn drop(&mut self) {
+                unsafe {
+                    let base = self.v.as_mut_ptr();
+                    let rest = self.len - self.next;
+                    ptr::copy(base.add(self.next), base.add(self.kept), rest);
+                    self.v.set_len(self.kept + rest);
+                }
+            }
+        }
+
+        let len = self.len();
+        let mut r = Retain {
+            v: self,
+            kept: 0,
+            next: 0,
+            len,
+        };
+        unsafe {
+            r.v.set_len(0);
+            let base = r.v.as_mut_ptr();
+            while r.next < r.len {
+                let i = r.next;
+                if f(&mut *base.add(i)) {
+                    ptr::copy(base.add(i), base.add(r.kept), 1);
+                    r.kept += 1;
+                    r.next += 1;
+                } else {
+                    r.next += 1;
+                    ptr::drop_in_place(base.add(i));
+                }
+            }
+        }
+    }
+
+    fn split_off(&mut self, at: usize) -> Self {
+        self.drain(at..).collect()
+    }
+}
@@ -0,0 +1,119 @@
+extern crate flat_map;
+
+use flat_map::array_flat_map::{Entry, IntoIter};
+use flat_map::ArrayFlatMap;
+#[cfg(feature = "alloc")]
+use flat_map::FlatMap;
+use std::cell::Cell;
+#[cfg(feature = "alloc")]
+use std::convert::TryFrom;
+#[cfg(feature = "alloc")]
+use std::iter::FromIterator;
+use std::rc::Rc;
+
+#[test]
+fn it_works() {
+    let mut m: ArrayFlatMap<i32, &str, 4> = ArrayFlatMap::new();
+    assert!(m.is_empty());
+    assert_eq!(m.capacity(), 4);
+    assert_eq!(m.insert(3, "c"), Ok(None));
+    assert_eq!(m.insert(1, "a"), Ok(None));
+    assert_eq!(m.insert(2, "b"), Ok(None));
+    assert_eq!(m.insert(3, "C"), Ok(Some("c")));
+    assert_eq!(m.insert(0, "z"), Ok(None));
+    assert!(m.is_full());
+    assert_eq!(m.insert(5, "e"), Err((5, "e")));
+    assert_eq!(m.insert(1, "A"), Ok(Some("a")));
+    assert!(m.keys().eq(&[0, 1, 2, 3]));
+
+    assert_eq!(m.get(&2), Some(&"b"));
+    assert_eq!(m.get(&5), None);
+    assert!(m.contains_key(&0));
+    *m.get_mut(&2).unwrap() = "B";
+    assert_eq!(m[&2], "B");
+    assert_eq!(m.get_key_value(&3), Some((&3, &"C")));
+    assert!(m.range(1..3).eq(vec![(&1, &"A"), (&2, &"B")]));
+    for (_, v) in m.range_mut(..=1) {
+        *v = "x";
+    }
+    assert!(m.values().eq(&["x", "x", "B", "C"]));
+
+    assert_eq!(m.remove(&1), Some("x"));
+    assert_eq!(m.remove(&1), None);
+    assert_eq!(m.remove_entry(&0), Some((0, "x")));
+    assert_eq!(m.first_key_value(), Some((&2, &"B")));
+    assert_eq!(m.pop_last(), Some((3, "C")));
+    assert_eq!(format!("{:?}", m), r#"{2: "B"}"#);
+    m.clear();
+    assert!(m.is_empty());
+}
+
+#[test]
+fn test_entry() {
+    let mut m: ArrayFlatMap<&str, i32, 2> = ArrayFlatMap::new();
+    *m.entry("b").or_insert(0).unwrap() += 1;
+    *m.entry("b").or_insert(0).unwrap() += 1;
+    *m.entry("a").or_insert_with(|| 10).unwrap() += 1;
+    assert_eq!(m.entry("c").or_insert(3), Err(("c", 3)));
+    match m.entry("a") {
+        Entry::Occupied(mut e) => {
+            assert_eq!(e.key(), &"a");
+            assert_eq!(e.insert(7), 11);
+            assert_eq!(e.remove(), 7);
+        }
+        Entry::Vacant(_) => unreachable!(),
+    }
+    match m.entry("c") {
+        Entry::Vacant(e) => assert_eq!(e.insert(3), Ok(&mut 3)),
+        Entry::Occupied(_) => unreachable!(),
+    }
+    assert!(m.iter().eq(vec![(&"b", &2), (&"c", &3)]));
+}
+
+#[test]
+fn test_drops() {
+    let counter = Rc::new(Cell::new(0));
+    struct Noisy(Rc<Cell<usize>>);
+    impl Clone for Noisy {
+        fn clone(&self) -> Noisy {
+            Noisy(self.0.clone())
+        }
+    }
+    impl Drop for Noisy {
+        fn drop(&mut self) {
+            self.0.set(self.0.get() + 1);
+        }
+    }
+
+    let mut m: ArrayFlatMap<i32, Noisy, 8> = ArrayFlatMap::new();
+    for i in 0..6 {
+        assert!(m.insert(i, Noisy(counter.clone())).is_ok());
+    }
+    drop(m.remove(&2));
+    assert_eq!(counter.get(), 1);
+    let m2 = m.clone();
+    drop(m);
+    assert_eq!(counter.get(), 6);
+
+    let mut iter: IntoIter<_, _, 8> = m2.into_iter();
+    assert_eq!(iter.len(), 5);
+    assert_eq!(iter.next().map(|(k, _)| k), Some(0));
+    assert_eq!(iter.next_back().map(|(k, _)| k), Some(5));
+    assert_eq!(counter.get(), 8);
+    drop(iter);
+    assert_eq!(counter.get(), 11);
+}
+
+#[cfg(feature = "alloc")]
+#[test]
+fn test_flat_map_conversions() {
+    let map = FlatMap::from_iter((0..5).map(|i| (i, i * 10)));
+    let array = ArrayFlatMap::<_, _, 8>::try_from(map.clone()).unwrap();
+    assert_eq!(array[&4], 40);
+    let back: FlatMap<_, _> = array.clone().into();
+    assert_eq!(back, map);
+
+    let too_big = ArrayFlatMap::<_, _, 4>::try_from(map).unwrap_err();
+    assert_eq!(too_big.len(), 5);
+    assert_eq!(array, array.clone());
+}
@@ -0,0 +1,108 @@
+#![cfg(feature = "std")]
+
+extern crate flat_map;
+extern crate rand;
+
+use flat_map::{BufferedFlatMap, FlatMap, Natural, Reversed};
+use rand::{Rng, SeedableRng, XorShiftRng};
+use std::collections::BTreeMap;
+use std::ops::Bound::Included;
+
+#[test]
+fn test_random_ops() {
+    let mut rng = XorShiftRng::from_seed([5, 6, 7, 8]);
+    for &limit in &[None, Some(0), Some(1), Some(7)] {
+        let mut map = BufferedFlatMap::new();
+        if let Some(limit) = limit {
+            map.set_buffer_limit(limit);
+        }
+        let mut expected = BTreeMap::new();
+        for i in 0..5000 {
+            let k = rng.gen_range(0u32, 1000);
+            match rng.gen_range(0, 10) {
+                0..=4 => assert_eq!(map.insert(k, i), expected.insert(k, i)),
+                5 | 6 => assert_eq!(map.remove(&k), expected.remove(&k)),
+                7 => {
+                    if let Some(v) = map.get_mut(&k) {
+                        *v += 1;
+                    }
+                    if let Some(v) = expected.get_mut(&k) {
+                        *v += 1;
+                    }
+                }
+                8 => assert_eq!(map.get(&k), expected.get(&k)),
+                _ => assert_eq!(map.contains_key(&k), expected.contains_key(&k)),
+            }
+            assert!(map.buffered() <= map.buffer_limit());
+            assert_eq!(map.len(), expected.len());
+        }
+        assert_eq!(format!("{:?}", map), format!("{:?}", expected));
+        assert!(map.iter().eq(expected.iter()));
+        assert_eq!(map.buffered(), 0);
+    }
+}
+
+#[test]
+fn test_flush() {
+    let mut map = BufferedFlatMap::with_comparator(Reversed(Natural));
+    map.set_buffer_limit(3);
+    map.extend(vec![(1, 'a'), (4, 'd')]);
+    map.insert(3, 'c');
+    map.insert(2, 'b');
+    map.insert(5, 'e');
+    assert_eq!(map.buffered(), 3);
+    assert_eq!(map.insert(2, 'B'), Some('b'));
+    assert_eq!(map.insert(4, 'D'), Some('d'));
+    assert_eq!(map.buffered(), 3);
+    assert_eq!(map[&2], 'B');
+
+    map.insert(0, '-');
+    assert_eq!(map.buffered(), 0);
+    assert!(map.keys().eq(&[5, 4, 3, 2, 1, 0]));
+
+    map.insert(6, 'f');
+    assert_eq!(map.first_key_value(), Some((&6, &'f')));
+    assert_eq!(map.buffered(), 0);
+    map.insert(7, 'g');
+    assert!(map
+        .range((Included(7), Included(5)))
+        .map(|(_, &v)| v)
+        .eq("gfe".chars()));
+}
+
+#[test]
+fn test_default_buffer_limit() {
+    // The square root of the merged map's size, but at least 64.
+    for &(len, limit) in &[
+        (0, 64),
+        (4_200, 64),
+        (9_999, 99),
+        (10_000, 100),
+        (10_200, 100),
+        (10_201, 101),
+    ] {
+        let map: BufferedFlatMap<_, _> = (0..len).map(|i| (i, ())).collect();
+        assert_eq!(map.buffer_limit(), limit);
+    }
+}
+
+#[test]
+fn test_conversions() {
+    let mut map: BufferedFlatMap<_, _> = (0..10).map(|i| (i * 2, i)).collect();
+    assert_eq!(map.buffered(), 0);
+    map.insert(3, 30);
+    map.insert(1, 10);
+    let clone = map.clone();
+    assert_eq!(clone, map);
+    assert_eq!(map.remove(&3), Some(30));
+    assert_ne!(clone, map);
+    map.retain(|&k, _| k % 4 != 0);
+
+    let flat: FlatMap<_, _> = map.into();
+    assert!(flat.keys().eq(&[1, 2, 6, 10, 14, 18]));
+    let map = BufferedFlatMap::from(flat);
+    assert!(map
+        .into_iter()
+        .map(|(k, _)| k)
+        .eq(vec![1, 2, 6, 10, 14, 18]));
+}
@@ -0,0 +1,91 @@
+#![cfg(feature = "std")]
+
+#[cfg(feature = "serde")]
+extern crate serde_json;
+
+extern crate flat_map;
+
+use flat_map::FlatMultiMap;
+
+#[test]
+fn it_works() {
+    let mut m = FlatMultiMap::new();
+    assert!(m.is_empty());
+    m.insert(2, "b");
+    m.insert(1, "a");
+    m.insert(2, "c");
+    m.insert(3, "d");
+    m.insert(2, "e");
+    assert_eq!(m.len(), 5);
+    assert_eq!(m.count(&2), 3);
+    assert_eq!(m.count(&4), 0);
+    assert!(m.get_all(&2).eq(&["b", "c", "e"]));
+    assert_eq!(m.get_all(&2).as_slice(), &["b", "c", "e"]);
+    assert_eq!(m.get_all(&0).count(), 0);
+    assert_eq!(m.get(&2), Some(&"b"));
+    assert!(m.contains_key(&3));
+    assert!(!m.contains_key(&0));
+    assert_eq!(m.equal_range(&2), 1..4);
+    assert_eq!(m.equal_range(&0), 0..0);
+    assert_eq!(m.equal_range(&9), 5..5);
+    assert!(m.keys().eq(&[1, 2, 2, 2, 3]));
+    assert!(m.keys_unique().eq(&[1, 2, 3]));
+
+    assert_eq!(m.remove_all(&2), vec!["b", "c", "e"]);
+    assert!(m.remove_all(&2).is_empty());
+    assert!(m.into_iter().eq(vec![(1, "a"), (3, "d")]));
+}
+
+#[test]
+fn test_groups() {
+    let m: FlatMultiMap<_, _> = vec![("y", 1), ("x", 2), ("y", 3), ("x", 4), ("z", 5)]
+        .into_iter()
+        .collect();
+    let groups: Vec<_> = m.groups().collect();
+    assert_eq!(
+        groups,
+        vec![(&"x", &[2, 4][..]), (&"y", &[1, 3][..]), (&"z", &[5][..])]
+    );
+    assert!(m.values().eq(&[2, 4, 1, 3, 5]));
+    assert_eq!(FlatMultiMap::<i32, i32>::new().groups().count(), 0);
+}
+
+#[test]
+fn test_extend_debug() {
+    let mut m = FlatMultiMap::new();
+    m.extend(vec![(1, 'a'), (0, 'z'), (1, 'b')]);
+    assert_eq!(format!("{:?}", m), "{0: 'z', 1: 'a', 1: 'b'}");
+    assert_eq!(m.clone(), m);
+    assert!(m.iter().rev().map(|(_, &v)| v).eq(vec!['b', 'a', 'z']));
+}
+
+#[test]
+fn test_extend_batch() {
+    let mut m: FlatMultiMap<_, _> = (0..20).map(|i| (i % 5 * 3, i)).collect();
+    let mut expected = m.clone();
+    let pairs: Vec<_> = (0..100).map(|i| (i * 7 % 17, 100 + i)).collect();
+    for &(k, v) in &pairs {
+        expected.insert(k, v);
+    }
+    m.extend(pairs);
+    assert_eq!(m, expected);
+    assert!(m.get_all(&3).eq(&[1, 6, 11, 16, 115, 132, 149, 166, 183]));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde() {
+    let m: FlatMultiMap<_, _> = vec![(2, 'b'), (1, 'a'), (2, 'c')].into_iter().collect();
+    let json = serde_json::to_string(&m).unwrap();
+    assert_eq!(json, r#"{"1":["a"],"2":["b","c"]}"#);
+    let new_m: FlatMultiMap<u32, char> = serde_json::from_str(&json).unwrap();
+    assert_eq!(new_m, m);
+
+    // Unsorted input and repeated keys are merged, keeping the input order
+    // of the values under each key.
+    let json = r#"{"3":["d"],"2":["b","c"],"1":[],"2":["e"]}"#;
+    let m: FlatMultiMap<u32, char> = serde_json::from_str(json).unwrap();
+    assert!(m
+        .iter()
+        .eq(vec![(&2, &'b'), (&2, &'c'), (&2, &'e'), (&3, &'d')]));
+}
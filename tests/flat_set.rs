@@ -0,0 +1,223 @@
+#![cfg(feature = "std")]
+
+#[cfg(feature = "serde")]
+extern crate serde_json;
+
+extern crate flat_map;
+
+use flat_map::FlatSet;
+
+// Equal when the numbers are, so the tag tells equal values apart.
+#[derive(Debug)]
+struct Tagged(i32, &'static str);
+
+impl PartialEq for Tagged {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+impl Eq for Tagged {}
+impl PartialOrd for Tagged {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for Tagged {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.0.cmp(&other.0)
+    }
+}
+
+#[test]
+fn it_works() {
+    let mut s = FlatSet::new();
+    assert!(s.is_empty());
+    assert!(s.insert(3));
+    assert!(s.insert(1));
+    assert!(!s.insert(3));
+    assert_eq!(s.len(), 2);
+    assert!(s.contains(&1));
+    assert!(!s.contains(&2));
+    assert_eq!(s.get(&3), Some(&3));
+    assert_eq!(s.first(), Some(&1));
+    assert_eq!(s.last(), Some(&3));
+    assert!(s.remove(&1));
+    assert!(!s.remove(&1));
+    assert_eq!(s.take(&3), Some(3));
+    assert_eq!(s.take(&3), None);
+    assert!(s.is_empty());
+}
+
+#[test]
+fn test_replace() {
+    let mut s = FlatSet::new();
+    assert!(s.insert(Tagged(1, "a")));
+    assert!(!s.insert(Tagged(1, "b")));
+    assert_eq!(s.get(&Tagged(1, "")).unwrap().1, "a");
+    assert_eq!(s.replace(Tagged(1, "c")).unwrap().1, "a");
+    assert_eq!(s.get(&Tagged(1, "")).unwrap().1, "c");
+    assert!(s.replace(Tagged(2, "d")).is_none());
+    assert_eq!(s.len(), 2);
+}
+
+#[test]
+fn test_from_iter_keeps_last() {
+    let s: FlatSet<_> = vec![
+        Tagged(2, "a"),
+        Tagged(1, "b"),
+        Tagged(2, "c"),
+        Tagged(1, "d"),
+    ]
+    .into_iter()
+    .collect();
+    assert!(s.iter().map(|t| t.1).eq(vec!["d", "c"]));
+}
+
+#[test]
+fn test_borrow() {
+    let s: FlatSet<String> = vec!["b".to_string(), "a".to_string()].into_iter().collect();
+    assert!(s.contains("a"));
+    assert_eq!(s.get("b").map(|x| x.as_str()), Some("b"));
+}
+
+#[test]
+fn test_iter() {
+    let s: FlatSet<_> = vec![5, 3, 1, 4, 1, 5].into_iter().collect();
+    assert!(s.iter().cloned().eq(vec![1, 3, 4, 5]));
+    assert!(s.iter().rev().cloned().eq(vec![5, 4, 3, 1]));
+    assert_eq!(s.iter().len(), 4);
+    assert!(s.range(2..5).cloned().eq(vec![3, 4]));
+    assert!((&s).into_iter().cloned().eq(vec![1, 3, 4, 5]));
+    assert!(s.into_iter().eq(vec![1, 3, 4, 5]));
+}
+
+#[test]
+fn test_subset_superset_disjoint() {
+    let a: FlatSet<_> = vec![1, 3, 5].into_iter().collect();
+    let b: FlatSet<_> = vec![1, 2, 3, 4, 5].into_iter().collect();
+    let c: FlatSet<_> = vec![2, 4, 6].into_iter().collect();
+    let empty: FlatSet<i32> = FlatSet::new();
+
+    assert!(a.is_subset(&b));
+    assert!(!b.is_subset(&a));
+    assert!(b.is_superset(&a));
+    assert!(!a.is_superset(&b));
+    assert!(a.is_subset(&a));
+    assert!(empty.is_subset(&a));
+    assert!(!c.is_subset(&b));
+
+    assert!(a.is_disjoint(&c));
+    assert!(!b.is_disjoint(&c));
+    assert!(empty.is_disjoint(&empty));
+}
+
+#[test]
+fn test_extend_and_pop() {
+    let mut s = FlatSet::new();
+    s.extend(vec![3, 1, 2]);
+    s.extend(&[2, 4]);
+    assert_eq!(s.len(), 4);
+    assert_eq!(s.pop_first(), Some(1));
+    assert_eq!(s.pop_last(), Some(4));
+    s.retain(|&x| x != 2);
+    assert!(s.into_iter().eq(vec![3]));
+}
+
+#[test]
+fn test_extend_keeps_existing() {
+    // Small batches are inserted one by one and large ones merged, and both
+    // keep a value already in the set, as insert does.
+    for &n in &[1, 20] {
+        let mut s: FlatSet<_> = vec![Tagged(0, "old")].into_iter().collect();
+        s.extend((0..n).map(|i| Tagged(i, "new")));
+        assert_eq!(s.len(), n as usize);
+        assert_eq!(s.first().unwrap().1, "old");
+        assert!(s.iter().skip(1).all(|t| t.1 == "new"));
+
+        s.extend((0..n).map(|i| Tagged(i, "newer")));
+        assert_eq!(s.first().unwrap().1, "old");
+        assert!(s.iter().skip(1).all(|t| t.1 == "new"));
+
+        // Of equal values within one batch, the first is added.
+        s.extend((0..n).map(|i| Tagged(-1, if i == 0 { "first" } else { "later" })));
+        assert_eq!(s.first().unwrap().1, "first");
+    }
+}
+
+#[test]
+fn test_ord_and_debug() {
+    let a: FlatSet<_> = vec![1, 2].into_iter().collect();
+    let b: FlatSet<_> = vec![1, 3].into_iter().collect();
+    assert!(a < b);
+    assert_eq!(a, a.clone());
+    assert_eq!(format!("{:?}", a), "{1, 2}");
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde() {
+    let s: FlatSet<u64> = vec![3, 1, 2].into_iter().collect();
+    let json = serde_json::to_string(&s).unwrap();
+    assert_eq!(json, "[1,2,3]");
+    let new_set: FlatSet<u64> = serde_json::from_str("[3,2,1,2]").unwrap();
+    assert_eq!(new_set, s);
+}
+
+fn set(v: &[i32]) -> FlatSet<i32> {
+    v.iter().cloned().collect()
+}
+
+#[test]
+fn test_union() {
+    let a = set(&[1, 3, 5, 7]);
+    let b = set(&[2, 3, 4, 7, 9]);
+    assert!(a.union(&b).cloned().eq(vec![1, 2, 3, 4, 5, 7, 9]));
+    assert!(a.union(&set(&[])).cloned().eq(vec![1, 3, 5, 7]));
+    assert_eq!(a.union(&b).size_hint(), (5, Some(9)));
+    assert_eq!(&a | &b, set(&[1, 2, 3, 4, 5, 7, 9]));
+}
+
+#[test]
+fn test_intersection() {
+    let a = set(&[1, 3, 5, 7]);
+    let b = set(&[2, 3, 4, 7, 9]);
+    assert!(a.intersection(&b).cloned().eq(vec![3, 7]));
+    assert!(b.intersection(&a).cloned().eq(vec![3, 7]));
+    assert_eq!(a.intersection(&set(&[])).next(), None);
+    assert_eq!(&a & &b, set(&[3, 7]));
+}
+
+#[test]
+fn test_intersection_gallop() {
+    let large: FlatSet<i32> = (0..10000).map(|i| i * 3).collect();
+    let small = set(&[-1, 0, 2, 3, 299, 300, 15000, 29997, 29998, 40000]);
+    let expected = vec![0, 3, 300, 15000, 29997];
+    assert!(small.intersection(&large).cloned().eq(expected.clone()));
+    assert!(large.intersection(&small).cloned().eq(expected));
+
+    let brute: Vec<_> = small
+        .iter()
+        .filter(|x| large.contains(x))
+        .cloned()
+        .collect();
+    assert!(large.intersection(&small).cloned().eq(brute));
+}
+
+#[test]
+fn test_difference() {
+    let a = set(&[1, 3, 5, 7]);
+    let b = set(&[2, 3, 4, 7, 9]);
+    assert!(a.difference(&b).cloned().eq(vec![1, 5]));
+    assert!(b.difference(&a).cloned().eq(vec![2, 4, 9]));
+    assert!(a.difference(&set(&[])).cloned().eq(vec![1, 3, 5, 7]));
+    assert_eq!(&a - &b, set(&[1, 5]));
+}
+
+#[test]
+fn test_symmetric_difference() {
+    let a = set(&[1, 3, 5, 7]);
+    let b = set(&[2, 3, 4, 7, 9]);
+    assert!(a.symmetric_difference(&b).cloned().eq(vec![1, 2, 4, 5, 9]));
+    assert!(b.symmetric_difference(&a).cloned().eq(vec![1, 2, 4, 5, 9]));
+    assert_eq!(&a ^ &b, set(&[1, 2, 4, 5, 9]));
+}
@@ -0,0 +1,97 @@
+#![cfg(feature = "std")]
+
+extern crate flat_map;
+
+use flat_map::{FlatMap, FrozenFlatMap};
+use std::ops::Bound::{Excluded, Included, Unbounded};
+use std::rc::Rc;
+
+#[test]
+fn it_works() {
+    let map: FlatMap<_, _> = vec![(3, "c"), (1, "a"), (2, "b")].into_iter().collect();
+    let frozen = map.clone().freeze();
+    assert_eq!(frozen.len(), 3);
+    assert_eq!(frozen.get(&2), Some(&"b"));
+    assert_eq!(frozen.get_key_value(&3), Some((&3, &"c")));
+    assert_eq!(frozen.get(&4), None);
+    assert!(frozen.contains_key(&1));
+    assert!(!frozen.contains_key(&0));
+    assert_eq!(frozen[&1], "a");
+    assert_eq!(frozen.first_key_value(), Some((&1, &"a")));
+    assert_eq!(frozen.last_key_value(), Some((&3, &"c")));
+    assert_eq!(format!("{:?}", frozen), r#"{1: "a", 2: "b", 3: "c"}"#);
+    assert_eq!(frozen.thaw(), map);
+
+    let empty = FlatMap::<u8, u8>::new().freeze();
+    assert!(empty.is_empty());
+    assert_eq!(empty.get(&0), None);
+    assert_eq!(empty.iter().next(), None);
+    assert_eq!(empty.range(..).next(), None);
+}
+
+// Every tree shape up to a few full levels, checked against FlatMap.
+#[test]
+fn test_matches_flat_map() {
+    for n in 0..40 {
+        let map: FlatMap<_, _> = (0..n).map(|i| (i * 2, i)).collect();
+        let frozen = FrozenFlatMap::from(map.clone());
+        assert!(frozen.iter().eq(map.iter()));
+        assert!(frozen.iter().rev().eq(map.iter().rev()));
+        assert_eq!(frozen.iter().len(), map.len());
+        for q in -1..2 * n + 1 {
+            assert_eq!(frozen.get(&q), map.get(&q));
+        }
+        for a in -1..2 * n + 1 {
+            for b in a..2 * n + 1 {
+                let bounds = [
+                    (Included(a), Included(b)),
+                    (Included(a), Excluded(b)),
+                    (Excluded(a), Included(b)),
+                    (Unbounded, Included(b)),
+                    (Excluded(a), Unbounded),
+                ];
+                for &r in &bounds {
+                    if a == b && r == (Excluded(a), Included(b)) {
+                        continue;
+                    }
+                    assert!(frozen.range(r).eq(map.range(r)), "n={} {:?}", n, r);
+                    assert!(frozen.range(r).rev().eq(map.range(r).rev()));
+                }
+            }
+        }
+        assert_eq!(FlatMap::from(frozen), map);
+    }
+}
+
+#[test]
+fn test_range_meets_in_middle() {
+    let frozen = (0..10).map(|i| (i, ())).collect::<FlatMap<_, _>>().freeze();
+    let mut r = frozen.range(2..6);
+    assert_eq!(r.next().map(|(&k, _)| k), Some(2));
+    assert_eq!(r.next_back().map(|(&k, _)| k), Some(5));
+    assert_eq!(r.next().map(|(&k, _)| k), Some(3));
+    assert_eq!(r.next_back().map(|(&k, _)| k), Some(4));
+    assert_eq!(r.next(), None);
+    assert_eq!(r.next_back(), None);
+}
+
+#[test]
+#[should_panic]
+fn test_range_backwards() {
+    let frozen = (0..10).map(|i| (i, ())).collect::<FlatMap<_, _>>().freeze();
+    frozen.range((Included(5), Included(2)));
+}
+
+#[test]
+fn test_drops() {
+    let value = Rc::new(());
+    let map: FlatMap<_, _> = (0..20).map(|i| (i, value.clone())).collect();
+    let frozen = map.freeze();
+    assert_eq!(Rc::strong_count(&value), 21);
+    let map = frozen.clone().thaw();
+    assert_eq!(Rc::strong_count(&value), 41);
+    drop(frozen);
+    assert_eq!(Rc::strong_count(&value), 21);
+    drop(map);
+    assert_eq!(Rc::strong_count(&value), 1);
+}
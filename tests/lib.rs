@@ -1,6 +1,14 @@
-#[cfg(any(feature = "serde"))]
+#![cfg(feature = "std")]
+// The tests below predate clippy's current lint set; keep them as written.
+#![allow(
+    clippy::assertions_on_constants,
+    clippy::double_ended_iterator_last,
+    clippy::non_canonical_partial_ord_impl
+)]
+
+#[cfg(feature = "serde")]
 extern crate serde;
-#[cfg(any(feature = "serde"))]
+#[cfg(feature = "serde")]
 extern crate serde_json;
 
 extern crate flat_map;
@@ -9,6 +17,7 @@ use flat_map::FlatMap;
 use flat_map::Occupied;
 use flat_map::Vacant;
 use std::iter::FromIterator;
+use std::ops::Bound::{Excluded, Included, Unbounded};
 
 use std::rc::Rc;
 
@@ -300,6 +309,173 @@ fn test_entry() {
     assert_eq!(map.len(), 6);
 }
 
+#[test]
+fn test_entry_api() {
+    let mut map: FlatMap<String, Vec<u32>> = FlatMap::new();
+    map.entry("b".to_string()).or_default().push(1);
+    map.entry("b".to_string()).or_default().push(2);
+    let len = map
+        .entry("abc".to_string())
+        .or_insert_with_key(|k| vec![k.len() as u32]);
+    assert_eq!(len, &[3]);
+    assert_eq!(map["b"], [1, 2]);
+
+    map.entry("b".to_string())
+        .and_modify(|v| v.clear())
+        .or_default()
+        .push(3);
+    map.entry("c".to_string())
+        .and_modify(|v| v.push(0))
+        .or_default();
+    assert_eq!(map["b"], [3]);
+    assert!(map["c"].is_empty());
+
+    assert_eq!(map.entry("a".to_string()).key(), "a");
+    assert_eq!(map.entry("b".to_string()).key(), "b");
+    match map.entry("a".to_string()) {
+        Vacant(e) => {
+            assert_eq!(e.key(), "a");
+            assert_eq!(e.into_key(), "a");
+        }
+        Occupied(_) => panic!(),
+    }
+    assert!(!map.contains_key("a"));
+
+    let entry = map.entry("a".to_string()).insert_entry(vec![9]);
+    assert_eq!(entry.key(), "a");
+    assert_eq!(entry.index(), 0);
+    let mut entry = map.entry("a".to_string()).insert_entry(vec![8]);
+    assert_eq!(entry.get_mut(), &[8]);
+    match map.entry("abc".to_string()) {
+        Occupied(e) => assert_eq!(e.remove_entry(), ("abc".to_string(), vec![3])),
+        Vacant(_) => panic!(),
+    }
+    assert_eq!(map.len(), 3);
+
+    let mut map: FlatMap<_, _> = vec![(1, 'a')].into_iter().collect();
+    assert_eq!(
+        format!("{:?}", map.entry(1)),
+        "Entry(OccupiedEntry { key: 1, value: 'a' })"
+    );
+    assert_eq!(format!("{:?}", map.entry(2)), "Entry(VacantEntry(2))");
+}
+
+#[test]
+fn test_entry_replace_key() {
+    let old = Rc::new(1);
+    let new = Rc::new(1);
+    let mut map = FlatMap::new();
+    map.insert(old.clone(), ());
+    match map.entry(new.clone()) {
+        Occupied(e) => assert!(Rc::ptr_eq(&e.replace_key().unwrap(), &old)),
+        Vacant(_) => panic!(),
+    }
+    assert_eq!(Rc::strong_count(&old), 1);
+    assert!(Rc::ptr_eq(map.keys().next().unwrap(), &new));
+}
+
+#[test]
+fn test_entry_replace_key_without_key() {
+    let mut map: FlatMap<_, _> = vec![(1, 'a')].into_iter().collect();
+    assert_eq!(map.first_entry().unwrap().replace_key(), None);
+    assert_eq!(map.last_entry().unwrap().replace_key(), None);
+    assert_eq!(map.entry(2).insert_entry('b').replace_key(), None);
+    assert!(map.iter().eq(vec![(&1, &'a'), (&2, &'b')]));
+}
+
+#[test]
+fn test_try_insert() {
+    let mut map = FlatMap::new();
+    *map.try_insert("a", 1).unwrap() += 1;
+    assert!(map.insert_new("b", 2));
+    assert!(!map.insert_new("b", 3));
+    assert_eq!(map["b"], 2);
+
+    let err = map.try_insert("a", 10).unwrap_err();
+    assert_eq!(err.key(), &"a");
+    assert_eq!(err.value(), &10);
+    assert_eq!(err.entry().get(), &2);
+    assert_eq!(
+        err.to_string(),
+        r#"failed to insert 10, key "a" already exists with value 2"#
+    );
+    let (entry, value) = err.into_entry();
+    *entry.into_mut() += value * 10;
+    assert_eq!(map["a"], 102);
+    assert_eq!(
+        map.try_insert("a", 0).unwrap_err().into_key_value(),
+        ("a", 0)
+    );
+    assert_eq!(map.len(), 2);
+
+    let old = Rc::new(1);
+    let new = Rc::new(1);
+    let mut map = FlatMap::new();
+    map.insert(old.clone(), ());
+    let (entry, ()) = map.try_insert(new.clone(), ()).unwrap_err().into_entry();
+    assert!(Rc::ptr_eq(&entry.replace_key().unwrap(), &old));
+    assert_eq!(Rc::strong_count(&new), 2);
+}
+
+#[test]
+fn test_try_extend() {
+    let mut map: FlatMap<_, _> = (0..10).map(|i| (i * 10, i)).collect();
+    map.try_extend(vec![(5, 0), (95, 0), (-5, 0)]).unwrap();
+    map.try_extend(Vec::new()).unwrap();
+    assert_eq!(map.len(), 13);
+    assert!(map.keys().take(3).eq(&[-5, 0, 5]));
+
+    let before = map.clone();
+    let err = map
+        .try_extend(vec![
+            (7, 0),
+            (90, 0),
+            (7, 1),
+            (1, 0),
+            (-5, 0),
+            (7, 2),
+            (8, 0),
+        ])
+        .unwrap_err();
+    assert_eq!(
+        err.conflicting(),
+        &[(-5, 0), (7, 0), (7, 1), (7, 2), (90, 0)]
+    );
+    assert_eq!(err.rest(), &[(1, 0), (8, 0)]);
+    assert_eq!(map, before);
+
+    let counter = Rc::new(());
+    let mut map: FlatMap<_, _> = (0..10).map(|i| (i, counter.clone())).collect();
+    let batch: Vec<_> = (5..15).map(|i| (i, counter.clone())).collect();
+    let (conflicting, rest) = map.try_extend(batch).unwrap_err().into_parts();
+    assert!(conflicting.iter().map(|(k, _)| *k).eq(5..10));
+    assert!(rest.iter().map(|(k, _)| *k).eq(10..15));
+    assert_eq!(Rc::strong_count(&counter), 21);
+    drop((conflicting, rest));
+    assert_eq!(Rc::strong_count(&counter), 11);
+}
+
+#[test]
+fn test_extend_keeps_key() {
+    // Both the one-by-one path and the sorted merge keep the stored key and
+    // take only the value, as BTreeMap does. `append` takes the whole pair.
+    for &n in &[1, 20] {
+        let old = Rc::new(0);
+        let mut map: FlatMap<_, _> = vec![(old.clone(), 'a')].into_iter().collect();
+        map.extend((0..n).map(|i| (Rc::new(i), 'b')));
+        assert_eq!(map.len(), n as usize);
+        assert!(Rc::ptr_eq(map.keys().next().unwrap(), &old));
+        assert_eq!(map[&0], 'b');
+
+        let new = Rc::new(0);
+        let mut other: FlatMap<_, _> = vec![(new.clone(), 'c')].into_iter().collect();
+        map.append(&mut other);
+        assert!(Rc::ptr_eq(map.keys().next().unwrap(), &new));
+        assert_eq!(Rc::strong_count(&old), 1);
+        assert_eq!(map[&0], 'c');
+    }
+}
+
 #[test]
 fn test_extend_ref() {
     let mut a = FlatMap::new();
@@ -429,7 +605,110 @@ fn test_retain() {
 }
 
 #[test]
-fn test_index(){
+fn test_retain_mut() {
+    let mut map: FlatMap<_, u32> = (0..10).map(|i| (i, i % 3)).collect();
+    map.retain_mut(|_, count| {
+        *count = count.saturating_sub(1);
+        *count > 0
+    });
+    assert!(map.into_iter().eq(vec![(2, 1), (5, 1), (8, 1)]));
+
+    let mut map: FlatMap<_, _> = (0..10).map(|i| (i, i)).collect();
+    map.retain_range(4.., |&k, v| {
+        *v *= 10;
+        k % 2 == 1
+    });
+    assert!(map.keys().eq(&[0, 1, 2, 3, 5, 7, 9]));
+    assert_eq!(map[&3], 3);
+    assert_eq!(map[&5], 50);
+    map.retain_range(100..200, |_, _| false);
+    map.retain_range(..=1, |_, _| false);
+    assert!(map.keys().eq(&[2, 3, 5, 7, 9]));
+}
+
+// `f` panics on key 6 after rejecting some of the keys before it.
+fn retain_until_panic<F: FnOnce(&mut dyn FnMut(&i32, &mut Rc<()>) -> bool)>(retain: F) {
+    retain(&mut |&k, _| {
+        assert!(k != 6);
+        k % 2 == 1
+    })
+}
+
+#[test]
+fn test_retain_mut_panic_safety() {
+    use flat_map::BoxedFlatMap;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    let counter = Rc::new(());
+    let mut map: FlatMap<_, _> = (0..10).map(|i| (i, counter.clone())).collect();
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        retain_until_panic(|f| map.retain_mut(f));
+    }));
+    assert!(result.is_err());
+    assert!(map.keys().eq(&[1, 3, 5, 6, 7, 8, 9]));
+    assert_eq!(Rc::strong_count(&counter), 1 + map.len());
+
+    map = (0..10).map(|i| (i, counter.clone())).collect();
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        retain_until_panic(|f| map.retain_range(3..8, f));
+    }));
+    assert!(result.is_err());
+    assert!(map.keys().eq(&[0, 1, 2, 3, 5, 6, 7, 8, 9]));
+    assert_eq!(Rc::strong_count(&counter), 1 + map.len());
+
+    drop(map);
+
+    let mut map: BoxedFlatMap<_, _> = (0..10).map(|i| (i, counter.clone())).collect();
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        retain_until_panic(|f| map.retain_mut(f));
+    }));
+    assert!(result.is_err());
+    assert!(map.keys().eq(&[1, 3, 5, 6, 7, 8, 9]));
+    assert_eq!(Rc::strong_count(&counter), 1 + map.len());
+
+    map = (0..10).map(|i| (i, counter.clone())).collect();
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        retain_until_panic(|f| map.retain_range(3..8, f));
+    }));
+    assert!(result.is_err());
+    assert!(map.keys().eq(&[0, 1, 2, 3, 5, 6, 7, 8, 9]));
+    assert_eq!(Rc::strong_count(&counter), 1 + map.len());
+}
+
+#[cfg(feature = "smallvec")]
+#[test]
+fn test_retain_mut_panic_safety_small_storage() {
+    use flat_map::SmallFlatMap;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    let counter = Rc::new(());
+    for &n in &[10, 20] {
+        let mut map: SmallFlatMap<_, _, 16> = (0..n).map(|i| (i, counter.clone())).collect();
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            retain_until_panic(|f| map.retain_mut(f));
+        }));
+        assert!(result.is_err());
+        assert!(map
+            .keys()
+            .cloned()
+            .eq(vec![1, 3, 5].into_iter().chain(6..n)));
+        assert_eq!(Rc::strong_count(&counter), 1 + map.len());
+
+        map = (0..n).map(|i| (i, counter.clone())).collect();
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            retain_until_panic(|f| map.retain_range(3..8, f));
+        }));
+        assert!(result.is_err());
+        assert!(map
+            .keys()
+            .cloned()
+            .eq(vec![0, 1, 2, 3, 5].into_iter().chain(6..n)));
+        assert_eq!(Rc::strong_count(&counter), 1 + map.len());
+    }
+}
+
+#[test]
+fn test_index() {
     let mut map = FlatMap::new();
 
     map.insert("a", 1);
@@ -437,7 +716,6 @@ fn test_index(){
 
     map[&"a"] = 2;
     assert_eq!(map[&"a"], 2);
-
 }
 
 macro_rules! create_append_test {
@@ -577,3 +855,854 @@ fn test_serde() {
     let new_map: FlatMap<u64, u64> = serde_json::from_str(&json).unwrap();
     assert_eq!(new_map.get(&18), map.get(&18));
 }
+
+#[test]
+fn test_range() {
+    let size = 200;
+    let map: FlatMap<_, _> = (0..size).map(|i| (i * 2, i)).collect();
+
+    assert_eq!(map.range(..).count(), size);
+    assert!(map
+        .range(10..20)
+        .map(|(&k, _)| k)
+        .eq(vec![10, 12, 14, 16, 18]));
+    assert!(map
+        .range(11..=20)
+        .map(|(&k, _)| k)
+        .eq(vec![12, 14, 16, 18, 20]));
+    assert!(map.range(..4).map(|(&k, _)| k).eq(vec![0, 2]));
+    assert!(map.range(395..).map(|(&k, _)| k).eq(vec![396, 398]));
+    assert_eq!(map.range(1000..).count(), 0);
+    assert_eq!(map.range(5..5).count(), 0);
+    assert_eq!(map.range(5..=5).count(), 0);
+    assert_eq!(map.range(6..=6).count(), 1);
+
+    {
+        use std::ops::Bound::{Excluded, Included};
+        assert!(map
+            .range((Excluded(10), Included(14)))
+            .map(|(&k, _)| k)
+            .eq(vec![12, 14]));
+    }
+
+    let mut range = map.range(100..120);
+    assert_eq!(range.len(), 10);
+    assert_eq!(range.next(), Some((&100, &50)));
+    assert_eq!(range.next_back(), Some((&118, &59)));
+    assert_eq!(range.len(), 8);
+}
+
+#[test]
+fn test_range_borrow() {
+    use std::ops::Bound::{Excluded, Included};
+    let map: FlatMap<String, usize> = ["a", "b", "c", "d"]
+        .iter()
+        .enumerate()
+        .map(|(i, s)| (s.to_string(), i))
+        .collect();
+    let keys: Vec<_> = map
+        .range::<str, _>((Included("b"), Excluded("d")))
+        .map(|(k, _)| k.as_str())
+        .collect();
+    assert_eq!(keys, ["b", "c"]);
+}
+
+#[test]
+fn test_range_mut() {
+    let mut map: FlatMap<_, _> = (0..10).map(|i| (i, i)).collect();
+    for (_, v) in map.range_mut(3..7) {
+        *v *= 10;
+    }
+    assert!(map
+        .values()
+        .cloned()
+        .eq(vec![0, 1, 2, 30, 40, 50, 60, 7, 8, 9]));
+    assert_eq!(map.range_mut(8..).next_back(), Some((&9, &mut 9)));
+}
+
+#[test]
+#[should_panic(expected = "range start is greater than range end")]
+fn test_range_backwards() {
+    let map: FlatMap<_, _> = (0..10).map(|i| (i, i)).collect();
+    #[allow(clippy::reversed_empty_ranges)]
+    map.range(5..3);
+}
+
+#[test]
+#[should_panic(expected = "range start and end are equal and excluded")]
+fn test_range_equal_excluded() {
+    use std::ops::Bound::Excluded;
+    let map: FlatMap<_, _> = (0..10).map(|i| (i, i)).collect();
+    map.range((Excluded(5), Excluded(5)));
+}
+
+#[test]
+fn test_first_last() {
+    let mut map: FlatMap<i32, i32> = FlatMap::new();
+    assert_eq!(map.first_key_value(), None);
+    assert_eq!(map.last_key_value(), None);
+    assert_eq!(map.pop_first(), None);
+    assert_eq!(map.pop_last(), None);
+    assert!(map.first_entry().is_none());
+    assert!(map.last_entry().is_none());
+
+    map.extend((1..6).map(|i| (i, i * 10)));
+    assert_eq!(map.first_key_value(), Some((&1, &10)));
+    assert_eq!(map.last_key_value(), Some((&5, &50)));
+    assert_eq!(map.pop_first(), Some((1, 10)));
+    assert_eq!(map.pop_last(), Some((5, 50)));
+    assert_eq!(map.len(), 3);
+
+    *map.first_entry().unwrap().get_mut() += 1;
+    assert_eq!(map[&2], 21);
+    {
+        let entry = map.last_entry().unwrap();
+        assert_eq!(entry.key(), &4);
+        assert_eq!(entry.remove(), 40);
+    }
+    assert!(map.into_iter().eq(vec![(2, 21), (3, 30)]));
+}
+
+#[test]
+fn test_append_interleaved() {
+    let mut a: FlatMap<_, _> = (0..100).filter(|i| i % 3 != 0).map(|i| (i, "a")).collect();
+    let mut b: FlatMap<_, _> = (0..100).filter(|i| i % 2 == 0).map(|i| (i, "b")).collect();
+    a.append(&mut b);
+    assert!(b.is_empty());
+    assert_eq!(
+        a.len(),
+        100 - (0..100).filter(|i| i % 3 == 0 && i % 2 != 0).count()
+    );
+    for (&k, &v) in &a {
+        assert!(k % 3 != 0 || k % 2 == 0);
+        assert_eq!(v, if k % 2 == 0 { "b" } else { "a" });
+    }
+    assert!(a.keys().zip(a.keys().skip(1)).all(|(x, y)| x < y));
+}
+
+#[test]
+fn test_append_disjoint_ranges() {
+    let mut a: FlatMap<_, _> = (0..10).map(|i| (i, i)).collect();
+    let mut b: FlatMap<_, _> = (10..20).map(|i| (i, i)).collect();
+    a.append(&mut b);
+    assert!(a.iter().map(|(&k, _)| k).eq(0..20));
+
+    let mut c: FlatMap<_, _> = (-10..0).map(|i| (i, i)).collect();
+    a.append(&mut c);
+    assert!(c.is_empty());
+    assert!(a.iter().map(|(&k, _)| k).eq(-10..20));
+
+    let mut empty = FlatMap::new();
+    empty.append(&mut a);
+    assert!(a.is_empty());
+    assert_eq!(empty.len(), 30);
+}
+
+#[test]
+fn test_append_drops() {
+    let counter = Rc::new(());
+    let mut a: FlatMap<_, _> = (0..10).map(|i| (i, counter.clone())).collect();
+    let mut b: FlatMap<_, _> = (5..15).map(|i| (i, counter.clone())).collect();
+    assert_eq!(Rc::strong_count(&counter), 21);
+    a.append(&mut b);
+    assert_eq!(a.len(), 15);
+    assert_eq!(Rc::strong_count(&counter), 16);
+    drop(a);
+    assert_eq!(Rc::strong_count(&counter), 1);
+}
+
+#[test]
+fn test_append_panic_safety() {
+    use flat_map::BoxedFlatMap;
+    use std::cell::Cell;
+    use std::cmp::Ordering;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    // Counts down on every comparison once armed and panics when it reaches one.
+    thread_local!(static FUSE: Cell<usize> = const { Cell::new(0) });
+
+    #[derive(PartialEq, Eq)]
+    struct Bomb(i32);
+
+    impl PartialOrd for Bomb {
+        fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+
+    impl Ord for Bomb {
+        fn cmp(&self, other: &Self) -> Ordering {
+            FUSE.with(|fuse| match fuse.get() {
+                0 => {}
+                1 => panic!("boom"),
+                n => fuse.set(n - 1),
+            });
+            self.0.cmp(&other.0)
+        }
+    }
+
+    let counter = Rc::new(());
+    let mut a = FlatMap::new();
+    let mut b = FlatMap::new();
+    for i in 0..20 {
+        a.insert(Bomb(i * 2), counter.clone());
+        b.insert(Bomb(i * 3), counter.clone());
+    }
+    FUSE.with(|fuse| fuse.set(12));
+    let result = catch_unwind(AssertUnwindSafe(|| a.append(&mut b)));
+    assert!(result.is_err());
+
+    let keys: Vec<_> = a.keys().map(|k| k.0).collect();
+    assert!(keys.windows(2).all(|w| w[0] < w[1]));
+    let keys: Vec<_> = b.keys().map(|k| k.0).collect();
+    assert!(keys.windows(2).all(|w| w[0] < w[1]));
+    assert_eq!(Rc::strong_count(&counter), 1 + a.len() + b.len());
+
+    FUSE.with(|fuse| fuse.set(0));
+    drop((a, b));
+    // Stores without spare capacity merge in a borrowed `Vec`, which must be
+    // put back on unwind.
+    let mut a: BoxedFlatMap<_, _> = (0..20).map(|i| (Bomb(i * 2), counter.clone())).collect();
+    let mut b: BoxedFlatMap<_, _> = (0..20).map(|i| (Bomb(i * 3), counter.clone())).collect();
+    FUSE.with(|fuse| fuse.set(12));
+    let result = catch_unwind(AssertUnwindSafe(|| a.append(&mut b)));
+    assert!(result.is_err());
+    assert!(a.len() >= 20);
+    let keys: Vec<_> = a.keys().map(|k| k.0).collect();
+    assert!(keys.windows(2).all(|w| w[0] < w[1]));
+    assert_eq!(Rc::strong_count(&counter), 1 + a.len() + b.len());
+}
+
+#[test]
+fn test_drain() {
+    let mut map: FlatMap<_, _> = (0..10).map(|i| (i, i * 10)).collect();
+    let mut drain = map.drain_range(3..7);
+    assert_eq!(drain.len(), 4);
+    assert_eq!(drain.next(), Some((3, 30)));
+    assert_eq!(drain.next_back(), Some((6, 60)));
+    drop(drain);
+    assert!(map.keys().eq(&[0, 1, 2, 7, 8, 9]));
+    assert_eq!(map.drain_range(100..).count(), 0);
+    assert!(map
+        .drain_range((Excluded(1), Unbounded))
+        .eq(vec![(2, 20), (7, 70), (8, 80), (9, 90)]));
+    assert!(map.drain().eq(vec![(0, 0), (1, 10)]));
+    assert!(map.is_empty());
+
+    let counter = Rc::new(());
+    let mut map: FlatMap<_, _> = (0..10).map(|i| (i, counter.clone())).collect();
+    map.drain_range(..=4).next();
+    assert_eq!(Rc::strong_count(&counter), 6);
+    map.drain();
+    assert_eq!(Rc::strong_count(&counter), 1);
+}
+
+#[test]
+fn test_extract_if() {
+    let mut map: FlatMap<_, _> = (0..10).map(|i| (i, i)).collect();
+    let extracted: Vec<_> = map
+        .extract_if(|&k, v| {
+            *v += 100;
+            k % 3 == 0
+        })
+        .collect();
+    assert_eq!(extracted, [(0, 100), (3, 103), (6, 106), (9, 109)]);
+    assert!(map.iter().eq(vec![
+        (&1, &101),
+        (&2, &102),
+        (&4, &104),
+        (&5, &105),
+        (&7, &107),
+        (&8, &108)
+    ]));
+
+    // Untested entries are kept when the iterator is dropped early.
+    assert_eq!(map.extract_if(|_, _| true).take(2).count(), 2);
+    assert!(map.keys().eq(&[4, 5, 7, 8]));
+    let mut iter = map.extract_if(|&k, _| k == 5);
+    assert_eq!(iter.next(), Some((5, 105)));
+    drop(iter);
+    assert!(map.keys().eq(&[4, 7, 8]));
+    assert_eq!(map.extract_if(|_, _| false).count(), 0);
+    assert_eq!(map.len(), 3);
+}
+
+#[test]
+fn test_extract_if_panic_safety() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    let counter = Rc::new(());
+    let mut map: FlatMap<_, _> = (0..10).map(|i| (i, counter.clone())).collect();
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        map.extract_if(|&k, _| {
+            assert!(k != 6);
+            k % 2 == 0
+        })
+        .for_each(drop)
+    }));
+    assert!(result.is_err());
+    assert!(map.keys().eq(&[1, 3, 5, 6, 7, 8, 9]));
+    assert_eq!(Rc::strong_count(&counter), 1 + map.len());
+}
+
+#[test]
+fn test_extend_bulk() {
+    let mut data = rand_data(1000);
+    // repeat some keys so the later value has to win
+    let repeats: Vec<_> = data.iter().step_by(7).map(|&(k, v)| (k, v ^ 1)).collect();
+    data.extend(repeats);
+
+    let mut map: FlatMap<_, _> = (0..50u32).map(|i| (i, i)).collect();
+    let mut expected: std::collections::BTreeMap<_, _> = map.clone().into_iter().collect();
+    map.extend(data.clone());
+    expected.extend(data);
+
+    assert_eq!(map.len(), expected.len());
+    assert!(map.into_iter().eq(expected));
+}
+
+#[test]
+fn test_extend_small() {
+    let mut map: FlatMap<_, _> = (0..10).map(|i| (i * 2, i)).collect();
+    map.extend(vec![(3, 30), (2, 20), (3, 31)]);
+    assert_eq!(map.len(), 11);
+    assert_eq!(map[&2], 20);
+    assert_eq!(map[&3], 31);
+
+    // an iterator without an upper bound takes the bulk path
+    let mut keys = 0..3;
+    map.extend(std::iter::from_fn(|| keys.next().map(|i| (i, 100))));
+    assert_eq!(map[&0], 100);
+    assert_eq!(map[&1], 100);
+    assert_eq!(map.len(), 12);
+}
+
+#[test]
+fn test_split_off_missing_key() {
+    let mut map: FlatMap<_, _> = (0..10).map(|i| (i * 10, i)).collect();
+    let right = map.split_off(&35);
+    assert!(map.keys().cloned().eq(vec![0, 10, 20, 30]));
+    assert!(right.keys().cloned().eq(vec![40, 50, 60, 70, 80, 90]));
+
+    let mut map: FlatMap<String, i32> = FlatMap::new();
+    map.insert("apple".to_string(), 1);
+    map.insert("cherry".to_string(), 2);
+    let right = map.split_off("banana");
+    assert_eq!(map.len(), 1);
+    assert_eq!(right["cherry"], 2);
+}
+
+#[test]
+fn test_split_off_index() {
+    let mut map: FlatMap<_, _> = (0..10).map(|i| (i, i)).collect();
+    let right = map.split_off_index(7);
+    assert!(map.keys().cloned().eq(0..7));
+    assert!(right.keys().cloned().eq(7..10));
+    let empty = map.split_off_index(7);
+    assert!(empty.is_empty());
+}
+
+#[test]
+#[should_panic]
+fn test_split_off_index_out_of_bounds() {
+    let mut map: FlatMap<_, _> = (0..10).map(|i| (i, i)).collect();
+    map.split_off_index(11);
+}
+
+#[test]
+fn test_positional() {
+    let mut map: FlatMap<_, _> = (0..10).map(|i| (i * 10, i)).collect();
+    assert_eq!(map.get_index(3), Some((&30, &3)));
+    assert_eq!(map.get_index(10), None);
+    *map.get_index_mut(3).unwrap().1 += 100;
+    assert_eq!(map[&30], 103);
+    assert_eq!(map.get_index_mut(10), None);
+
+    assert_eq!(map.index_of(&30), Some(3));
+    assert_eq!(map.index_of(&35), None);
+    assert_eq!(map.rank(&30), 3);
+    assert_eq!(map.rank(&35), 4);
+    assert_eq!(map.rank(&-1), 0);
+    assert_eq!(map.rank(&1000), 10);
+    for (i, k) in map.keys().enumerate() {
+        assert_eq!(map.index_of(k), Some(i));
+        assert_eq!(map.get_index(map.rank(k)).map(|(k, _)| k), Some(k));
+    }
+
+    assert!(map.index_range(2..5).map(|(&k, _)| k).eq(vec![20, 30, 40]));
+    assert!(map.index_range(8..).rev().map(|(&k, _)| k).eq(vec![90, 80]));
+    assert_eq!(map.index_range(..).len(), 10);
+    assert_eq!(map.index_range(10..).len(), 0);
+    for (_, v) in map.index_range_mut(..=1) {
+        *v = 0;
+    }
+    assert!(map.values().take(3).eq(&[0, 0, 2]));
+
+    assert_eq!(map.remove_index(0), Some((0, 0)));
+    assert_eq!(map.remove_index(9), None);
+    assert_eq!(map.get_index(0), Some((&10, &0)));
+
+    match map.entry(25) {
+        Vacant(e) => assert_eq!(e.index(), 2),
+        Occupied(_) => panic!(),
+    }
+    match map.entry(30) {
+        Occupied(e) => assert_eq!(e.index(), 2),
+        Vacant(_) => panic!(),
+    }
+    assert_eq!(map.entry(95).index(), 9);
+}
+
+#[test]
+#[should_panic]
+fn test_index_range_out_of_bounds() {
+    let map: FlatMap<_, _> = (0..10).map(|i| (i, i)).collect();
+    map.index_range(5..11);
+}
+
+#[test]
+fn test_cursor() {
+    let map: FlatMap<_, _> = (1..=5).map(|i| (i * 10, i)).collect();
+    let mut cursor = map.cursor_front();
+    assert_eq!(cursor.key_value(), Some((&10, &1)));
+    assert_eq!(cursor.peek_prev(), None);
+    assert_eq!(cursor.peek_next(), Some((&20, &2)));
+    cursor.move_prev();
+    assert_eq!(cursor.key(), None);
+    assert_eq!(cursor.index(), None);
+    assert_eq!(cursor.peek_prev(), Some((&50, &5)));
+    assert_eq!(cursor.peek_next(), Some((&10, &1)));
+    cursor.move_prev();
+    assert_eq!(cursor.key(), Some(&50));
+    assert_eq!(cursor.index(), Some(4));
+    cursor.move_next();
+    cursor.move_next();
+    assert_eq!(cursor.value(), Some(&1));
+    assert_eq!(format!("{:?}", cursor), "Cursor(Some((10, 1)))");
+
+    assert_eq!(map.cursor_back().key(), Some(&50));
+    assert_eq!(map.lower_bound(Included(&30)).key(), Some(&30));
+    assert_eq!(map.lower_bound(Excluded(&30)).key(), Some(&40));
+    assert_eq!(map.lower_bound(Included(&35)).key(), Some(&40));
+    assert_eq!(map.lower_bound(Excluded(&50)).key(), None);
+    assert_eq!(map.lower_bound(Unbounded).key(), Some(&10));
+    assert_eq!(map.upper_bound(Included(&30)).key(), Some(&30));
+    assert_eq!(map.upper_bound(Excluded(&30)).key(), Some(&20));
+    assert_eq!(map.upper_bound(Included(&35)).key(), Some(&30));
+    assert_eq!(map.upper_bound(Excluded(&10)).key(), None);
+    assert_eq!(map.upper_bound(Unbounded).key(), Some(&50));
+
+    let empty = FlatMap::<i32, i32>::new();
+    let mut cursor = empty.cursor_back();
+    assert_eq!(cursor.key(), None);
+    cursor.move_next();
+    cursor.move_prev();
+    assert_eq!(cursor.peek_next(), None);
+    assert_eq!(cursor.peek_prev(), None);
+}
+
+#[test]
+fn test_cursor_mut() {
+    let mut map: FlatMap<_, _> = (1..=5).map(|i| (i * 10, i)).collect();
+    {
+        let mut cursor = map.lower_bound_mut(Included(&25));
+        assert_eq!(cursor.key(), Some(&30));
+        *cursor.value_mut().unwrap() += 100;
+        *cursor.peek_prev().unwrap().1 += 100;
+        cursor.insert_before(25, 0);
+        cursor.insert_after(35, 0);
+        assert_eq!(cursor.index(), Some(3));
+        assert_eq!(cursor.remove_current(), Some((30, 103)));
+        assert_eq!(cursor.key(), Some(&35));
+        assert_eq!(cursor.remove_current_and_move_back(), Some((35, 0)));
+        assert_eq!(cursor.key(), Some(&25));
+        cursor.move_next();
+        assert_eq!(cursor.as_cursor().peek_next(), Some((&50, &5)));
+    }
+    assert!(map.keys().eq(&[10, 20, 25, 40, 50]));
+    assert_eq!(map[&20], 102);
+
+    // At the ghost position, inserts go at either end.
+    {
+        let mut cursor = map.cursor_back_mut();
+        cursor.move_next();
+        assert_eq!(cursor.key(), None);
+        cursor.insert_after(0, 0);
+        cursor.insert_before(60, 0);
+        assert_eq!(cursor.key(), None);
+        assert_eq!(cursor.remove_current(), None);
+        assert_eq!(cursor.peek_next(), Some((&0, &mut 0)));
+        assert_eq!(cursor.peek_prev(), Some((&60, &mut 0)));
+        cursor.move_next();
+        assert_eq!(cursor.remove_current_and_move_back(), Some((0, 0)));
+        assert_eq!(cursor.key(), None);
+    }
+    assert!(map.keys().eq(&[10, 20, 25, 40, 50, 60]));
+
+    // Draining the map through a cursor.
+    let mut cursor = map.cursor_front_mut();
+    while cursor.remove_current().is_some() {}
+    assert_eq!(cursor.key(), None);
+    cursor.insert_after(1, 1);
+    assert!(map.into_iter().eq(vec![(1, 1)]));
+}
+
+#[test]
+#[should_panic(expected = "key does not sort before the entry after it")]
+fn test_cursor_insert_out_of_order() {
+    let mut map: FlatMap<_, _> = (1..=5).map(|i| (i * 10, i)).collect();
+    map.cursor_front_mut().insert_after(20, 0);
+}
+
+#[test]
+fn test_cursor_insert_unchecked() {
+    let mut map: FlatMap<_, _> = (1..=3).map(|i| (i * 10, i)).collect();
+    let mut cursor = map.upper_bound_mut(Excluded(&20));
+    unsafe {
+        cursor.insert_after_unchecked(15, 0);
+        cursor.insert_before_unchecked(5, 0);
+    }
+    assert_eq!(map.keys().cloned().collect::<Vec<_>>(), [5, 10, 15, 20, 30]);
+}
+
+#[test]
+fn test_split_at() {
+    let map: FlatMap<_, _> = (0..10).map(|i| (i * 10, i)).collect();
+    let (left, right) = map.split_at(&45);
+    assert_eq!(left.len(), 5);
+    assert_eq!(right.len(), 5);
+    assert_eq!(left.get(&40), Some(&4));
+    assert_eq!(left.get(&50), None);
+    assert_eq!(right.get(&50), Some(&5));
+    assert!(right.contains_key(&90));
+    assert_eq!(left.last_key_value(), Some((&40, &4)));
+    assert_eq!(right.first_key_value(), Some((&50, &5)));
+    assert!(right.range(60..80).map(|(&k, _)| k).eq(vec![60, 70]));
+    assert!(left.keys().cloned().eq(vec![0, 10, 20, 30, 40]));
+    assert_eq!(format!("{:?}", map.split_at(&10).0), "{0: 0}");
+
+    let (left, right) = map.split_at(&1000);
+    assert_eq!(left.len(), 10);
+    assert!(right.is_empty());
+    assert_eq!(map.as_view().len(), 10);
+}
+
+#[test]
+fn test_from_iter_last_wins() {
+    let map: FlatMap<_, _> = vec![(1, "a"), (2, "b"), (1, "c"), (1, "d")]
+        .into_iter()
+        .collect();
+    assert_eq!(map.len(), 2);
+    assert_eq!(map[&1], "d");
+    assert_eq!(map[&2], "b");
+
+    let data = rand_data(500);
+    let map = FlatMap::from_iter(data.iter().map(|&(k, v)| (k % 50, v)));
+    let expected: std::collections::BTreeMap<_, _> =
+        data.iter().map(|&(k, v)| (k % 50, v)).collect();
+    assert!(map.into_iter().eq(expected));
+}
+
+#[test]
+fn test_from_iter_merge() {
+    let words = "the quick fox jumps over the lazy dog the end";
+    let counts = FlatMap::from_iter_merge(words.split(' ').map(|w| (w, 1)), |_, a, b| a + b);
+    assert_eq!(counts[&"the"], 3);
+    assert_eq!(counts[&"fox"], 1);
+    assert_eq!(counts.len(), 8);
+
+    let joined = FlatMap::from_iter_merge(
+        vec![
+            (1, "a".to_string()),
+            (2, "x".to_string()),
+            (1, "b".to_string()),
+        ],
+        |k, mut old, new| {
+            assert_eq!(*k, 1);
+            old.push_str(&new);
+            old
+        },
+    );
+    assert_eq!(joined[&1], "ab");
+    assert_eq!(joined[&2], "x");
+
+    let empty: FlatMap<i32, i32> = FlatMap::from_iter_merge(vec![], |_, a, _| a);
+    assert!(empty.is_empty());
+}
+
+#[test]
+fn test_try_from_iter() {
+    let map = FlatMap::try_from_iter(vec![(3, 'c'), (1, 'a'), (2, 'b')]).unwrap();
+    assert!(map.into_iter().eq(vec![(1, 'a'), (2, 'b'), (3, 'c')]));
+
+    let err =
+        FlatMap::try_from_iter(vec![(3, 'c'), (1, 'a'), (3, 'x'), (1, 'y'), (1, 'z')]).unwrap_err();
+    assert_eq!(err.keys(), &[1, 3]);
+    assert_eq!(err.to_string(), "2 key(s) occur more than once");
+    assert_eq!(err.into_keys(), vec![1, 3]);
+}
+
+#[test]
+fn test_from_sorted_vec() {
+    let v: Vec<_> = (0..100).map(|i| (i, i * 2)).collect();
+    let map = FlatMap::from_sorted_vec(v.clone()).unwrap();
+    assert_eq!(map[&50], 100);
+    assert_eq!(map.into_inner(), v);
+
+    assert!(FlatMap::<i32, i32>::from_sorted_vec(vec![]).is_ok());
+    let err = FlatMap::from_sorted_vec(vec![(1, 1), (3, 3), (2, 2)]).unwrap_err();
+    assert_eq!(err.index(), 2);
+    assert_eq!(err.to_string(), "entry 2 is out of order");
+    let err = FlatMap::from_sorted_vec(vec![(1, 1), (1, 1)]).unwrap_err();
+    assert_eq!(err.index(), 1);
+
+    let map = unsafe { FlatMap::from_sorted_vec_unchecked(vec![(1, 'a'), (5, 'e')]) };
+    assert_eq!(map.get(&5), Some(&'e'));
+}
+
+#[test]
+fn test_builder() {
+    use flat_map::FlatMapBuilder;
+
+    let mut builder = FlatMapBuilder::with_capacity(3);
+    assert!(builder.is_empty());
+    builder.push("a", 1).unwrap();
+    builder.push("c", 3).unwrap();
+    let (err, rejected) = builder.push("b", 2).unwrap_err();
+    assert_eq!(err.index(), 2);
+    assert_eq!(rejected, ("b", 2));
+    assert_eq!(builder.push("c", 4).unwrap_err().0.index(), 2);
+    builder.push("d", 4).unwrap();
+    assert_eq!(builder.len(), 3);
+
+    let map = builder.build();
+    assert!(map.into_iter().eq(vec![("a", 1), ("c", 3), ("d", 4)]));
+}
+
+#[test]
+fn test_reversed() {
+    use flat_map::{FlatMapBy, Reversed};
+    use std::ops::Bound::Included;
+
+    let mut map: FlatMapBy<_, _, Reversed> = (0..10).map(|i| (i, i * 10)).collect();
+    assert!(map.keys().cloned().eq((0..10).rev()));
+    assert_eq!(map.insert(4, 0), Some(40));
+    assert_eq!(map.insert(20, 200), None);
+    assert_eq!(map.first_key_value(), Some((&20, &200)));
+    assert_eq!(map.get(&4), Some(&0));
+    assert_eq!(map.remove(&5), Some(50));
+    assert!(map
+        .range((Included(7), Included(2)))
+        .map(|(&k, _)| k)
+        .eq(vec![7, 6, 4, 3, 2]));
+    *map.entry(3).or_insert(0) += 1;
+    assert_eq!(map[&3], 31);
+
+    let tail = map.split_off(&6);
+    assert!(map.keys().cloned().eq(vec![20, 9, 8, 7]));
+    assert!(tail.keys().cloned().eq(vec![6, 4, 3, 2, 1, 0]));
+
+    map.extend((0..20).map(|i| (i, i)));
+    assert!(map.keys().cloned().eq((0..21).rev()));
+    assert_eq!(map[&8], 8);
+}
+
+#[test]
+#[should_panic]
+fn test_reversed_range_backwards() {
+    use flat_map::{FlatMapBy, Reversed};
+
+    let map: FlatMapBy<i32, i32, Reversed> = FlatMapBy::new();
+    map.range(2..7);
+}
+
+#[derive(Clone, Copy, Default)]
+struct CaseInsensitive;
+
+impl flat_map::Comparator<str> for CaseInsensitive {
+    fn compare(&self, a: &str, b: &str) -> std::cmp::Ordering {
+        let a = a.chars().flat_map(char::to_lowercase);
+        let b = b.chars().flat_map(char::to_lowercase);
+        a.cmp(b)
+    }
+}
+
+impl flat_map::Comparator<String> for CaseInsensitive {
+    fn compare(&self, a: &String, b: &String) -> std::cmp::Ordering {
+        flat_map::Comparator::<str>::compare(self, a, b)
+    }
+}
+
+#[test]
+fn test_comparator_borrow() {
+    use flat_map::FlatMapBy;
+
+    let mut map: FlatMapBy<String, i32, CaseInsensitive> = vec![
+        ("b".to_string(), 1),
+        ("A".to_string(), 2),
+        ("B".to_string(), 3),
+    ]
+    .into_iter()
+    .collect();
+    assert_eq!(map.len(), 2);
+    assert_eq!(map.get("a"), Some(&2));
+    assert_eq!(map["b"], 3);
+    assert!(map.contains_key("A"));
+    assert_eq!(map.insert("C".to_string(), 4), None);
+    assert_eq!(map.insert("c".to_string(), 5), Some(4));
+    assert!(map.keys().eq(vec!["A", "B", "C"]));
+    assert_eq!(map.remove_entry("b"), Some(("B".to_string(), 3)));
+
+    let map = FlatMapBy::<_, _, CaseInsensitive>::try_from_iter(vec![
+        ("x".to_string(), 1),
+        ("X".to_string(), 2),
+    ]);
+    assert_eq!(map.unwrap_err().keys(), &["x".to_string()]);
+}
+
+#[test]
+fn test_comparator_fn() {
+    use flat_map::FlatMapBy;
+
+    let mut map = FlatMapBy::with_comparator(f64::total_cmp);
+    for &x in &[2.5, -0.0, f64::NAN, 0.0, -1.0, f64::INFINITY] {
+        map.insert(x, x.to_string());
+    }
+    assert_eq!(map.len(), 6);
+    assert_eq!(map.get(&0.0).map(String::as_str), Some("0"));
+    assert_eq!(map.get(&-0.0).map(String::as_str), Some("-0"));
+    assert!(map.get(&f64::NAN).is_some());
+    assert_eq!(map.last_key_value().unwrap().1, "NaN");
+    assert!(map.keys().take(3).eq(&[-1.0, -0.0, 0.0]));
+
+    let mut builder = flat_map::FlatMapBuilder::with_comparator(|a: &i32, b: &i32| b.cmp(a));
+    builder.push(3, 'c').unwrap();
+    assert!(builder.push(4, 'd').is_err());
+    builder.push(1, 'a').unwrap();
+    let map = builder.build();
+    assert_eq!(map.as_view().get(&1), Some(&'a'));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde_comparator() {
+    use flat_map::{FlatMapBy, Reversed};
+
+    let map: FlatMapBy<u64, u64, Reversed> =
+        serde_json::from_str(r#"{"1":1,"3":3,"2":2}"#).unwrap();
+    assert!(map.keys().cloned().eq(vec![3, 2, 1]));
+    assert_eq!(
+        serde_json::to_string(&map).unwrap(),
+        r#"{"3":3,"2":2,"1":1}"#
+    );
+}
+
+// Closures have no `Default`, so these need the `_with_comparator` forms.
+#[test]
+fn test_constructors_with_comparator() {
+    use flat_map::FlatMapBy;
+
+    let desc = |a: &i32, b: &i32| b.cmp(a);
+    let map: FlatMapBy<_, _, _> =
+        FlatMapBy::from_iter_with_comparator(vec![(1, 'a'), (3, 'c'), (1, 'A')], desc);
+    assert!(map.iter().eq(vec![(&3, &'c'), (&1, &'A')]));
+
+    let v = map.into_inner();
+    let map: FlatMapBy<_, _, _> =
+        FlatMapBy::from_sorted_vec_with_comparator(v.clone(), desc).unwrap();
+    assert_eq!(map.get(&1), Some(&'A'));
+    let unsorted: Result<FlatMapBy<_, _, _>, _> =
+        FlatMapBy::from_sorted_vec_with_comparator(v.into_iter().rev().collect(), desc);
+    assert_eq!(unsorted.unwrap_err().index(), 1);
+
+    let words = vec![("a", 1), ("b", 1), ("a", 1)];
+    let counts: FlatMapBy<_, _, _> = FlatMapBy::from_iter_merge_with_comparator(
+        words,
+        |_, x, y| x + y,
+        |a: &&str, b: &&str| b.cmp(a),
+    );
+    assert!(counts.iter().eq(vec![(&"b", &1), (&"a", &2)]));
+
+    let err =
+        FlatMapBy::<_, _, _>::try_from_iter_with_comparator(vec![(2, ()), (1, ()), (2, ())], desc)
+            .unwrap_err();
+    assert_eq!(err.keys(), &[2]);
+    let map: FlatMapBy<_, _, _> =
+        FlatMapBy::try_from_iter_with_comparator(vec![(2, ()), (1, ())], desc).unwrap();
+    assert!(map.keys().eq(&[2, 1]));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde_with_comparator() {
+    use flat_map::FlatMapBy;
+
+    let desc = |a: &u64, b: &u64| b.cmp(a);
+    let mut de = serde_json::Deserializer::from_str(r#"{"1":1,"3":3,"2":2,"1":4}"#);
+    let map: FlatMapBy<u64, u64, _> =
+        FlatMapBy::deserialize_with_comparator(&mut de, desc).unwrap();
+    assert!(map.iter().eq(vec![(&3, &3), (&2, &2), (&1, &4)]));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde_boxed_storage() {
+    use flat_map::BoxedFlatMap;
+
+    let map: BoxedFlatMap<u8, u8> = serde_json::from_str(r#"{"2":1,"1":2,"2":3}"#).unwrap();
+    assert!(map.iter().eq(vec![(&1, &2), (&2, &3)]));
+    assert_eq!(map.capacity(), 2);
+}
+
+#[test]
+fn test_boxed_storage() {
+    use flat_map::BoxedFlatMap;
+
+    let mut map: BoxedFlatMap<i32, char> = vec![(3, 'c'), (1, 'a')].into_iter().collect();
+    assert_eq!(map.capacity(), 2);
+    assert_eq!(map.insert(2, 'b'), None);
+    assert_eq!(map.capacity(), 3);
+    *map.entry(4).or_insert('x') = 'd';
+    assert_eq!(map.remove(&1), Some('a'));
+    assert_eq!(map.pop_first(), Some((2, 'b')));
+    map.extend((5..20).map(|i| (i, 'z')));
+    map.retain(|&k, _| k < 6);
+    let mut other: BoxedFlatMap<_, _> = FlatMap::from_iter(vec![(0, '0')]).into_storage();
+    map.append(&mut other);
+    assert!(other.is_empty());
+    let tail = map.split_off(&4);
+    assert!(map.iter().eq(vec![(&0, &'0'), (&3, &'c')]));
+    assert!(tail.into_iter().eq(vec![(4, 'd'), (5, 'z')]));
+    assert_eq!(map.into_inner().len(), 2);
+}
+
+#[cfg(feature = "smallvec")]
+#[test]
+fn test_small_storage() {
+    use flat_map::SmallFlatMap;
+
+    let mut map: SmallFlatMap<&str, i32, 4> = SmallFlatMap::new();
+    assert_eq!(map.capacity(), 4);
+    map.insert("b", 2);
+    map.insert("a", 1);
+    *map.entry("c").or_insert(0) += 3;
+    assert!(!map.clone().into_inner().spilled());
+    assert_eq!(map["c"], 3);
+    map.extend((0..10).map(|i| ("z", i)));
+    assert_eq!(map["z"], 9);
+    map.insert("d", 4);
+    assert!(map.into_inner().spilled());
+
+    let map: SmallFlatMap<_, _, 2> = (0..10).map(|i| (i, i)).collect();
+    assert!(map.range(3..5).eq(vec![(&3, &3), (&4, &4)]));
+    assert_eq!(format!("{:?}", map.into_iter().next_back()), "Some((9, 9))");
+}
+
+#[cfg(all(feature = "serde", feature = "smallvec"))]
+#[test]
+fn test_serde_small_storage() {
+    use flat_map::SmallFlatMap;
+
+    let map: SmallFlatMap<u8, u8, 2> = serde_json::from_str(r#"{"2":4,"1":2}"#).unwrap();
+    assert_eq!(serde_json::to_string(&map).unwrap(), r#"{"1":2,"2":4}"#);
+}
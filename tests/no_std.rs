@@ -0,0 +1,48 @@
+// Compiled as a `#![no_std]` crate so that it can only name items from `core`
+// and `alloc`. Run with `--no-default-features` (optionally adding
+// `--features alloc`) to check the library itself builds without std.
+#![no_std]
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+extern crate flat_map;
+
+use flat_map::ArrayFlatMap;
+
+#[test]
+fn test_array_flat_map() {
+    let mut m: ArrayFlatMap<u8, char, 3> = ArrayFlatMap::new();
+    assert_eq!(m.insert(2, 'b'), Ok(None));
+    assert_eq!(m.insert(1, 'a'), Ok(None));
+    assert_eq!(m.insert(3, 'c'), Ok(None));
+    assert_eq!(m.insert(4, 'd'), Err((4, 'd')));
+    assert_eq!(m.get(&1), Some(&'a'));
+    assert!(m.keys().cloned().eq([1, 2, 3].iter().cloned()));
+    assert_eq!(m.remove(&2), Some('b'));
+    assert_eq!(m.len(), 2);
+}
+
+#[cfg(feature = "alloc")]
+#[test]
+fn test_alloc_collections() {
+    use alloc::vec::Vec;
+    use flat_map::{FlatMap, FlatMultiMap, FlatSet};
+
+    let mut m = FlatMap::new();
+    m.insert(3, 'c');
+    m.insert(1, 'a');
+    m.insert(2, 'b');
+    assert_eq!(m.get(&2), Some(&'b'));
+    assert_eq!(
+        m.into_iter().collect::<Vec<_>>(),
+        [(1, 'a'), (2, 'b'), (3, 'c')]
+    );
+
+    let s: FlatSet<_> = [3, 1, 3, 2].iter().cloned().collect();
+    assert!(s.iter().cloned().eq(1..4));
+
+    let mut mm = FlatMultiMap::new();
+    mm.insert(1, 'a');
+    mm.insert(1, 'b');
+    assert!(mm.get_all(&1).eq(&['a', 'b']));
+}
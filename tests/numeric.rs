@@ -0,0 +1,151 @@
+#![cfg(feature = "std")]
+
+extern crate flat_map;
+extern crate rand;
+
+use flat_map::{Comparator, FlatMap, FlatMapBy, NumericKey, Reversed};
+use rand::{Rng, SeedableRng, XorShiftRng};
+use std::fmt::Debug;
+
+// Checks both numeric strategies against a plain binary search, for every
+// key in the map, its neighbours and the extra `probes`.
+fn check_strategies<K, C>(map: FlatMapBy<K, (), C>, probes: &[K])
+where
+    K: NumericKey + Copy + Debug,
+    C: Comparator<K> + Clone,
+{
+    let keys: Vec<K> = map.keys().cloned().collect();
+    let cmp = map.comparator().clone();
+    let expected = |q: &K| keys.binary_search_by(|k| cmp.compare(k, q));
+    let learned: Vec<_> = [0, 1, 4, 32]
+        .iter()
+        .map(|&eps| map.clone().freeze_learned(eps))
+        .collect();
+    for q in keys.iter().chain(probes) {
+        assert_eq!(map.interpolation_search(q), expected(q), "{:?}", q);
+        assert_eq!(map.get_interpolated(q), map.get(q));
+        for l in &learned {
+            assert_eq!(l.search(q), expected(q), "eps {} {:?}", l.epsilon(), q);
+        }
+    }
+}
+
+fn rng(seed: u32) -> XorShiftRng {
+    XorShiftRng::from_seed([seed, 0x9e37_79b9, 0x7f4a_7c15, 0xf39c_c060])
+}
+
+fn collect<K: Ord>(keys: Vec<K>) -> FlatMap<K, ()> {
+    keys.into_iter().map(|k| (k, ())).collect()
+}
+
+#[test]
+fn test_uniform() {
+    for seed in 1..20 {
+        let mut rng = rng(seed);
+        let len = rng.gen_range(0, 2000);
+        let keys: Vec<u64> = (0..len).map(|_| rng.gen()).collect();
+        let probes: Vec<u64> = (0..200).map(|_| rng.gen()).collect();
+        check_strategies(collect(keys), &probes);
+
+        let keys: Vec<i32> = (0..len).map(|_| rng.gen_range(-5000, 5000)).collect();
+        let probes: Vec<i32> = (-5001..5001).step_by(7).collect();
+        check_strategies(collect(keys), &probes);
+    }
+}
+
+#[test]
+fn test_piecewise_and_skewed() {
+    for seed in 1..20 {
+        let mut rng = rng(seed);
+        // A few runs with very different densities.
+        let mut keys = Vec::new();
+        let mut next = rng.gen_range(0u64, 1000);
+        for _ in 0..rng.gen_range(1, 8) {
+            let step = [1, 3, 1000, 1 << 40][rng.gen_range(0, 4)];
+            for _ in 0..rng.gen_range(1, 500) {
+                next = next.saturating_add(rng.gen_range(1, step + 1));
+                keys.push(next);
+            }
+        }
+        let probes: Vec<u64> = keys.iter().flat_map(|&k| vec![k - 1, k + 1]).collect();
+        check_strategies(collect(keys), &probes);
+
+        // Heavily clustered towards zero.
+        let keys: Vec<u64> = (0..1000)
+            .map(|_| (rng.gen::<f64>().powi(8) * 1e15) as u64)
+            .collect();
+        let probes: Vec<u64> = (0..200).map(|_| rng.gen_range(0, 1_000_000)).collect();
+        check_strategies(collect(keys), &probes);
+    }
+}
+
+// Near the ends of the 64-bit range neighbouring keys share an `f64`, so
+// the key mapping is flat and the strategies have to lean on comparisons.
+#[test]
+fn test_lossy_keys() {
+    let mut rng = rng(7);
+    let keys: Vec<u64> = (0..1000)
+        .map(|_| u64::MAX - rng.gen_range(0, 5000))
+        .collect();
+    let probes = [0, u64::MAX, u64::MAX - 5001];
+    check_strategies(collect(keys), &probes);
+
+    let keys: Vec<i128> = (0..1000)
+        .map(|i| {
+            if i % 2 == 0 {
+                i128::MIN + i
+            } else {
+                i128::MAX - i
+            }
+        })
+        .collect();
+    check_strategies(collect(keys), &[0, i128::MIN, i128::MAX]);
+}
+
+#[test]
+fn test_floats() {
+    let mut rng = rng(3);
+    let mut keys: Vec<f64> = (0..1000).map(|_| rng.gen::<f64>() * 1e6 - 5e5).collect();
+    keys.extend_from_slice(&[
+        0.0,
+        -0.0,
+        f64::MIN_POSITIVE,
+        f64::MAX,
+        f64::MIN,
+        f64::INFINITY,
+        f64::NEG_INFINITY,
+        f64::NAN,
+        -f64::NAN,
+    ]);
+    let mut map = FlatMapBy::with_comparator(f64::total_cmp as fn(&f64, &f64) -> _);
+    map.extend(keys.iter().map(|&k| (k, ())));
+    let probes: Vec<f64> = (0..200).map(|_| rng.gen::<f64>() * 2e6 - 1e6).collect();
+    check_strategies(map, &probes);
+}
+
+// A key mapping running against the comparator only costs probes.
+#[test]
+fn test_reversed() {
+    let mut rng = rng(11);
+    let map: FlatMapBy<i64, (), Reversed> = (0..1000).map(|_| (rng.gen::<i64>(), ())).collect();
+    let probes: Vec<i64> = (0..200).map(|_| rng.gen()).collect();
+    check_strategies(map, &probes);
+}
+
+#[test]
+fn test_learned_flat_map() {
+    let map: FlatMap<u32, u32> = (0..10_000).map(|i| (i * 4, i)).collect();
+    let learned = map.clone().freeze_learned(2);
+    assert_eq!(learned.segments(), 1);
+    assert_eq!(learned.len(), 10_000);
+    assert_eq!(learned[&400], 100);
+    assert_eq!(learned.get(&401), None);
+    assert!(learned.contains_key(&39_996));
+    assert!(learned.range(8..=16).map(|(_, &v)| v).eq(2..5));
+    assert!(learned.iter().eq(map.iter()));
+    assert_eq!(learned.thaw(), map);
+
+    let empty = FlatMap::<u8, ()>::new().freeze_learned(4);
+    assert_eq!(empty.segments(), 0);
+    assert_eq!(empty.search(&1), Err(0));
+}
@@ -0,0 +1,126 @@
+#![cfg(feature = "std")]
+
+// Maps ordered by `Primitive` take a fast path through the searches behind
+// `get`, `insert`, `remove` and `entry`: SIMD scans with the `simd` feature,
+// the branchless binary search every map uses otherwise. These check both
+// against `BTreeMap` for every key type, for maps on either side of the scan
+// cutoff.
+
+extern crate flat_map;
+extern crate rand;
+
+use flat_map::{
+    Comparator, FlatMap, FlatMapBy, FlatSet, Primitive, PrimitiveKey, Reversed, SoaFlatMap,
+};
+use rand::{Rng, SeedableRng, XorShiftRng};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Debug;
+
+macro_rules! check_type {
+    ($t:ty) => {{
+        let mut rng = XorShiftRng::from_seed([1, 2, 3, 4]);
+        let edges = [<$t>::MIN, <$t>::MIN + 1, 0, 1, <$t>::MAX - 1, <$t>::MAX];
+        for &len in &[
+            0, 1, 2, 3, 7, 8, 9, 15, 16, 17, 31, 33, 63, 64, 65, 100, 300,
+        ] {
+            // Keys from a narrow band, so that probes often hit.
+            let keys: Vec<$t> = (0..len)
+                .map(|_| (rng.gen_range(0u8, 200) as $t).wrapping_mul(3))
+                .chain(edges.iter().cloned().take(len % 7))
+                .collect();
+            let probes: Vec<$t> = (0..=255u8)
+                .map(|i| i as $t)
+                .chain(edges.iter().cloned())
+                .collect();
+            check(&keys, &probes);
+        }
+    }};
+}
+
+fn check<T: PrimitiveKey + Debug>(keys: &[T], probes: &[T]) {
+    let expected: BTreeMap<T, usize> = keys.iter().enumerate().map(|(i, &k)| (k, i)).collect();
+    let map: FlatMap<T, usize> = keys.iter().enumerate().map(|(i, &k)| (k, i)).collect();
+    let set: FlatSet<T> = keys.iter().cloned().collect();
+    let soa: SoaFlatMap<T, usize> = map.clone().into();
+    let reversed: FlatMapBy<T, usize, Reversed> = map.iter().map(|(&k, &v)| (k, v)).collect();
+    let primitive: FlatMapBy<T, usize, Primitive> = map.iter().map(|(&k, &v)| (k, v)).collect();
+    let primitive_set: FlatMapBy<T, (), Primitive> = keys.iter().map(|&k| (k, ())).collect();
+    for q in probes {
+        assert_eq!(map.get(q), expected.get(q), "{:?} in {:?}", q, keys);
+        assert_eq!(set.contains(q), expected.contains_key(q));
+        assert_eq!(soa.get(q), expected.get(q));
+        assert_eq!(reversed.get(q), expected.get(q));
+        assert_eq!(primitive.get(q), expected.get(q), "{:?} in {:?}", q, keys);
+        assert_eq!(primitive_set.contains_key(q), expected.contains_key(q));
+    }
+
+    check_updates(map, expected.clone(), probes);
+    check_updates(primitive, expected, probes);
+
+    let mut set = set;
+    let mut expected_set: BTreeSet<T> = keys.iter().cloned().collect();
+    for q in probes {
+        assert_eq!(set.insert(*q), expected_set.insert(*q));
+    }
+    assert!(set.iter().eq(expected_set.iter()));
+}
+
+fn check_updates<T, C>(
+    mut map: FlatMapBy<T, usize, C>,
+    mut expected: BTreeMap<T, usize>,
+    probes: &[T],
+) where
+    T: PrimitiveKey + Debug,
+    C: Comparator<T>,
+{
+    for (i, q) in probes.iter().enumerate() {
+        match i % 4 {
+            0 => assert_eq!(map.insert(*q, i), expected.insert(*q, i)),
+            1 => assert_eq!(map.remove(q), expected.remove(q)),
+            2 => {
+                *map.entry(*q).or_insert(0) += 1;
+                *expected.entry(*q).or_insert(0) += 1;
+            }
+            _ => {
+                if let Some(v) = map.get_mut(q) {
+                    *v += 10;
+                }
+                if let Some(v) = expected.get_mut(q) {
+                    *v += 10;
+                }
+            }
+        }
+    }
+    assert!(map.iter().eq(expected.iter()));
+}
+
+#[test]
+fn test_unsigned() {
+    check_type!(u8);
+    check_type!(u16);
+    check_type!(u32);
+    check_type!(u64);
+    check_type!(u128);
+    check_type!(usize);
+}
+
+#[test]
+fn test_signed() {
+    check_type!(i8);
+    check_type!(i16);
+    check_type!(i32);
+    check_type!(i64);
+    check_type!(i128);
+    check_type!(isize);
+}
+
+// Borrowed and wrapped keys are searched through `Borrow`, and must agree
+// with the plain keys.
+#[test]
+fn test_other_keys() {
+    let map: FlatMap<&u32, u32> = [3, 1, 2].iter().map(|k| (k, *k)).collect();
+    assert_eq!(map.get(&&2), Some(&2));
+    let map: FlatMap<Box<u32>, u32> = (0..100).map(|k| (Box::new(k * 2), k)).collect();
+    assert_eq!(map.get(&40), Some(&20));
+    assert_eq!(map.get(&41), None);
+}
@@ -0,0 +1,147 @@
+#![cfg(feature = "std")]
+
+#[cfg(feature = "serde")]
+extern crate serde_json;
+
+extern crate flat_map;
+
+use flat_map::soa_flat_map::Entry;
+use flat_map::{FlatMap, SoaFlatMap};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+#[test]
+fn it_works() {
+    let mut m = SoaFlatMap::new();
+    assert!(m.is_empty());
+    assert_eq!(m.insert(3, "c"), None);
+    assert_eq!(m.insert(1, "a"), None);
+    assert_eq!(m.insert(2, "b"), None);
+    assert_eq!(m.insert(3, "C"), Some("c"));
+    assert_eq!(m.keys(), &[1, 2, 3]);
+    assert_eq!(m.values(), &["a", "b", "C"]);
+    assert_eq!(m.get(&2), Some(&"b"));
+    assert_eq!(m.get_key_value(&4), None);
+    assert!(m.contains_key(&1));
+    *m.get_mut(&1).unwrap() = "A";
+    assert_eq!(m[&1], "A");
+    assert_eq!(m.first_key_value(), Some((&1, &"A")));
+    assert_eq!(m.last_key_value(), Some((&3, &"C")));
+
+    assert!(m.range(2..).eq(vec![(&2, &"b"), (&3, &"C")]));
+    for (_, v) in m.range_mut(..=2) {
+        *v = "x";
+    }
+    assert_eq!(m.values(), &["x", "x", "C"]);
+
+    assert_eq!(m.remove(&2), Some("x"));
+    assert_eq!(m.remove_entry(&2), None);
+    assert_eq!(m.pop_first(), Some((1, "x")));
+    assert_eq!(m.pop_last(), Some((3, "C")));
+    assert!(m.is_empty());
+}
+
+#[test]
+fn test_entry() {
+    let mut m: SoaFlatMap<_, _> = vec![(1, 10), (3, 30)].into_iter().collect();
+    *m.entry(1).or_insert(0) += 1;
+    *m.entry(2).or_insert_with(|| 20) += 1;
+    match m.entry(3) {
+        Entry::Occupied(mut e) => {
+            assert_eq!(e.key(), &3);
+            assert_eq!(e.insert(33), 30);
+            assert_eq!(e.remove(), 33);
+        }
+        Entry::Vacant(_) => unreachable!(),
+    }
+    assert_eq!(m.keys(), &[1, 2]);
+    assert_eq!(m.values(), &[11, 21]);
+    *m.last_entry().unwrap().get_mut() = 0;
+    assert_eq!(m.first_entry().unwrap().into_mut(), &mut 11);
+    assert_eq!(m[&2], 0);
+}
+
+#[test]
+fn test_conversions_and_bulk() {
+    let mut a: SoaFlatMap<_, _> = vec![(1, 'a'), (3, 'c'), (1, 'A')].into_iter().collect();
+    assert_eq!(a.keys(), &[1, 3]);
+    assert_eq!(a.values(), &['A', 'c']);
+    let mut b: SoaFlatMap<_, _> = vec![(2, 'b'), (3, 'C')].into_iter().collect();
+    a.append(&mut b);
+    assert!(b.is_empty());
+    assert!(a.iter().eq(vec![(&1, &'A'), (&2, &'b'), (&3, &'C')]));
+
+    let c = a.split_off(&2);
+    assert_eq!(a.keys(), &[1]);
+    assert_eq!(c.keys(), &[2, 3]);
+
+    let flat: FlatMap<_, _> = c.clone().into();
+    assert_eq!(SoaFlatMap::from(flat), c);
+    let (keys, values) = c.into_parts();
+    assert!(SoaFlatMap::from_sorted_parts(keys, values).is_ok());
+    assert_eq!(
+        SoaFlatMap::from_sorted_parts(vec![1, 1], vec![(), ()])
+            .unwrap_err()
+            .index(),
+        1
+    );
+
+    let err = SoaFlatMap::try_from_iter(vec![(2, 'a'), (1, 'b'), (2, 'c')]).unwrap_err();
+    assert_eq!(err.keys(), &[2]);
+    let counts = SoaFlatMap::from_iter_merge(vec![("a", 1), ("b", 1), ("a", 1)], |_, x, y| x + y);
+    assert_eq!(counts.values(), &[2, 1]);
+    assert_eq!(format!("{:?}", counts), r#"{"a": 2, "b": 1}"#);
+}
+
+#[test]
+fn test_extend() {
+    let mut soa: SoaFlatMap<_, _> = (0..30).map(|i| (i * 2, i)).collect();
+    let mut flat: FlatMap<_, _> = soa.clone().into();
+    let pairs: Vec<_> = (0..100).map(|i| (i * 7 % 61, 100 + i)).collect();
+    soa.extend(pairs.iter().map(|(k, v)| (k, v)));
+    flat.extend(pairs);
+    assert_eq!(soa, SoaFlatMap::from(flat));
+    assert_eq!(soa[&7], 162);
+    soa.extend(vec![(-1, 0), (7, 0)]);
+    assert_eq!(soa.keys()[0], -1);
+    assert_eq!(soa[&7], 0);
+}
+
+#[test]
+fn test_retain() {
+    let mut m: SoaFlatMap<_, _> = (0..10).map(|i| (i, i * 10)).collect();
+    m.retain(|k, _| k % 3 != 0);
+    assert_eq!(m.keys(), &[1, 2, 4, 5, 7, 8]);
+    assert_eq!(m.values(), &[10, 20, 40, 50, 70, 80]);
+
+    // A panicking predicate still leaves the map sorted, with the visited
+    // entries filtered and the rest untouched.
+    let mut m: SoaFlatMap<_, _> = (0..6).map(|i| (i, i)).collect();
+    let r = catch_unwind(AssertUnwindSafe(|| {
+        m.retain(|&k, _| {
+            if k == 3 {
+                panic!("oops");
+            }
+            k != 1
+        })
+    }));
+    assert!(r.is_err());
+    assert_eq!(m.keys(), &[0, 2, 3, 4, 5]);
+    assert_eq!(m.values(), &[0, 2, 3, 4, 5]);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde() {
+    let m: SoaFlatMap<String, u32> = vec![("b".to_owned(), 2), ("a".to_owned(), 1)]
+        .into_iter()
+        .collect();
+    let json = serde_json::to_string(&m).unwrap();
+    assert_eq!(json, r#"{"a":1,"b":2}"#);
+    let back: SoaFlatMap<String, u32> = serde_json::from_str(&json).unwrap();
+    assert_eq!(back, m);
+
+    let unsorted: SoaFlatMap<String, u32> =
+        serde_json::from_str(r#"{"c":3,"a":0,"b":2,"a":1}"#).unwrap();
+    assert_eq!(unsorted.keys(), &["a", "b", "c"]);
+    assert_eq!(unsorted.values(), &[1, 2, 3]);
+}